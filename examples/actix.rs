@@ -1,31 +1,29 @@
 use actix_files as fs;
-use actix_web::{get, http::StatusCode, App, HttpResponse, HttpServer};
-use std::cell::RefCell;
+use actix_web::{get, http::StatusCode, web, App, HttpResponse, HttpServer};
 use std::env;
 use std::fs::read_to_string;
+use std::num::NonZeroUsize;
 use std::path::Path;
 
-use ssr_rs::Ssr;
+use ssr_rs::{ModuleType, Ssr, SsrPool};
 use std::time::Instant;
 
-thread_local! {
-    static SSR: RefCell<Ssr> = RefCell::new({
-        let ssr = Ssr::new();
-        ssr.load(
-            &read_to_string(Path::new("./tests/assets/react-17-iife.js").to_str().unwrap()).unwrap(),
-            "",
-            "cjs"
-        ).unwrap();
-        ssr
-    });
-}
-
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     println!("{:?}", env::current_dir()?);
 
-    HttpServer::new(|| {
+    let source = read_to_string(Path::new("./tests/assets/react-17-iife.js"))?;
+    let pool = SsrPool::new(NonZeroUsize::new(4).unwrap(), move || {
+        Ssr::builder()
+            .source(&source, "")
+            .module_type(ModuleType::Iife)
+            .build()
+    })
+    .unwrap();
+
+    HttpServer::new(move || {
         App::new()
+            .app_data(web::Data::new(pool.clone()))
             .service(fs::Files::new("/styles", "client/dist/ssr/styles/").show_files_listing())
             .service(fs::Files::new("/images", "client/dist/ssr/images/").show_files_listing())
             .service(fs::Files::new("/scripts", "client/dist/client/").show_files_listing())
@@ -37,9 +35,9 @@ async fn main() -> std::io::Result<()> {
 }
 
 #[get("/")]
-async fn index() -> HttpResponse {
+async fn index(pool: web::Data<SsrPool>) -> HttpResponse {
     let start = Instant::now();
-    let result = SSR.with(|ssr| ssr.borrow_mut().render_to_string(None).unwrap());
+    let result = pool.render(None).await.unwrap();
     println!("Elapsed: {:?}", start.elapsed());
 
     HttpResponse::build(StatusCode::OK)
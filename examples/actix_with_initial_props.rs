@@ -4,7 +4,7 @@ use std::cell::RefCell;
 use std::fs::read_to_string;
 use std::path::Path;
 
-use ssr_rs::Ssr;
+use ssr_rs::{ModuleType, Ssr};
 
 thread_local! {
     static SSR: RefCell<Ssr> = RefCell::new({
@@ -12,7 +12,7 @@ thread_local! {
         ssr.load(
             &read_to_string(Path::new("./tests/assets/react-17-iife.js").to_str().unwrap()).unwrap(),
             "",
-            "cjs"
+            ModuleType::Iife
         ).unwrap();
         ssr
     });
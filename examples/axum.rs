@@ -1,35 +1,32 @@
-use axum::{response::Html, routing::get, Router};
-use ssr_rs::Ssr;
-use std::cell::RefCell;
+use axum::{extract::State, response::Html, routing::get, Router};
+use ssr_rs::{ModuleType, Ssr, SsrPool};
 use std::fs::read_to_string;
+use std::num::NonZeroUsize;
 use std::path::Path;
 use std::time::Instant;
 
-thread_local! {
-    static SSR: RefCell<Ssr> = RefCell::new({
-        let ssr = Ssr::new();
-        ssr.load(
-            &read_to_string(Path::new("./tests/assets/react-17-iife.js").to_str().unwrap()).unwrap(),
-            "",
-            "cjs"
-        ).unwrap();
-        ssr
-    });
-}
-
 #[tokio::main]
 async fn main() {
+    let source = read_to_string(Path::new("./tests/assets/react-17-iife.js")).unwrap();
+    let pool = SsrPool::new(NonZeroUsize::new(4).unwrap(), move || {
+        Ssr::builder()
+            .source(&source, "")
+            .module_type(ModuleType::Iife)
+            .build()
+    })
+    .unwrap();
+
     // build our application with a single route
-    let app = Router::new().route("/", get(root));
+    let app = Router::new().route("/", get(root)).with_state(pool);
 
     // run our app with hyper, listening globally on port 8080
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await.unwrap();
     axum::serve(listener, app).await.unwrap();
 }
 
-async fn root() -> Html<String> {
+async fn root(State(pool): State<SsrPool>) -> Html<String> {
     let start = Instant::now();
-    let result = SSR.with(|ssr| ssr.borrow_mut().render_to_string(None));
+    let result = pool.render(None).await;
     println!("Elapsed: {:?}", start.elapsed());
     Html(result.unwrap())
 }
@@ -1,41 +1,33 @@
-use ssr_rs::Ssr;
-use std::cell::RefCell;
+use ssr_rs::{ModuleType, Ssr, SsrPool};
 use std::fs::read_to_string;
+use std::num::NonZeroUsize;
 use std::path::Path;
-use std::thread;
 use std::time::Instant;
 
-thread_local! {
-    static SSR: RefCell<Ssr> = RefCell::new({
-        let ssr = Ssr::new();
-        ssr.load(
-            &read_to_string(Path::new("./tests/assets/react-17-iife.js").to_str().unwrap()).unwrap(),
-            "",
-            "cjs"
-        ).unwrap();
-        ssr
-    });
-}
+#[tokio::main]
+async fn main() {
+    let source = read_to_string(Path::new("./tests/assets/react-17-iife.js")).unwrap();
+    let pool = SsrPool::new(NonZeroUsize::new(2).unwrap(), move || {
+        Ssr::builder()
+            .source(&source, "")
+            .module_type(ModuleType::Iife)
+            .build()
+    })
+    .unwrap();
 
-fn main() {
-    let threads: Vec<_> = (0..2)
+    let tasks: Vec<_> = (0..2)
         .map(|i| {
-            thread::spawn(move || {
-                println!("Thread #{i} started!");
+            let pool = pool.clone();
+            tokio::spawn(async move {
+                println!("Task #{i} started!");
                 let start = Instant::now();
-                println!(
-                    "result: {}",
-                    SSR.with(|ssr| ssr.borrow_mut().render_to_string(None).unwrap())
-                );
-                println!(
-                    "Thread #{i} finished! - Elapsed time: {:?}",
-                    start.elapsed()
-                );
+                println!("result: {}", pool.render(None).await.unwrap());
+                println!("Task #{i} finished! - Elapsed time: {:?}", start.elapsed());
             })
         })
         .collect();
 
-    for handle in threads {
-        handle.join().unwrap();
+    for task in tasks {
+        task.await.unwrap();
     }
 }
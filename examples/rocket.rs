@@ -2,7 +2,7 @@
 extern crate rocket;
 use rocket::fs::FileServer;
 use rocket::response::content;
-use ssr_rs::Ssr;
+use ssr_rs::{ModuleType, Ssr};
 use std::cell::RefCell;
 use std::fs::read_to_string;
 use std::path::Path;
@@ -14,7 +14,7 @@ thread_local! {
         ssr.load(
             &read_to_string(Path::new("./tests/assets/react-17-iife.js").to_str().unwrap()).unwrap(),
             "",
-            "cjs"
+            ModuleType::Iife
         ).unwrap();
         ssr
     });
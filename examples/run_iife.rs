@@ -1,4 +1,4 @@
-use ssr_rs::Ssr;
+use ssr_rs::{ModuleType, Ssr};
 use std::fs::read_to_string;
 use std::time::Instant;
 
@@ -7,7 +7,7 @@ fn main() {
 
     let start = Instant::now();
     let ssr = Ssr::new();
-    ssr.load(&source, "", "cjs").unwrap();
+    ssr.load(&source, "", ModuleType::Iife).unwrap();
     let duration = start.elapsed();
     println!("Ssr creation and loading took: {:?}", duration);
 
@@ -1,4 +1,4 @@
-use ssr_rs::Ssr;
+use ssr_rs::{ModuleType, Ssr};
 use std::cell::RefCell;
 use std::fs::read_to_string;
 use std::path::Path;
@@ -11,7 +11,7 @@ thread_local! {
         ssr.load(
             &read_to_string(Path::new("./tests/assets/react-17-iife.js").to_str().unwrap()).unwrap(),
             "",
-            "cjs"
+            ModuleType::Iife
         ).unwrap();
         ssr
     });
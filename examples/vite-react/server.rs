@@ -3,7 +3,7 @@ use std::cell::RefCell;
 use std::fs::read_to_string;
 use std::path::Path;
 
-use ssr_rs::Ssr;
+use ssr_rs::{ModuleType, Ssr};
 
 thread_local! {
     static SSR: RefCell<Ssr> = RefCell::new({
@@ -11,7 +11,7 @@ thread_local! {
         ssr.load(
             &read_to_string(Path::new("./dist/server-entry.js").to_str().unwrap()).unwrap(),
             "SSR",
-            "cjs"
+            ModuleType::Cjs
         ).unwrap();
         ssr
     });
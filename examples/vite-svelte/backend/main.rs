@@ -1,58 +1,54 @@
 use salvo::prelude::*;
-use ssr_rs::Ssr;
-use std::cell::RefCell;
+use ssr_rs::{ModuleType, Ssr, SsrPool};
 use std::fs::read_to_string;
+use std::num::NonZeroUsize;
 use std::path::Path;
+use std::sync::OnceLock;
 
-thread_local! {
-    static SSR: RefCell<Ssr> = RefCell::new({
-        let ssr = Ssr::new();
-        ssr.load(
-            &read_to_string(Path::new("./dist/server/server.js").to_str().unwrap()).unwrap(),
-            "render",
-            "esm",
-        ).unwrap_or_else(|err| {
-            eprintln!("Failed to initialize SSR: {}", err);
-            std::process::exit(1);
-        });
-        ssr
-    });
-}
+static POOL: OnceLock<SsrPool> = OnceLock::new();
 
 #[handler]
 async fn index(res: &mut Response) {
-    let result = SSR.with(|ssr| {
-        let ssr = ssr.borrow_mut();
-        ssr.render_to_string(None).unwrap_or_else(|err| {
-            eprintln!("Error rendering to string: {}", err);
-            String::new()
-        })
-    });
+    let pool = POOL.get().expect("The SSR pool is not initialized");
+    let output = pool
+        .run(|ssr| ssr.render_output("render", None))
+        .await
+        .and_then(|output| output);
 
-    if result.is_empty() {
-        eprintln!("Rendered result is empty");
-        res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
-        res.render(Text::Plain("Internal Server Error"));
-        return;
-    }
-
-    let result: serde_json::Value = match serde_json::from_str(&result) {
-        Ok(val) => val,
+    let output = match output {
+        Ok(output) => output,
         Err(err) => {
-            eprintln!("Failed to parse JSON: {}", err);
+            eprintln!("Error rendering: {}", err);
             res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
             res.render(Text::Plain("Internal Server Error"));
             return;
         }
     };
 
-    let html = result["html"].as_str().unwrap_or("");
-    let css = result["css"].as_str().unwrap_or("");
+    if let Some(location) = &output.redirect {
+        res.render(Redirect::found(location));
+        return;
+    }
+
+    res.status_code(StatusCode::from_u16(output.status).unwrap_or(StatusCode::OK));
+    for (name, value) in &output.headers {
+        let result = match salvo::http::HeaderName::from_bytes(name.as_bytes()) {
+            Ok(header) => res
+                .add_header(header, value, false)
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        if let Err(err) = result {
+            eprintln!("Invalid header {}: {}", name, err);
+        }
+    }
 
     let full_html = format!(
         r#"<!DOCTYPE html>
         <html>
         <head>
+            {}
             <style>{}</style>
             <link rel="stylesheet" href="/client/assets/main.css">
         </head>
@@ -61,13 +57,26 @@ async fn index(res: &mut Response) {
             <script type="module" src="/client/main.js"></script>
         </body>
         </html>"#,
-        css, html
+        output.head, output.css, output.html
     );
     res.render(Text::Html(full_html));
 }
 
 #[tokio::main]
 async fn main() {
+    let source = read_to_string(Path::new("./dist/server/server.js")).unwrap();
+    let pool = SsrPool::new(NonZeroUsize::new(4).unwrap(), move || {
+        Ssr::builder()
+            .source(&source, "render")
+            .module_type(ModuleType::Esm)
+            .build()
+    })
+    .unwrap_or_else(|err| {
+        eprintln!("Failed to initialize SSR: {}", err);
+        std::process::exit(1);
+    });
+    POOL.set(pool).ok();
+
     let router = Router::new()
         .push(Router::with_path("/client/<**path>").get(StaticDir::new(["./dist/client"])))
         .push(
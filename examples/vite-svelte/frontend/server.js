@@ -1,6 +1,6 @@
 import App from './App.svelte';
 
 export function render() {
-  const { html, css } = App.render();
-  return JSON.stringify({ html, css: css.code });
+  const { html, css, head } = App.render();
+  return { html, css, head };
 }
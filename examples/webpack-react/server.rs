@@ -1,5 +1,5 @@
 use actix_web::{get, http::StatusCode, App, HttpResponse, HttpServer};
-use ssr_rs::Ssr;
+use ssr_rs::{ModuleType, Ssr};
 use std::cell::RefCell;
 use std::fs::read_to_string;
 use std::path::Path;
@@ -10,7 +10,7 @@ thread_local! {
         ssr.load(
             &read_to_string(Path::new("./dist/ssr/index.js").to_str().unwrap()).unwrap(),
             "SSR",
-            "cjs"
+            ModuleType::Cjs
         ).unwrap();
         ssr
     });
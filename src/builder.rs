@@ -0,0 +1,354 @@
+use crate::console::{ConsoleMessage, ConsoleSink};
+use crate::error::SsrError;
+use crate::event_loop::{AsyncFunction, BoxFuture};
+use crate::loader::ModuleLoader;
+use crate::module_type::ModuleType;
+use crate::render_cache::{self, CacheKeyFn, CacheLimits, RenderCache};
+use crate::snapshot::Snapshot;
+use crate::ssr::Ssr;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+const DEFAULT_SCRIPT_CACHE_SIZE: usize = 100;
+
+/// Configures an [`Ssr`] instance before creating it.
+///
+/// ```no_run
+/// use ssr_rs::{ModuleType, Ssr};
+/// use std::fs::read_to_string;
+/// use std::time::Duration;
+///
+/// let ssr = Ssr::builder()
+///     .heap_limits(0, 256 * 1024 * 1024)
+///     .timeout(Duration::from_secs(2))
+///     .render_cache(false)
+///     .global("APP_ENV", &"production")
+///     .source(&read_to_string("./path/to/build.js").unwrap(), "SSR")
+///     .module_type(ModuleType::Cjs)
+///     .build()
+///     .unwrap();
+/// ```
+pub struct SsrBuilder {
+    pub(crate) script_cache_size: NonZeroUsize,
+    pub(crate) code_cache_dir: Option<PathBuf>,
+    pub(crate) render_cache: bool,
+    pub(crate) render_cache_limits: CacheLimits,
+    pub(crate) render_cache_backend: Option<Arc<dyn RenderCache>>,
+    pub(crate) cache_key: Option<CacheKeyFn>,
+    pub(crate) heap_limits: Option<(usize, usize)>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) virtual_time: bool,
+    pub(crate) globals: Vec<(String, serde_json::Value)>,
+    pub(crate) async_functions: Vec<(String, AsyncFunction)>,
+    pub(crate) console: Option<ConsoleSink>,
+    pub(crate) module_loader: Option<Rc<dyn ModuleLoader>>,
+    pub(crate) node_builtins: bool,
+    pub(crate) snapshot: Option<Snapshot>,
+    module_type: ModuleType,
+    source: Option<(String, String)>,
+    source_map: Option<String>,
+    error: Option<SsrError>,
+}
+
+impl Default for SsrBuilder {
+    fn default() -> Self {
+        SsrBuilder {
+            script_cache_size: NonZeroUsize::new(DEFAULT_SCRIPT_CACHE_SIZE).unwrap(),
+            code_cache_dir: None,
+            render_cache: true,
+            render_cache_limits: CacheLimits::default(),
+            render_cache_backend: None,
+            cache_key: None,
+            heap_limits: None,
+            timeout: None,
+            virtual_time: false,
+            globals: Vec::new(),
+            async_functions: Vec::new(),
+            console: None,
+            module_loader: None,
+            node_builtins: false,
+            snapshot: None,
+            module_type: ModuleType::Auto,
+            source: None,
+            source_map: None,
+            error: None,
+        }
+    }
+}
+
+impl SsrBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how many compiled scripts are kept in memory. Defaults to 100.
+    pub fn script_cache_size(mut self, size: NonZeroUsize) -> Self {
+        self.script_cache_size = size;
+        self
+    }
+
+    /// Stores the V8 code cache of the bundle, and of the modules it imports, in `dir` (created
+    /// if needed), so that new instances and restarted processes skip compiling them again.
+    /// Disabled by default.
+    ///
+    /// The files are named after the hash of the source compiled. Those V8 rejects, e.g. when
+    /// they were created with other V8 flags, are replaced, and the V8 version is part of the
+    /// hash, so that upgrading it leaves the old files unused.
+    pub fn code_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.code_cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Enables or disables caching rendered results by props. Enabled by default.
+    pub fn render_cache(mut self, enabled: bool) -> Self {
+        self.render_cache = enabled;
+        self
+    }
+
+    /// Sets how many rendered results are kept in memory, the least recently used ones being
+    /// dropped first. Defaults to 100.
+    pub fn render_cache_size(mut self, size: NonZeroUsize) -> Self {
+        self.render_cache_limits.max_entries = size;
+        self
+    }
+
+    /// Limits the total size of the rendered results kept in memory. Unlimited by default.
+    ///
+    /// A result larger than `max_bytes` on its own is not cached.
+    pub fn render_cache_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.render_cache_limits.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Renders again the results cached for longer than `ttl`. They never expire by default.
+    pub fn render_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.render_cache_limits.ttl = Some(ttl);
+        self
+    }
+
+    /// Caches the rendered results in `cache` instead of a
+    /// [`MemoryRenderCache`](crate::MemoryRenderCache) of the instance, e.g. one shared by the
+    /// instances of a [`SsrPool`](crate::SsrPool) or an external store adapted with
+    /// [`StoreRenderCache`](crate::StoreRenderCache).
+    ///
+    /// The instances sharing a cache must load the same bundles, as the results are cached by
+    /// props and render function only. The size, byte and TTL limits set on the builder do not
+    /// apply to `cache`.
+    pub fn render_cache_backend(mut self, cache: Arc<dyn RenderCache>) -> Self {
+        self.render_cache_backend = Some(cache);
+        self
+    }
+
+    /// Caches the rendered results by the key `f` derives from the props instead of the exact
+    /// props string, so that equivalent props share their result. Returning `None` renders the
+    /// props without caching the result.
+    ///
+    /// `f` is given the props string parsed as JSON (a JSON string if it is not valid JSON,
+    /// `null` without props), or the props given to [`Ssr::render_with`].
+    ///
+    /// ```no_run
+    /// use ssr_rs::Ssr;
+    ///
+    /// let ssr = Ssr::builder()
+    ///     // Logged in users get a page of their own.
+    ///     .cache_key(|props| match props["user"].is_null() {
+    ///         true => Some(props["path"].to_string()),
+    ///         false => None,
+    ///     })
+    ///     .source("var SSR = {x: (props) => props};", "SSR")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn cache_key<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&serde_json::Value) -> Option<String> + 'static,
+    {
+        self.cache_key = Some(Rc::new(f));
+        self
+    }
+
+    /// Caches the rendered results by the props serialized with their keys sorted and without
+    /// the properties named after one of `fields` (e.g. timestamps or CSRF tokens), at any depth.
+    /// See [`SsrBuilder::cache_key`].
+    pub fn cache_key_ignoring(self, fields: &[&str]) -> Self {
+        let fields: Vec<_> = fields.iter().map(|field| field.to_string()).collect();
+        self.cache_key(move |props| Some(render_cache::canonical_key(props, &fields)))
+    }
+
+    /// Sets the initial and maximum heap size of the isolate, see [`Ssr::with_heap_limits`].
+    pub fn heap_limits(mut self, initial_bytes: usize, max_bytes: usize) -> Self {
+        self.heap_limits = Some((initial_bytes, max_bytes));
+        self
+    }
+
+    /// Sets the default execution timeout, see [`Ssr::set_timeout`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Makes the timers (`setTimeout`, `setInterval`) fire as soon as nothing else can run,
+    /// in order, as if their delay had elapsed. Disabled by default.
+    ///
+    /// Renders waiting for timers then complete without waiting and always produce the same
+    /// output. The timeout still applies in real time, which is what stops a render waiting
+    /// forever on a `setInterval`.
+    pub fn virtual_time(mut self, enabled: bool) -> Self {
+        self.virtual_time = enabled;
+        self
+    }
+
+    /// Defines `name` on the global object with `value` converted to its JS equivalent.
+    pub fn global<T: Serialize + ?Sized>(mut self, name: &str, value: &T) -> Self {
+        match serde_json::to_value(value) {
+            Ok(value) => self.globals.push((name.to_string(), value)),
+            Err(err) => self.error = Some(SsrError::Serialization(err.to_string())),
+        }
+        self
+    }
+
+    /// Defines `name` on the global object as an async function backed by `f`.
+    ///
+    /// Its first argument is deserialized into `A`, and it returns a promise resolved with the
+    /// output of the future returned by `f`, or rejected with an `Error` carrying the message of
+    /// the `Err`. The futures only make progress while [`Ssr::render`] waits for them: a blocking
+    /// render waiting for one fails with [`SsrError::UnsettledPromise`].
+    pub fn async_function<A, R, F, Fut>(mut self, name: &str, f: F) -> Self
+    where
+        A: DeserializeOwned,
+        R: Serialize,
+        F: Fn(A) -> Fut + 'static,
+        Fut: Future<Output = Result<R, String>> + 'static,
+    {
+        let function: AsyncFunction =
+            Rc::new(move |arg| -> BoxFuture<Result<serde_json::Value, String>> {
+                let future = serde_json::from_value(arg).map(&f);
+                Box::pin(async move {
+                    let output = future.map_err(|err| err.to_string())?.await?;
+                    serde_json::to_value(output).map_err(|err| err.to_string())
+                })
+            });
+        self.async_functions.push((name.to_string(), function));
+        self
+    }
+
+    /// Sends the messages logged with `console` to `sink` instead of `tracing`.
+    ///
+    /// By default each message is emitted as a `tracing` event with the `ssr_rs::console`
+    /// target, at the level matching the `console` method (`console.log` being `info`), so that
+    /// it belongs to the span the render runs in. See [`Ssr::capture_console`] to collect the
+    /// messages of a single render instead.
+    pub fn console<F>(mut self, sink: F) -> Self
+    where
+        F: Fn(&ConsoleMessage) + 'static,
+    {
+        self.console = Some(Rc::new(sink));
+        self
+    }
+
+    /// Sets the loader resolving the `import`s of the bundle, static or dynamic, see
+    /// [`ModuleLoader`]. Without one, an ES module bundle importing other modules fails to load
+    /// with [`SsrError::ModuleLoad`], and `import()` rejects.
+    pub fn module_loader<L: ModuleLoader + 'static>(mut self, loader: L) -> Self {
+        self.module_loader = Some(Rc::new(loader));
+        self
+    }
+
+    /// Lets the bundle `require` or `import` lightweight shims of the Node built-in modules
+    /// `buffer`, `events`, `path`, `stream` and `util`, with or without the `node:` prefix.
+    /// Disabled by default.
+    ///
+    /// The shims cover what SSR bundles commonly use, e.g. piping React's
+    /// `renderToPipeableStream` into a `Writable`, not the whole Node API.
+    pub fn node_builtins(mut self, enabled: bool) -> Self {
+        self.node_builtins = enabled;
+        self
+    }
+
+    /// Sets the module type of the bundle given to [`SsrBuilder::source`]. Defaults to
+    /// [`ModuleType::Auto`].
+    pub fn module_type(mut self, module_type: ModuleType) -> Self {
+        self.module_type = module_type;
+        self
+    }
+
+    /// Sets the bundle loaded by [`SsrBuilder::build`], see [`Ssr::load`].
+    pub fn source(mut self, source: &str, entry_point: &str) -> Self {
+        self.source = Some((source.to_string(), entry_point.to_string()));
+        self
+    }
+
+    /// Starts the instance from `snapshot`, with the bundle it was created with already loaded,
+    /// instead of an empty context. See [`Snapshot`] for what the builder must set again.
+    ///
+    /// A bundle given to [`SsrBuilder::source`] is loaded on top of it.
+    pub fn snapshot(mut self, snapshot: Snapshot) -> Self {
+        self.snapshot = Some(snapshot);
+        self
+    }
+
+    /// Sets the source map of the bundle, see [`Ssr::load_with_source_map`]. Without a bundle,
+    /// it is the source map of the bundle of the snapshot given to [`SsrBuilder::snapshot`].
+    pub fn source_map(mut self, source_map: &str) -> Self {
+        self.source_map = Some(source_map.to_string());
+        self
+    }
+
+    /// Creates the instance, loading the bundle if one was given.
+    pub fn build(mut self) -> Result<Ssr, SsrError> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+
+        let source = self.source.take();
+        let source_map = self.source_map.take();
+        let module_type = self.module_type;
+
+        let ssr = Ssr::from_builder(self)?;
+        match (source, source_map) {
+            (Some((source, entry_point)), source_map) => {
+                ssr.load_with_source_map(&source, &entry_point, module_type, source_map.as_deref())?
+            }
+            (None, Some(source_map)) => ssr.set_source_map(&source_map)?,
+            (None, None) => {}
+        }
+
+        Ok(ssr)
+    }
+
+    /// Creates a [`Snapshot`] of the instance once the bundle given to [`SsrBuilder::source`]
+    /// is loaded, to start instances with it already loaded with [`SsrBuilder::snapshot`].
+    ///
+    /// Fails with [`SsrError::InvalidSnapshot`] if the builder was itself given a snapshot.
+    pub fn create_snapshot(mut self) -> Result<Snapshot, SsrError> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        if self.snapshot.is_some() {
+            return Err(SsrError::InvalidSnapshot(
+                "A snapshot cannot be created from another one".to_string(),
+            ));
+        }
+
+        let source = self.source.take();
+        let source_map = self.source_map.take();
+        let module_type = self.module_type;
+
+        let ssr = Ssr::snapshot_creator(self)?;
+        let loaded = match source {
+            Some((source, entry_point)) => {
+                ssr.load_with_source_map(&source, &entry_point, module_type, source_map.as_deref())
+            }
+            None => Ok(()),
+        };
+        // The isolate has to be serialized even when the bundle failed to load.
+        let snapshot = ssr.into_snapshot();
+        loaded.and(snapshot)
+    }
+}
@@ -0,0 +1,46 @@
+/// The prefix of the names given to the built-in modules, which `require` and `import` also
+/// accept.
+const PREFIX: &str = "node:";
+
+/// The sources of the Node built-in module shims, written as CommonJS modules.
+const BUILTINS: [(&str, &str); 5] = [
+    ("buffer", include_str!("builtins/buffer.js")),
+    ("events", include_str!("builtins/events.js")),
+    ("path", include_str!("builtins/path.js")),
+    ("stream", include_str!("builtins/stream.js")),
+    ("util", include_str!("builtins/util.js")),
+];
+
+/// Returns the name of the built-in module `specifier` refers to, e.g. `node:path` for `path`
+/// and `node:path`.
+pub(crate) fn resolve(specifier: &str) -> Option<String> {
+    let name = specifier.strip_prefix(PREFIX).unwrap_or(specifier);
+    BUILTINS
+        .iter()
+        .any(|(builtin, _)| *builtin == name)
+        .then(|| format!("{PREFIX}{name}"))
+}
+
+/// Returns the source of the built-in module named `name`, as returned by [`resolve`].
+pub(crate) fn source(name: &str) -> Option<&'static str> {
+    let name = name.strip_prefix(PREFIX)?;
+    BUILTINS
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, source)| *source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(resolve("path").as_deref(), Some("node:path"));
+        assert_eq!(resolve("node:stream").as_deref(), Some("node:stream"));
+        assert_eq!(resolve("fs"), None);
+        assert_eq!(resolve("./path"), None);
+        assert!(source("node:util").is_some());
+        assert_eq!(source("util"), None);
+    }
+}
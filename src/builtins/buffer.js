@@ -0,0 +1,287 @@
+// The `buffer` module: `Buffer` as a `Uint8Array` subclass, with the usual encodings.
+"use strict";
+
+const BASE64 = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const ENCODINGS = ["utf8", "utf-8", "hex", "base64", "base64url", "latin1", "binary", "ascii"];
+
+function normalizeEncoding(encoding = "utf8") {
+  const normalized = String(encoding).toLowerCase();
+  if (!ENCODINGS.includes(normalized)) {
+    throw new TypeError(`Unknown encoding: ${encoding}`);
+  }
+  if (normalized === "utf-8") return "utf8";
+  if (normalized === "binary") return "latin1";
+  return normalized;
+}
+
+function encodeUtf8(string) {
+  const bytes = [];
+  for (const char of string) {
+    let code = char.codePointAt(0);
+    // Lone surrogates become U+FFFD.
+    if (code >= 0xd800 && code <= 0xdfff) code = 0xfffd;
+    if (code < 0x80) {
+      bytes.push(code);
+    } else if (code < 0x800) {
+      bytes.push(0xc0 | (code >> 6), 0x80 | (code & 0x3f));
+    } else if (code < 0x10000) {
+      bytes.push(0xe0 | (code >> 12), 0x80 | ((code >> 6) & 0x3f), 0x80 | (code & 0x3f));
+    } else {
+      bytes.push(
+        0xf0 | (code >> 18),
+        0x80 | ((code >> 12) & 0x3f),
+        0x80 | ((code >> 6) & 0x3f),
+        0x80 | (code & 0x3f),
+      );
+    }
+  }
+  return bytes;
+}
+
+function decodeUtf8(bytes) {
+  let string = "";
+  let index = 0;
+  while (index < bytes.length) {
+    const byte = bytes[index];
+    const length = byte < 0x80 ? 1 : byte >= 0xf0 ? 4 : byte >= 0xe0 ? 3 : byte >= 0xc0 ? 2 : 0;
+    let code = length === 1 ? byte : byte & (0xff >> (length + 1));
+    let valid = length > 0 && index + length <= bytes.length;
+    for (let offset = 1; valid && offset < length; offset++) {
+      const next = bytes[index + offset];
+      valid = (next & 0xc0) === 0x80;
+      code = (code << 6) | (next & 0x3f);
+    }
+    if (valid) {
+      string += String.fromCodePoint(code);
+      index += length;
+    } else {
+      string += "\ufffd";
+      index += 1;
+    }
+  }
+  return string;
+}
+
+function encodeBase64(bytes, url) {
+  let string = "";
+  for (let index = 0; index < bytes.length; index += 3) {
+    const chunk = (bytes[index] << 16) | ((bytes[index + 1] || 0) << 8) | (bytes[index + 2] || 0);
+    const chars = [18, 12, 6, 0].map((shift) => BASE64[(chunk >> shift) & 0x3f]);
+    const kept = Math.min(bytes.length - index, 3) + 1;
+    string += chars.slice(0, kept).join("") + (url ? "" : "=".repeat(4 - kept));
+  }
+  return url ? string.replace(/\+/g, "-").replace(/\//g, "_") : string;
+}
+
+function decodeBase64(string) {
+  const clean = string.replace(/-/g, "+").replace(/_/g, "/").replace(/[^A-Za-z0-9+/]/g, "");
+  const bytes = [];
+  let bits = 0;
+  let value = 0;
+  for (const char of clean) {
+    value = (value << 6) | BASE64.indexOf(char);
+    bits += 6;
+    if (bits >= 8) {
+      bits -= 8;
+      bytes.push((value >> bits) & 0xff);
+    }
+  }
+  return bytes;
+}
+
+function encode(string, encoding) {
+  switch (normalizeEncoding(encoding)) {
+    case "hex": {
+      const bytes = [];
+      for (let index = 0; index + 1 < string.length; index += 2) {
+        const byte = parseInt(string.slice(index, index + 2), 16);
+        if (Number.isNaN(byte)) break;
+        bytes.push(byte);
+      }
+      return bytes;
+    }
+    case "base64":
+    case "base64url":
+      return decodeBase64(string);
+    case "latin1":
+      return Array.from(string, (char) => char.charCodeAt(0) & 0xff);
+    case "ascii":
+      return Array.from(string, (char) => char.charCodeAt(0) & 0x7f);
+    default:
+      return encodeUtf8(string);
+  }
+}
+
+function decode(bytes, encoding) {
+  switch (normalizeEncoding(encoding)) {
+    case "hex":
+      return Array.from(bytes, (byte) => byte.toString(16).padStart(2, "0")).join("");
+    case "base64":
+      return encodeBase64(bytes, false);
+    case "base64url":
+      return encodeBase64(bytes, true);
+    case "latin1":
+      return Array.from(bytes, (byte) => String.fromCharCode(byte)).join("");
+    case "ascii":
+      return Array.from(bytes, (byte) => String.fromCharCode(byte & 0x7f)).join("");
+    default:
+      return decodeUtf8(bytes);
+  }
+}
+
+class Buffer extends Uint8Array {
+  static from(value, encodingOrOffset, length) {
+    if (typeof value === "string") {
+      return new Buffer(encode(value, encodingOrOffset));
+    }
+    if (value instanceof ArrayBuffer) {
+      const offset = encodingOrOffset || 0;
+      return new Buffer(value, offset, length === undefined ? value.byteLength - offset : length);
+    }
+    if (ArrayBuffer.isView(value)) {
+      return new Buffer(new Uint8Array(value.buffer, value.byteOffset, value.byteLength));
+    }
+    if (value && value.type === "Buffer" && Array.isArray(value.data)) {
+      return new Buffer(value.data);
+    }
+    if (value && typeof value.length === "number") {
+      return new Buffer(Array.from(value));
+    }
+    throw new TypeError("The first argument must be a string, Buffer, ArrayBuffer, Array or array-like object");
+  }
+
+  static alloc(size, fill = 0, encoding) {
+    const buffer = new Buffer(size);
+    if (fill !== 0) buffer.fill(fill, 0, size, encoding);
+    return buffer;
+  }
+
+  static allocUnsafe(size) {
+    return new Buffer(size);
+  }
+
+  static byteLength(value, encoding) {
+    if (typeof value !== "string") return value.byteLength;
+    return encode(value, encoding).length;
+  }
+
+  static concat(list, totalLength) {
+    const length = totalLength === undefined
+      ? list.reduce((sum, buffer) => sum + buffer.length, 0)
+      : totalLength;
+    const result = Buffer.alloc(length);
+    let offset = 0;
+    for (const buffer of list) {
+      if (offset >= length) break;
+      const chunk = buffer.subarray(0, length - offset);
+      result.set(chunk, offset);
+      offset += chunk.length;
+    }
+    return result;
+  }
+
+  static isBuffer(value) {
+    return value instanceof Buffer;
+  }
+
+  static isEncoding(encoding) {
+    return typeof encoding === "string" && ENCODINGS.includes(encoding.toLowerCase());
+  }
+
+  static compare(a, b) {
+    return a.compare(b);
+  }
+
+  toString(encoding, start = 0, end = this.length) {
+    return decode(this.subarray(start, end), encoding);
+  }
+
+  toJSON() {
+    return { type: "Buffer", data: Array.from(this) };
+  }
+
+  fill(value, offset = 0, end = this.length, encoding) {
+    if (typeof value === "string") {
+      const bytes = encode(value, typeof offset === "string" ? offset : encoding);
+      if (typeof offset === "string") offset = 0;
+      for (let index = offset; index < end && bytes.length > 0; index++) {
+        this[index] = bytes[(index - offset) % bytes.length];
+      }
+      return this;
+    }
+    return super.fill(value, offset, end);
+  }
+
+  write(string, offset = 0, length = this.length - offset, encoding = "utf8") {
+    if (typeof offset === "string") {
+      encoding = offset;
+      offset = 0;
+      length = this.length;
+    }
+    const bytes = encode(string, encoding).slice(0, Math.min(length, this.length - offset));
+    this.set(bytes, offset);
+    return bytes.length;
+  }
+
+  slice(start, end) {
+    return this.subarray(start, end);
+  }
+
+  equals(other) {
+    return this.compare(other) === 0;
+  }
+
+  compare(other) {
+    const length = Math.min(this.length, other.length);
+    for (let index = 0; index < length; index++) {
+      if (this[index] !== other[index]) return this[index] < other[index] ? -1 : 1;
+    }
+    return Math.sign(this.length - other.length);
+  }
+
+  copy(target, targetStart = 0, sourceStart = 0, sourceEnd = this.length) {
+    const chunk = this.subarray(sourceStart, sourceEnd).subarray(0, target.length - targetStart);
+    target.set(chunk, targetStart);
+    return chunk.length;
+  }
+}
+
+function invalidCharacter(message) {
+  const error = new Error(message);
+  error.name = "InvalidCharacterError";
+  return error;
+}
+
+function atob(data) {
+  let string = String(data).replace(/[\t\n\f\r ]/g, "");
+  if (string.length % 4 === 0) {
+    string = string.replace(/={1,2}$/, "");
+  }
+  if (string.length % 4 === 1 || /[^A-Za-z0-9+/]/.test(string)) {
+    throw invalidCharacter("The string to be decoded is not correctly encoded");
+  }
+  return decode(decodeBase64(string), "latin1");
+}
+
+function btoa(data) {
+  const string = String(data);
+  const bytes = [];
+  for (let index = 0; index < string.length; index++) {
+    const code = string.charCodeAt(index);
+    if (code > 0xff) {
+      throw invalidCharacter("The string to be encoded contains characters outside of the Latin1 range");
+    }
+    bytes.push(code);
+  }
+  return encodeBase64(bytes, false);
+}
+
+const kMaxLength = 2 ** 32 - 1;
+
+module.exports = {
+  Buffer,
+  kMaxLength,
+  constants: { MAX_LENGTH: kMaxLength, MAX_STRING_LENGTH: 2 ** 29 - 24 },
+  atob,
+  btoa,
+};
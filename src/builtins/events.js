@@ -0,0 +1,146 @@
+// The `events` module: `EventEmitter` and its helpers.
+"use strict";
+
+class EventEmitter {
+  constructor() {
+    this._events = new Map();
+    this._maxListeners = EventEmitter.defaultMaxListeners;
+  }
+
+  _listeners(event) {
+    if (!this._events) this._events = new Map();
+    let listeners = this._events.get(event);
+    if (!listeners) {
+      listeners = [];
+      this._events.set(event, listeners);
+    }
+    return listeners;
+  }
+
+  _add(event, listener, { once = false, prepend = false } = {}) {
+    if (typeof listener !== "function") {
+      throw new TypeError('The "listener" argument must be of type function');
+    }
+    if (this._events && this._events.has("newListener")) {
+      this.emit("newListener", event, listener);
+    }
+    const entry = { listener, once };
+    const listeners = this._listeners(event);
+    if (prepend) {
+      listeners.unshift(entry);
+    } else {
+      listeners.push(entry);
+    }
+    return this;
+  }
+
+  on(event, listener) {
+    return this._add(event, listener);
+  }
+
+  addListener(event, listener) {
+    return this.on(event, listener);
+  }
+
+  prependListener(event, listener) {
+    return this._add(event, listener, { prepend: true });
+  }
+
+  once(event, listener) {
+    return this._add(event, listener, { once: true });
+  }
+
+  prependOnceListener(event, listener) {
+    return this._add(event, listener, { once: true, prepend: true });
+  }
+
+  off(event, listener) {
+    const listeners = this._events && this._events.get(event);
+    if (!listeners) return this;
+    const index = listeners.findIndex((entry) => entry.listener === listener);
+    if (index !== -1) {
+      listeners.splice(index, 1);
+      if (this._events.has("removeListener")) this.emit("removeListener", event, listener);
+    }
+    return this;
+  }
+
+  removeListener(event, listener) {
+    return this.off(event, listener);
+  }
+
+  removeAllListeners(event) {
+    if (!this._events) return this;
+    if (event === undefined) {
+      this._events.clear();
+    } else {
+      this._events.delete(event);
+    }
+    return this;
+  }
+
+  emit(event, ...args) {
+    const listeners = this._events && this._events.get(event);
+    if (!listeners || listeners.length === 0) {
+      if (event === "error") {
+        throw args[0] instanceof Error ? args[0] : new Error(`Unhandled error. (${args[0]})`);
+      }
+      return false;
+    }
+    for (const entry of [...listeners]) {
+      if (entry.once) this.off(event, entry.listener);
+      entry.listener.apply(this, args);
+    }
+    return true;
+  }
+
+  listeners(event) {
+    const listeners = this._events && this._events.get(event);
+    return listeners ? listeners.map((entry) => entry.listener) : [];
+  }
+
+  rawListeners(event) {
+    return this.listeners(event);
+  }
+
+  listenerCount(event) {
+    return this.listeners(event).length;
+  }
+
+  eventNames() {
+    return this._events
+      ? [...this._events.keys()].filter((event) => this._events.get(event).length > 0)
+      : [];
+  }
+
+  setMaxListeners(count) {
+    this._maxListeners = count;
+    return this;
+  }
+
+  getMaxListeners() {
+    return this._maxListeners;
+  }
+}
+
+EventEmitter.defaultMaxListeners = 10;
+EventEmitter.EventEmitter = EventEmitter;
+
+EventEmitter.once = function once(emitter, event) {
+  return new Promise((resolve, reject) => {
+    const onError = (err) => {
+      emitter.off(event, onEvent);
+      reject(err);
+    };
+    const onEvent = (...args) => {
+      if (event !== "error") emitter.off("error", onError);
+      resolve(args);
+    };
+    emitter.once(event, onEvent);
+    if (event !== "error") emitter.once("error", onError);
+  });
+};
+
+EventEmitter.listenerCount = (emitter, event) => emitter.listenerCount(event);
+
+module.exports = EventEmitter;
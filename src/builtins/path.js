@@ -0,0 +1,135 @@
+// A POSIX `path` module. The working directory is `/`.
+"use strict";
+
+function assertPath(path) {
+  if (typeof path !== "string") {
+    throw new TypeError(`The "path" argument must be of type string, received ${typeof path}`);
+  }
+}
+
+function normalizeSegments(path, allowAboveRoot) {
+  const segments = [];
+  for (const segment of path.split("/")) {
+    if (segment === "" || segment === ".") continue;
+    if (segment === "..") {
+      if (segments.length > 0 && segments[segments.length - 1] !== "..") {
+        segments.pop();
+      } else if (allowAboveRoot) {
+        segments.push("..");
+      }
+    } else {
+      segments.push(segment);
+    }
+  }
+  return segments.join("/");
+}
+
+function isAbsolute(path) {
+  assertPath(path);
+  return path.startsWith("/");
+}
+
+function normalize(path) {
+  assertPath(path);
+  if (path === "") return ".";
+  const absolute = isAbsolute(path);
+  const trailing = path.endsWith("/");
+  let normalized = normalizeSegments(path, !absolute);
+  if (normalized === "" && !absolute) normalized = ".";
+  if (normalized !== "" && trailing) normalized += "/";
+  return absolute ? `/${normalized}` : normalized;
+}
+
+function join(...paths) {
+  paths.forEach(assertPath);
+  const joined = paths.filter((path) => path !== "").join("/");
+  return joined === "" ? "." : normalize(joined);
+}
+
+function resolve(...paths) {
+  let resolved = "";
+  for (let index = paths.length - 1; index >= 0 && !resolved.startsWith("/"); index--) {
+    assertPath(paths[index]);
+    if (paths[index] !== "") resolved = `${paths[index]}/${resolved}`;
+  }
+  return `/${normalizeSegments(resolved, false)}`;
+}
+
+function relative(from, to) {
+  const fromSegments = resolve(from).split("/").filter(Boolean);
+  const toSegments = resolve(to).split("/").filter(Boolean);
+  let common = 0;
+  while (
+    common < fromSegments.length &&
+    common < toSegments.length &&
+    fromSegments[common] === toSegments[common]
+  ) {
+    common++;
+  }
+  return [
+    ...fromSegments.slice(common).map(() => ".."),
+    ...toSegments.slice(common),
+  ].join("/");
+}
+
+function dirname(path) {
+  assertPath(path);
+  const trimmed = path.length > 1 ? path.replace(/\/+$/, "") : path;
+  const index = trimmed.lastIndexOf("/");
+  if (index === -1) return ".";
+  if (index === 0) return "/";
+  return trimmed.slice(0, index);
+}
+
+function basename(path, ext) {
+  assertPath(path);
+  const trimmed = path.length > 1 ? path.replace(/\/+$/, "") : path;
+  let base = trimmed.slice(trimmed.lastIndexOf("/") + 1);
+  if (ext !== undefined && base !== ext && base.endsWith(ext)) {
+    base = base.slice(0, base.length - ext.length);
+  }
+  return base;
+}
+
+function extname(path) {
+  const base = basename(path);
+  const index = base.lastIndexOf(".");
+  return index <= 0 ? "" : base.slice(index);
+}
+
+function parse(path) {
+  assertPath(path);
+  const root = isAbsolute(path) ? "/" : "";
+  const base = basename(path);
+  const ext = extname(path);
+  // Only an explicit `./` prefix gives a `.` directory.
+  let dir = dirname(path);
+  if (dir === "." && !path.startsWith("./")) dir = "";
+  return { root, dir, base, ext, name: base.slice(0, base.length - ext.length) };
+}
+
+function format({ root = "", dir, base, name = "", ext = "" }) {
+  const file = base || `${name}${ext}`;
+  if (!dir) return `${root}${file}`;
+  return dir === root ? `${dir}${file}` : `${dir}/${file}`;
+}
+
+const path = {
+  sep: "/",
+  delimiter: ":",
+  isAbsolute,
+  normalize,
+  join,
+  resolve,
+  relative,
+  dirname,
+  basename,
+  extname,
+  parse,
+  format,
+  toNamespacedPath: (path) => path,
+};
+path.posix = path;
+path.win32 = path;
+
+module.exports = path;
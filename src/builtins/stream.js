@@ -0,0 +1,356 @@
+// A small `stream` module: enough for bundles piping their output (e.g. React's
+// `renderToPipeableStream`) into a `Writable`.
+"use strict";
+
+const EventEmitter = require("events");
+
+class Stream extends EventEmitter {
+  pipe(destination, { end = true } = {}) {
+    this.on("data", (chunk) => destination.write(chunk));
+    if (end) this.on("end", () => destination.end());
+    destination.emit("pipe", this);
+    return destination;
+  }
+}
+
+class Readable extends Stream {
+  constructor(options = {}) {
+    super();
+    this._queue = [];
+    this._flowing = null;
+    this._ended = false;
+    this._scheduled = false;
+    this.readable = true;
+    this.destroyed = false;
+    this.readableEnded = false;
+    if (options.read) this._read = options.read;
+    if (options.destroy) this._destroy = options.destroy;
+  }
+
+  static from(iterable) {
+    const readable = new Readable();
+    (async () => {
+      try {
+        for await (const chunk of iterable) readable.push(chunk);
+        readable.push(null);
+      } catch (err) {
+        readable.destroy(err);
+      }
+    })();
+    return readable;
+  }
+
+  _read() {}
+
+  push(chunk) {
+    if (chunk === null) {
+      this._ended = true;
+    } else {
+      this._queue.push(chunk);
+    }
+    this._schedule();
+    return !this._ended;
+  }
+
+  unshift(chunk) {
+    this._queue.unshift(chunk);
+    this._schedule();
+  }
+
+  read() {
+    const chunk = this._queue.length > 0 ? this._queue.shift() : null;
+    this._schedule();
+    return chunk;
+  }
+
+  on(event, listener) {
+    super.on(event, listener);
+    if (event === "data" && this._flowing !== false) this.resume();
+    if (event === "readable") this._schedule();
+    return this;
+  }
+
+  resume() {
+    this._flowing = true;
+    this._schedule();
+    return this;
+  }
+
+  pause() {
+    this._flowing = false;
+    return this;
+  }
+
+  isPaused() {
+    return this._flowing === false;
+  }
+
+  setEncoding() {
+    return this;
+  }
+
+  destroy(err) {
+    if (this.destroyed) return this;
+    this.destroyed = true;
+    this.readable = false;
+    const done = (err) => {
+      queueMicrotask(() => {
+        if (err) this.emit("error", err);
+        this.emit("close");
+      });
+    };
+    if (this._destroy) {
+      this._destroy(err || null, done);
+    } else {
+      done(err);
+    }
+    return this;
+  }
+
+  _schedule() {
+    if (this._scheduled || this.destroyed) return;
+    this._scheduled = true;
+    queueMicrotask(() => {
+      this._scheduled = false;
+      this._flow();
+    });
+  }
+
+  _flow() {
+    if (this._flowing) {
+      while (this._queue.length > 0 && this._flowing && !this.destroyed) {
+        this.emit("data", this._queue.shift());
+      }
+    } else if (this._queue.length > 0) {
+      this.emit("readable");
+    }
+
+    if (this._ended && this._queue.length === 0 && !this.readableEnded) {
+      this.readableEnded = true;
+      this.readable = false;
+      this.emit("end");
+      this.emit("close");
+    } else if (!this._ended && this._queue.length === 0 && this._flowing) {
+      this._read();
+    }
+  }
+
+  async *[Symbol.asyncIterator]() {
+    const chunks = [];
+    let done = false;
+    let failure = null;
+    let wake = null;
+    const notify = () => {
+      if (wake) wake();
+    };
+    this.on("data", (chunk) => {
+      chunks.push(chunk);
+      notify();
+    });
+    this.on("end", () => {
+      done = true;
+      notify();
+    });
+    this.on("error", (err) => {
+      failure = err;
+      notify();
+    });
+
+    while (true) {
+      if (failure) throw failure;
+      if (chunks.length > 0) {
+        yield chunks.shift();
+      } else if (done) {
+        return;
+      } else {
+        await new Promise((resolve) => (wake = resolve));
+        wake = null;
+      }
+    }
+  }
+}
+
+class Writable extends Stream {
+  constructor(options = {}) {
+    super();
+    this.writable = true;
+    this.destroyed = false;
+    this.writableEnded = false;
+    this.writableFinished = false;
+    if (options.write) this._write = options.write;
+    if (options.final) this._final = options.final;
+    if (options.destroy) this._destroy = options.destroy;
+  }
+}
+
+// Shared by `Writable` and `Duplex`, which cannot extend both `Readable` and `Writable`.
+const writableMethods = {
+  _write(chunk, encoding, callback) {
+    callback();
+  },
+
+  write(chunk, encoding, callback) {
+    if (typeof encoding === "function") {
+      callback = encoding;
+      encoding = "utf8";
+    }
+    if (this.writableEnded) {
+      const err = new Error("write after end");
+      queueMicrotask(() => this.emit("error", err));
+      if (callback) callback(err);
+      return false;
+    }
+    this._write(chunk, encoding || "utf8", (err) => {
+      if (err) this.emit("error", err);
+      if (callback) callback(err);
+    });
+    return true;
+  },
+
+  end(chunk, encoding, callback) {
+    if (typeof chunk === "function") {
+      callback = chunk;
+      chunk = null;
+    } else if (typeof encoding === "function") {
+      callback = encoding;
+      encoding = undefined;
+    }
+    if (chunk !== null && chunk !== undefined) this.write(chunk, encoding);
+    if (this.writableEnded) return this;
+    this.writableEnded = true;
+    this.writable = false;
+
+    const finish = (err) => {
+      if (err) {
+        this.emit("error", err);
+        return;
+      }
+      this.writableFinished = true;
+      this.emit("finish");
+      if (callback) callback();
+      if (!this.readable) this.emit("close");
+    };
+    if (this._final) {
+      this._final(finish);
+    } else {
+      queueMicrotask(() => finish());
+    }
+    return this;
+  },
+
+  cork() {},
+
+  uncork() {},
+
+  setDefaultEncoding() {
+    return this;
+  },
+};
+
+Object.assign(Writable.prototype, writableMethods);
+
+Writable.prototype.destroy = function destroy(err) {
+  if (this.destroyed) return this;
+  this.destroyed = true;
+  this.writable = false;
+  queueMicrotask(() => {
+    if (err) this.emit("error", err);
+    this.emit("close");
+  });
+  return this;
+};
+
+class Duplex extends Readable {
+  constructor(options = {}) {
+    super(options);
+    this.writable = true;
+    this.writableEnded = false;
+    this.writableFinished = false;
+    if (options.write) this._write = options.write;
+    if (options.final) this._final = options.final;
+  }
+}
+
+Object.assign(Duplex.prototype, writableMethods);
+
+class Transform extends Duplex {
+  constructor(options = {}) {
+    super(options);
+    if (options.transform) this._transform = options.transform;
+    if (options.flush) this._flush = options.flush;
+  }
+
+  _transform(chunk, encoding, callback) {
+    callback(null, chunk);
+  }
+
+  _write(chunk, encoding, callback) {
+    this._transform(chunk, encoding, (err, data) => {
+      if (data !== null && data !== undefined) this.push(data);
+      callback(err);
+    });
+  }
+
+  _final(callback) {
+    const done = (err, data) => {
+      if (data !== null && data !== undefined) this.push(data);
+      this.push(null);
+      callback(err);
+    };
+    if (this._flush) {
+      this._flush(done);
+    } else {
+      done();
+    }
+  }
+}
+
+class PassThrough extends Transform {}
+
+function finished(stream, callback) {
+  let called = false;
+  const done = (err) => {
+    if (called) return;
+    called = true;
+    callback(err);
+  };
+  stream.on("error", done);
+  stream.on(stream instanceof Writable || stream._write ? "finish" : "end", () => done());
+  return () => {
+    called = true;
+  };
+}
+
+function pipeline(...streams) {
+  const callback = typeof streams[streams.length - 1] === "function" ? streams.pop() : () => {};
+  if (Array.isArray(streams[0])) streams = streams[0];
+
+  let failed = false;
+  const fail = (err) => {
+    if (failed) return;
+    failed = true;
+    streams.forEach((stream) => stream.destroy && stream.destroy());
+    callback(err);
+  };
+  streams.forEach((stream) => stream.on("error", fail));
+  streams.reduce((source, destination) => source.pipe(destination));
+  finished(streams[streams.length - 1], (err) => (err ? fail(err) : !failed && callback()));
+  return streams[streams.length - 1];
+}
+
+Stream.Stream = Stream;
+Stream.Readable = Readable;
+Stream.Writable = Writable;
+Stream.Duplex = Duplex;
+Stream.Transform = Transform;
+Stream.PassThrough = PassThrough;
+Stream.finished = finished;
+Stream.pipeline = pipeline;
+Stream.promises = {
+  finished: (stream) =>
+    new Promise((resolve, reject) => finished(stream, (err) => (err ? reject(err) : resolve()))),
+  pipeline: (...streams) =>
+    new Promise((resolve, reject) => pipeline(...streams, (err) => (err ? reject(err) : resolve()))),
+};
+
+module.exports = Stream;
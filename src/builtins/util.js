@@ -0,0 +1,132 @@
+// The parts of the `util` module used by SSR bundles.
+"use strict";
+
+function inspect(value) {
+  if (typeof value === "string") return JSON.stringify(value);
+  if (typeof value === "function") {
+    return value.name ? `[Function: ${value.name}]` : "[Function (anonymous)]";
+  }
+  if (value instanceof Error) return value.stack || String(value);
+  if (typeof value === "object" && value !== null) {
+    try {
+      return JSON.stringify(value);
+    } catch {
+      return Object.prototype.toString.call(value);
+    }
+  }
+  return String(value);
+}
+
+function format(template, ...args) {
+  if (typeof template !== "string") {
+    return [template, ...args]
+      .map((arg) => (typeof arg === "string" ? arg : inspect(arg)))
+      .join(" ");
+  }
+
+  let index = 0;
+  const formatted = template.replace(/%([sdifjoOc%])/g, (match, specifier) => {
+    if (specifier === "%") return "%";
+    if (index >= args.length) return match;
+    const arg = args[index++];
+    switch (specifier) {
+      case "s":
+        return typeof arg === "string" ? arg : inspect(arg);
+      case "d":
+        return String(Number(arg));
+      case "i":
+        return String(parseInt(arg, 10));
+      case "f":
+        return String(parseFloat(arg));
+      case "c":
+        return "";
+      default:
+        return inspect(arg);
+    }
+  });
+
+  const rest = args
+    .slice(index)
+    .map((arg) => (typeof arg === "string" ? arg : inspect(arg)));
+  return [formatted, ...rest].join(" ");
+}
+
+function inherits(constructor, superConstructor) {
+  Object.defineProperty(constructor, "super_", { value: superConstructor, writable: true });
+  Object.setPrototypeOf(constructor.prototype, superConstructor.prototype);
+}
+
+const custom = Symbol.for("nodejs.util.promisify.custom");
+
+function promisify(original) {
+  if (typeof original !== "function") {
+    throw new TypeError('The "original" argument must be of type function');
+  }
+  if (original[custom]) return original[custom];
+
+  return function (...args) {
+    return new Promise((resolve, reject) => {
+      original.call(this, ...args, (err, value) => (err ? reject(err) : resolve(value)));
+    });
+  };
+}
+promisify.custom = custom;
+
+function callbackify(original) {
+  return function (...args) {
+    const callback = args.pop();
+    original.apply(this, args).then(
+      (value) => callback(null, value),
+      (err) => callback(err),
+    );
+  };
+}
+
+function deprecate(fn) {
+  return fn;
+}
+
+function debuglog() {
+  const log = () => {};
+  log.enabled = false;
+  return log;
+}
+
+function isDeepStrictEqual(a, b) {
+  if (Object.is(a, b)) return true;
+  if (typeof a !== "object" || typeof b !== "object" || a === null || b === null) return false;
+  if (Object.getPrototypeOf(a) !== Object.getPrototypeOf(b)) return false;
+  const keys = Object.keys(a);
+  return (
+    keys.length === Object.keys(b).length &&
+    keys.every((key) => Object.prototype.hasOwnProperty.call(b, key) && isDeepStrictEqual(a[key], b[key]))
+  );
+}
+
+const types = {
+  isPromise: (value) => value instanceof Promise,
+  isDate: (value) => value instanceof Date,
+  isRegExp: (value) => value instanceof RegExp,
+  isMap: (value) => value instanceof Map,
+  isSet: (value) => value instanceof Set,
+  isTypedArray: (value) => ArrayBuffer.isView(value) && !(value instanceof DataView),
+  isUint8Array: (value) => value instanceof Uint8Array,
+  isNativeError: (value) => value instanceof Error,
+};
+
+module.exports = {
+  format,
+  inspect,
+  inherits,
+  promisify,
+  callbackify,
+  deprecate,
+  debuglog,
+  debug: debuglog,
+  isDeepStrictEqual,
+  isArray: Array.isArray,
+  types,
+  // Defined when the web APIs are installed.
+  TextEncoder: globalThis.TextEncoder,
+  TextDecoder: globalThis.TextDecoder,
+};
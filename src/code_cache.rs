@@ -0,0 +1,145 @@
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use v8::script_compiler::{CompileOptions, Source};
+
+/// Persists the V8 code cache of the compiled scripts and modules in a directory, so that new
+/// instances and restarted processes skip compiling them again.
+///
+/// The cache of a source is stored in a file named after the hash of the source and of the V8
+/// version. V8 rejects a cache created with other flags, which is then replaced once the source
+/// is compiled. Stored in an isolate slot so that the compilers can reach it.
+pub(crate) struct CodeCache {
+    dir: PathBuf,
+}
+
+impl CodeCache {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        CodeCache { dir }
+    }
+
+    /// Returns the entry of `source` in the code cache of the isolate `scope` belongs to, if it
+    /// has one, reading the cached data stored for it.
+    pub(crate) fn entry(scope: &mut v8::HandleScope, source: &str) -> Option<Entry> {
+        let cache = scope.get_slot::<Rc<CodeCache>>()?;
+        let hash = hash(v8::V8::get_version(), source);
+        let path = cache.dir.join(format!("{hash:016x}.v8cache"));
+        let data = fs::read(&path).ok();
+        Some(Entry { path, data })
+    }
+}
+
+/// The code cache of a source being compiled.
+pub(crate) struct Entry {
+    path: PathBuf,
+    data: Option<Vec<u8>>,
+}
+
+impl Entry {
+    /// Stores the code cache returned by `create` once `source` is compiled, unless V8 accepted
+    /// the cached data it was compiled with.
+    pub(crate) fn update<F>(&self, source: &Source, create: F)
+    where
+        F: FnOnce() -> Option<v8::UniqueRef<v8::CachedData<'static>>>,
+    {
+        if source
+            .get_cached_data()
+            .is_some_and(|data| !data.rejected())
+        {
+            return;
+        }
+        if let Some(data) = create() {
+            self.write(&data);
+        }
+    }
+
+    fn write(&self, data: &[u8]) {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        // Written aside first, so that the other instances never read a partial file.
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let temp = self
+            .path
+            .with_extension(format!("{}-{id}.tmp", std::process::id()));
+        let written = self
+            .path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&temp, data))
+            .and_then(|()| fs::rename(&temp, &self.path));
+
+        if let Err(err) = written {
+            let _ = fs::remove_file(&temp);
+            tracing::warn!(
+                target: "ssr_rs::code_cache",
+                "Failed to write {}: {err}",
+                self.path.display()
+            );
+        }
+    }
+}
+
+/// Returns the source of `string` to compile, with the cached data of `entry` if any, along
+/// with the options consuming it.
+pub(crate) fn source(
+    entry: Option<&Entry>,
+    string: v8::Local<v8::String>,
+    origin: &v8::ScriptOrigin,
+) -> (Source, CompileOptions) {
+    match entry.and_then(|entry| entry.data.as_deref()) {
+        Some(data) => (
+            Source::new_with_cached_data(string, Some(origin), v8::CachedData::new(data)),
+            CompileOptions::ConsumeCodeCache,
+        ),
+        None => (
+            Source::new(string, Some(origin)),
+            CompileOptions::NoCompileOptions,
+        ),
+    }
+}
+
+/// Hashes `source` along with the V8 `version`, see [`fnv1a`].
+fn hash(version: &str, source: &str) -> u64 {
+    fnv1a(version.bytes().chain([0]).chain(source.bytes()))
+}
+
+/// Hashes `bytes` with FNV-1a, which unlike the hasher of the standard library gives the same
+/// hash in every process.
+pub(crate) fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    bytes.into_iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(hash("", ""), 0xaf63_bd4c_8601_b7df);
+        assert_eq!(hash("12.9", "var a;"), hash("12.9", "var a;"));
+        assert_ne!(hash("12.9", "var a;"), hash("13.0", "var a;"));
+        assert_ne!(hash("12.9", "var a;"), hash("12.9", "var b;"));
+    }
+
+    #[test]
+    fn test_write() {
+        let dir = std::env::temp_dir().join(format!("ssr_rs_code_cache_{}", std::process::id()));
+        let entry = Entry {
+            path: dir.join("cache.v8cache"),
+            data: None,
+        };
+
+        entry.write(b"first");
+        entry.write(b"second");
+        assert_eq!(fs::read(&entry.path).unwrap(), b"second");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,254 @@
+use crate::builtins;
+use crate::code_cache::{self, CodeCache};
+use crate::error::SsrError;
+use crate::modules::ModuleMap;
+use crate::ssr::Ssr;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use v8::MapFnTo;
+
+/// The parameters of the function a CommonJS module is wrapped in, as in Node.
+const PARAMETERS: [&str; 5] = ["exports", "require", "module", "__filename", "__dirname"];
+
+/// The extensions tried, in order, for a `require` specifier without one.
+const EXTENSIONS: [&str; 2] = [".js", ".json"];
+
+/// The `module` objects of the CommonJS modules evaluated by an instance, by resolved name.
+///
+/// A module is cached before it runs, so that cyclic `require`s get its partial exports as in
+/// Node. Stored in an isolate slot so that `require` can reach it.
+#[derive(Default)]
+pub(crate) struct CommonJs {
+    modules: RefCell<HashMap<String, v8::Global<v8::Object>>>,
+}
+
+impl CommonJs {
+    /// Returns the CommonJS modules of the isolate `scope` belongs to.
+    pub(crate) fn get(scope: &mut v8::HandleScope) -> Rc<CommonJs> {
+        scope
+            .get_slot::<Rc<CommonJs>>()
+            .cloned()
+            .expect("The CommonJS modules are not installed")
+    }
+}
+
+/// Compiles `source` followed by `suffix` as the body of the function wrapping the CommonJS
+/// module named `name`. Returns `None` with an exception pending if it does not compile.
+pub(crate) fn compile<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    source: &str,
+    name: &str,
+    suffix: &str,
+) -> Option<v8::Local<'s, v8::Function>> {
+    let body = format!("{source}{suffix}");
+    let string = v8::String::new(scope, &body)?;
+    let origin = Ssr::script_origin(scope, name, false);
+    let entry = CodeCache::entry(scope, &body);
+    let (mut compiled, options) = code_cache::source(entry.as_ref(), string, &origin);
+    let parameters: Vec<_> = PARAMETERS
+        .iter()
+        .map(|parameter| v8::String::new(scope, parameter).unwrap())
+        .collect();
+
+    // Compiled as a function rather than wrapped in one, which keeps the locations of the
+    // errors (and the source maps) aligned with the source.
+    let function = v8::script_compiler::compile_function(
+        scope,
+        &mut compiled,
+        &parameters,
+        &[],
+        options,
+        v8::script_compiler::NoCacheReason::NoReason,
+    )?;
+
+    if let Some(entry) = entry {
+        entry.update(&compiled, || function.create_code_cache());
+    }
+    Some(function)
+}
+
+/// Calls the function wrapping the CommonJS module named `name`, returning its `module` object
+/// and the value the function returned. Returns `None` with an exception pending if it throws.
+pub(crate) fn call<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    function: v8::Local<v8::Function>,
+    name: &str,
+) -> Option<(v8::Local<'s, v8::Object>, v8::Local<'s, v8::Value>)> {
+    let module = v8::Object::new(scope);
+    let exports = v8::Object::new(scope);
+    let filename = string(scope, name);
+    let directory = name
+        .rsplit_once('/')
+        .map_or(".", |(directory, _)| directory);
+    let directory = string(scope, directory);
+    let require = v8::Function::builder(require_callback)
+        .data(filename.into())
+        .build(scope)?;
+
+    set(scope, module, "id", filename.into());
+    set(scope, module, "filename", filename.into());
+    set(scope, module, "exports", exports.into());
+    set(scope, module, "require", require.into());
+    set_loaded(scope, module, false);
+
+    let commonjs = CommonJs::get(scope);
+    commonjs
+        .modules
+        .borrow_mut()
+        .insert(name.to_string(), v8::Global::new(scope, module));
+
+    let args = [
+        exports.into(),
+        require.into(),
+        module.into(),
+        filename.into(),
+        directory.into(),
+    ];
+    let Some(returned) = function.call(scope, exports.into(), &args) else {
+        // As in Node, a module that failed is required again from scratch.
+        commonjs.modules.borrow_mut().remove(name);
+        return None;
+    };
+
+    set_loaded(scope, module, true);
+    Some((module, returned))
+}
+
+/// Returns `module.exports`.
+pub(crate) fn exports<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    module: v8::Local<v8::Object>,
+) -> v8::Local<'s, v8::Value> {
+    let key = string(scope, "exports");
+    module
+        .get(scope, key.into())
+        .unwrap_or_else(|| v8::undefined(scope).into())
+}
+
+/// Returns the native callback of `require`, see [`crate::snapshot`].
+pub(crate) fn callbacks() -> Vec<v8::FunctionCallback> {
+    vec![require_callback.map_fn_to()]
+}
+
+/// Implements `require(specifier)`, relative to the module name given as data.
+fn require_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    if !args.get(0).is_string() {
+        let msg = string(scope, "The module specifier must be a string");
+        let error = v8::Exception::type_error(scope, msg);
+        scope.throw_exception(error);
+        return;
+    }
+    let specifier = args.get(0).to_rust_string_lossy(scope);
+    let referrer = args.data().to_rust_string_lossy(scope);
+
+    match require(scope, &specifier, &referrer) {
+        Ok(Some(exports)) => rv.set(exports),
+        // The module threw: its exception propagates.
+        Ok(None) => {}
+        Err(err) => {
+            let msg = string(scope, &err.to_string());
+            let error = v8::Exception::error(scope, msg);
+            scope.throw_exception(error);
+        }
+    }
+}
+
+fn require<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    specifier: &str,
+    referrer: &str,
+) -> Result<Option<v8::Local<'s, v8::Value>>, SsrError> {
+    let modules = ModuleMap::get(scope);
+    let commonjs = CommonJs::get(scope);
+    let resolved = modules.resolve(specifier, referrer)?;
+    if builtins::source(&resolved).is_some() {
+        return Ok(require_builtin(scope, &resolved));
+    }
+
+    // Node's resolution of a file or directory, minus `node_modules`: the exact name first,
+    // then with each extension, whatever the name already ends with (e.g. `./lib.min`).
+    let mut candidates = vec![resolved.clone()];
+    candidates.extend(EXTENSIONS.iter().map(|ext| format!("{resolved}{ext}")));
+    candidates.push(format!("{resolved}/index.js"));
+
+    let mut first_error = None;
+    for name in candidates {
+        if let Some(module) = commonjs.modules.borrow().get(&name) {
+            let module = v8::Local::new(scope, module);
+            return Ok(Some(exports(scope, module)));
+        }
+
+        match modules.loader()?.load(&name) {
+            Ok(source) => return evaluate(scope, &source, &name),
+            Err(err) => {
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    Err(first_error.unwrap_or_else(|| SsrError::ModuleLoad(format!("Cannot find {specifier}"))))
+}
+
+/// Returns the exports of the built-in module named `name`, evaluating it on first use.
+/// Returns `None` with an exception pending if it throws.
+pub(crate) fn require_builtin<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    name: &str,
+) -> Option<v8::Local<'s, v8::Value>> {
+    if let Some(module) = CommonJs::get(scope).modules.borrow().get(name) {
+        let module = v8::Local::new(scope, module);
+        return Some(exports(scope, module));
+    }
+
+    let source = builtins::source(name)?;
+    let function = compile(scope, source, name, "")?;
+    call(scope, function, name).map(|(module, _)| exports(scope, module))
+}
+
+fn evaluate<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    source: &str,
+    name: &str,
+) -> Result<Option<v8::Local<'s, v8::Value>>, SsrError> {
+    if name.ends_with(".json") {
+        let json = string(scope, source);
+        let Some(value) = v8::json::parse(scope, json) else {
+            return Ok(None);
+        };
+        let module = v8::Object::new(scope);
+        set(scope, module, "exports", value);
+        CommonJs::get(scope)
+            .modules
+            .borrow_mut()
+            .insert(name.to_string(), v8::Global::new(scope, module));
+        return Ok(Some(value));
+    }
+
+    let Some(function) = compile(scope, source, name, "") else {
+        return Ok(None);
+    };
+    Ok(call(scope, function, name).map(|(module, _)| exports(scope, module)))
+}
+
+fn set(
+    scope: &mut v8::HandleScope,
+    object: v8::Local<v8::Object>,
+    key: &str,
+    value: v8::Local<v8::Value>,
+) {
+    let key = string(scope, key);
+    object.set(scope, key.into(), value);
+}
+
+fn set_loaded(scope: &mut v8::HandleScope, module: v8::Local<v8::Object>, loaded: bool) {
+    let loaded = v8::Boolean::new(scope, loaded);
+    set(scope, module, "loaded", loaded.into());
+}
+
+fn string<'s>(scope: &mut v8::HandleScope<'s>, value: &str) -> v8::Local<'s, v8::String> {
+    v8::String::new(scope, value).unwrap()
+}
@@ -0,0 +1,413 @@
+use crate::error::{JsError, SsrError};
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use v8::MapFnTo;
+
+/// The number of frames printed by `console.trace`.
+const TRACE_FRAME_LIMIT: usize = 10;
+
+/// Receives the messages logged with `console`, see [`SsrBuilder::console`](crate::SsrBuilder::console).
+pub(crate) type ConsoleSink = Rc<dyn Fn(&ConsoleMessage)>;
+
+/// The severity of a console message, derived from the `console` method that logged it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    /// `console.trace`, followed by the JS stack trace.
+    Trace,
+    /// `console.debug`.
+    Debug,
+    /// `console.log`, `console.info`, `console.dir`, `console.dirxml`, `console.table` and the
+    /// labels of `console.group`.
+    Info,
+    /// `console.warn`.
+    Warn,
+    /// `console.error` and failed `console.assert`.
+    Error,
+}
+
+impl LogLevel {
+    const ALL: [LogLevel; 5] = [
+        LogLevel::Trace,
+        LogLevel::Debug,
+        LogLevel::Info,
+        LogLevel::Warn,
+        LogLevel::Error,
+    ];
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self {
+            LogLevel::Trace => "trace",
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        };
+        write!(f, "{level}")
+    }
+}
+
+/// A message logged by the bundle with `console`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsoleMessage {
+    pub level: LogLevel,
+    /// The arguments formatted as Node does it: `printf`-like substitutions (`%s`, `%d`, `%o`,
+    /// ...) in the first one, the others joined with spaces.
+    pub message: String,
+}
+
+/// Where the `console` methods send their messages.
+///
+/// Stored in an isolate slot so that the native callbacks can reach it.
+pub(crate) struct Console {
+    sink: Option<ConsoleSink>,
+    /// The messages of the running [`Ssr::capture_console`](crate::Ssr::capture_console) call.
+    captured: RefCell<Option<Vec<ConsoleMessage>>>,
+}
+
+impl Console {
+    /// Creates a console sending its messages to `sink`, or to `tracing` without one.
+    pub(crate) fn new(sink: Option<ConsoleSink>) -> Self {
+        Console {
+            sink,
+            captured: RefCell::new(None),
+        }
+    }
+
+    /// Defines the `console` object on the global object of the current context.
+    pub(crate) fn install(
+        scope: &mut v8::ContextScope<'_, v8::HandleScope>,
+    ) -> Result<(), SsrError> {
+        let console = v8::Object::new(scope);
+
+        let methods = [
+            ("trace", LogLevel::Trace),
+            ("debug", LogLevel::Debug),
+            ("log", LogLevel::Info),
+            ("info", LogLevel::Info),
+            ("dir", LogLevel::Info),
+            ("dirxml", LogLevel::Info),
+            ("table", LogLevel::Info),
+            ("warn", LogLevel::Warn),
+            ("error", LogLevel::Error),
+        ];
+        for (name, level) in methods {
+            let data = v8::Integer::new_from_unsigned(scope, level as u32);
+            let function = v8::Function::builder(log_callback)
+                .data(data.into())
+                .build(scope);
+            set_method(scope, console, name, function)?;
+        }
+
+        let assert = v8::Function::new(scope, assert_callback);
+        set_method(scope, console, "assert", assert)?;
+
+        for name in ["group", "groupCollapsed"] {
+            let group = v8::Function::new(scope, group_callback);
+            set_method(scope, console, name, group)?;
+        }
+
+        // Messages are not indented and there is no timing or counting: these only have to
+        // exist for the bundles calling them.
+        let noops = [
+            "groupEnd",
+            "time",
+            "timeEnd",
+            "timeLog",
+            "count",
+            "countReset",
+        ];
+        for name in noops {
+            let noop = v8::Function::new(scope, noop_callback);
+            set_method(scope, console, name, noop)?;
+        }
+
+        let global = scope.get_current_context().global(scope);
+        let key = v8::String::new(scope, "console").unwrap();
+        global.set(scope, key.into(), console.into());
+        Ok(())
+    }
+
+    /// Returns the native callbacks of the `console` methods, see [`crate::snapshot`].
+    pub(crate) fn callbacks() -> Vec<v8::FunctionCallback> {
+        vec![
+            log_callback.map_fn_to(),
+            assert_callback.map_fn_to(),
+            group_callback.map_fn_to(),
+            noop_callback.map_fn_to(),
+        ]
+    }
+
+    /// Runs `f`, collecting the messages logged meanwhile instead of sending them to the sink.
+    pub(crate) fn capture<R>(&self, f: impl FnOnce() -> R) -> (R, Vec<ConsoleMessage>) {
+        let outer = self.captured.replace(Some(Vec::new()));
+        let output = f();
+        let messages = self.captured.replace(outer).unwrap_or_default();
+        (output, messages)
+    }
+
+    fn emit(&self, message: ConsoleMessage) {
+        if let Some(captured) = self.captured.borrow_mut().as_mut() {
+            captured.push(message);
+            return;
+        }
+
+        match &self.sink {
+            Some(sink) => sink(&message),
+            None => trace_message(&message),
+        }
+    }
+}
+
+/// The default sink: emits the message as a `tracing` event with the `ssr_rs::console` target.
+fn trace_message(message: &ConsoleMessage) {
+    let text = &message.message;
+    match message.level {
+        LogLevel::Trace => tracing::trace!(target: "ssr_rs::console", "{text}"),
+        LogLevel::Debug => tracing::debug!(target: "ssr_rs::console", "{text}"),
+        LogLevel::Info => tracing::info!(target: "ssr_rs::console", "{text}"),
+        LogLevel::Warn => tracing::warn!(target: "ssr_rs::console", "{text}"),
+        LogLevel::Error => tracing::error!(target: "ssr_rs::console", "{text}"),
+    }
+}
+
+fn set_method(
+    scope: &mut v8::HandleScope,
+    console: v8::Local<v8::Object>,
+    name: &str,
+    function: Option<v8::Local<v8::Function>>,
+) -> Result<(), SsrError> {
+    let function = function.ok_or_else(|| {
+        SsrError::Evaluation(JsError::new(format!("Failed to create console.{name}")))
+    })?;
+    let key = v8::String::new(scope, name).unwrap();
+    console.set(scope, key.into(), function.into());
+    Ok(())
+}
+
+/// Implements the logging methods, the level being given as data.
+fn log_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let level = args.data().uint32_value(scope).unwrap_or_default() as usize;
+    let level = LogLevel::ALL.get(level).copied().unwrap_or(LogLevel::Info);
+
+    let values: Vec<_> = (0..args.length()).map(|index| args.get(index)).collect();
+    let mut message = format_values(scope, &values);
+    if level == LogLevel::Trace {
+        message = match message.as_str() {
+            "" => format!("Trace{}", stack_trace(scope)),
+            _ => format!("Trace: {message}{}", stack_trace(scope)),
+        };
+    }
+
+    emit(scope, level, message);
+}
+
+/// Implements `console.assert(condition, ...data)`.
+fn assert_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    if args.get(0).boolean_value(scope) {
+        return;
+    }
+
+    let values: Vec<_> = (1..args.length()).map(|index| args.get(index)).collect();
+    let message = match format_values(scope, &values) {
+        message if message.is_empty() => "Assertion failed".to_string(),
+        message => format!("Assertion failed: {message}"),
+    };
+    emit(scope, LogLevel::Error, message);
+}
+
+/// Implements `console.group(...label)` and `console.groupCollapsed`, logging the label if any.
+fn group_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    if args.length() == 0 {
+        return;
+    }
+    let values: Vec<_> = (0..args.length()).map(|index| args.get(index)).collect();
+    let message = format_values(scope, &values);
+    emit(scope, LogLevel::Info, message);
+}
+
+fn noop_callback(
+    _scope: &mut v8::HandleScope,
+    _args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+}
+
+fn emit(scope: &mut v8::HandleScope, level: LogLevel, message: String) {
+    let console = scope
+        .get_slot::<Rc<Console>>()
+        .cloned()
+        .expect("The console is not installed");
+    console.emit(ConsoleMessage { level, message });
+}
+
+/// Formats the arguments of a `console` method: substitutions in the first one when it is a
+/// string, then the remaining ones separated by spaces.
+fn format_values(scope: &mut v8::HandleScope, values: &[v8::Local<v8::Value>]) -> String {
+    let mut parts = Vec::with_capacity(values.len());
+    let mut rest = values;
+
+    if let Some((first, others)) = values.split_first().filter(|(first, _)| first.is_string()) {
+        let template = first.to_rust_string_lossy(scope);
+        let mut others = others.iter();
+        let mut formatted = String::with_capacity(template.len());
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            let specifier = match (c, chars.peek()) {
+                ('%', Some(&next)) if "sdifoOjc%".contains(next) => next,
+                _ => {
+                    formatted.push(c);
+                    continue;
+                }
+            };
+            chars.next();
+
+            if specifier == '%' {
+                formatted.push('%');
+                continue;
+            }
+            let Some(value) = others.next() else {
+                // Node prints the specifiers left without a value as is.
+                formatted.push('%');
+                formatted.push(specifier);
+                continue;
+            };
+
+            match specifier {
+                's' => formatted.push_str(&display(scope, *value)),
+                'd' | 'i' => {
+                    let number = value.number_value(scope).unwrap_or(f64::NAN);
+                    let number = if specifier == 'i' {
+                        number.trunc()
+                    } else {
+                        number
+                    };
+                    formatted.push_str(&format_number(number));
+                }
+                'f' => {
+                    let number = value.number_value(scope).unwrap_or(f64::NAN);
+                    formatted.push_str(&format_number(number));
+                }
+                // CSS styles only make sense in a browser.
+                'c' => {}
+                _ => formatted.push_str(&inspect(scope, *value)),
+            }
+        }
+
+        parts.push(formatted);
+        rest = others.as_slice();
+    }
+
+    for value in rest {
+        parts.push(display(scope, *value));
+    }
+    parts.join(" ")
+}
+
+/// Strings are printed as is, other values are inspected.
+fn display(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> String {
+    if value.is_string() {
+        value.to_rust_string_lossy(scope)
+    } else {
+        inspect(scope, value)
+    }
+}
+
+/// A readable representation of `value`: errors print their stack, functions their name and
+/// other objects their JSON.
+fn inspect(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> String {
+    let scope = &mut v8::TryCatch::new(scope);
+
+    if value.is_native_error() {
+        let stack = value.to_object(scope).and_then(|error| {
+            let key = v8::String::new(scope, "stack").unwrap();
+            error
+                .get(scope, key.into())
+                .filter(|stack| stack.is_string())
+        });
+        if let Some(stack) = stack {
+            return stack.to_rust_string_lossy(scope);
+        }
+    }
+
+    if let Ok(function) = v8::Local::<v8::Function>::try_from(value) {
+        let name = function.get_name(scope).to_rust_string_lossy(scope);
+        return match name.as_str() {
+            "" => "[Function (anonymous)]".to_string(),
+            name => format!("[Function: {name}]"),
+        };
+    }
+
+    if value.is_string() {
+        // Nested in a substitution like `%o`: quoted as JSON would do.
+        return serde_json::Value::String(value.to_rust_string_lossy(scope)).to_string();
+    }
+
+    if value.is_object() {
+        // `JSON.stringify` fails on cycles and `BigInt`s: fall back to the string conversion.
+        if let Some(json) = v8::json::stringify(scope, value).filter(|_| !scope.has_caught()) {
+            return json.to_rust_string_lossy(scope);
+        }
+        scope.reset();
+    }
+
+    value
+        .to_detail_string(scope)
+        .map(|value| value.to_rust_string_lossy(scope))
+        .unwrap_or_default()
+}
+
+fn format_number(number: f64) -> String {
+    if number.fract() == 0.0 && number.abs() < i64::MAX as f64 {
+        (number as i64).to_string()
+    } else {
+        number.to_string()
+    }
+}
+
+/// The current JS stack trace, one `at` line per frame as in `Error.prototype.stack`.
+fn stack_trace(scope: &mut v8::HandleScope) -> String {
+    let Some(trace) = v8::StackTrace::current_stack_trace(scope, TRACE_FRAME_LIMIT) else {
+        return String::new();
+    };
+
+    let mut lines = String::new();
+    for index in 0..trace.get_frame_count() {
+        let Some(frame) = trace.get_frame(scope, index) else {
+            continue;
+        };
+        let script = frame
+            .get_script_name(scope)
+            .map(|name| name.to_rust_string_lossy(scope))
+            .unwrap_or_else(|| "<anonymous>".to_string());
+        let location = format!(
+            "{script}:{}:{}",
+            frame.get_line_number(),
+            frame.get_column()
+        );
+
+        match frame.get_function_name(scope) {
+            Some(name) if name.length() > 0 => {
+                let name = name.to_rust_string_lossy(scope);
+                lines.push_str(&format!("\n    at {name} ({location})"));
+            }
+            _ => lines.push_str(&format!("\n    at {location}")),
+        }
+    }
+    lines
+}
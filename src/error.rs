@@ -0,0 +1,197 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+/// A JavaScript exception captured while compiling, evaluating or calling the bundle.
+///
+/// Host side failures which are not caused by a thrown value (e.g. a missing `exports`
+/// object) are reported with only the `message` set.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct JsError {
+    /// The stringified exception, e.g. `Error: Something went wrong`.
+    pub message: String,
+    /// The JavaScript stack trace, when the thrown value carries one.
+    pub stack: Option<String>,
+    /// The resource name of the script where the exception originated.
+    pub script_name: Option<String>,
+    /// The 1-based line number where the exception originated.
+    pub line: Option<usize>,
+    /// The 1-based column number where the exception originated.
+    pub column: Option<usize>,
+}
+
+impl JsError {
+    pub(crate) fn new(message: impl Into<String>) -> Self {
+        JsError {
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn from_exception(
+        scope: &mut v8::HandleScope,
+        exception: v8::Local<v8::Value>,
+    ) -> Self {
+        let message = exception.to_rust_string_lossy(scope);
+
+        let stack = exception.to_object(scope).and_then(|object| {
+            let key = v8::String::new(scope, "stack").unwrap();
+            object
+                .get(scope, key.into())
+                .filter(|stack| stack.is_string())
+                .map(|stack| stack.to_rust_string_lossy(scope))
+        });
+
+        let details = v8::Exception::create_message(scope, exception);
+        let script_name = details
+            .get_script_resource_name(scope)
+            .filter(|name| !name.is_null_or_undefined())
+            .map(|name| name.to_rust_string_lossy(scope));
+        let line = details.get_line_number(scope).filter(|line| *line > 0);
+        let column = line.map(|_| details.get_start_column() + 1);
+
+        JsError {
+            message,
+            stack,
+            script_name,
+            line,
+            column,
+        }
+    }
+
+    /// Builds the error from the exception caught by `try_catch`, falling back to
+    /// `fallback` when nothing was thrown (e.g. the execution was terminated).
+    pub(crate) fn from_try_catch(
+        try_catch: &mut v8::TryCatch<v8::HandleScope>,
+        fallback: &str,
+    ) -> Self {
+        match try_catch.exception() {
+            Some(exception) => Self::from_exception(try_catch, exception),
+            None => Self::new(fallback),
+        }
+    }
+}
+
+impl fmt::Display for JsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(stack) = &self.stack {
+            return write!(f, "{stack}");
+        }
+        write!(f, "{}", self.message)?;
+        if let (Some(script_name), Some(line), Some(column)) =
+            (&self.script_name, self.line, self.column)
+        {
+            write!(f, " ({script_name}:{line}:{column})")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for JsError {}
+
+/// The error returned by every fallible [`Ssr`](crate::Ssr) operation.
+///
+/// Each variant maps to a distinct failure stage so that servers can react differently,
+/// e.g. answering with a `500` for a rejected render but failing fast at startup for a
+/// bundle that does not compile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SsrError {
+    /// The source (script or module) could not be compiled.
+    Compile(JsError),
+    /// The source compiled but threw or failed while being evaluated or called.
+    Evaluation(JsError),
+    /// The requested entry point does not exist in the evaluated bundle.
+    EntryPointNotFound(String),
+    /// The entry point exists but is not a function.
+    EntryNotFunction(String),
+    /// No render function is exported under the requested name.
+    ExportNotFound(String),
+    /// The promise returned by the bundle was rejected.
+    PromiseRejected(JsError),
+    /// The render result could not be converted to a string.
+    ResultNotStringifiable,
+    /// The render result does not have the shape of a [`RenderOutput`](crate::RenderOutput).
+    InvalidOutput(String),
+    /// The given module type is not supported.
+    UnsupportedModuleType(String),
+    /// The given source map could not be parsed.
+    InvalidSourceMap(String),
+    /// A module imported by the bundle could not be resolved or loaded by the
+    /// [`ModuleLoader`](crate::ModuleLoader).
+    ModuleLoad(String),
+    /// The JS execution exceeded the configured timeout and was terminated.
+    Timeout(Duration),
+    /// A Rust value could not be converted to or from its JS representation.
+    Serialization(String),
+    /// The isolate reached its heap limit and the execution was aborted.
+    OutOfMemory,
+    /// The promise returned by the bundle is still pending once every queued job ran, so it
+    /// can never settle. Without a timeout, it is also reported once intervals alone kept the
+    /// promise pending for 30 seconds (of virtual time, when enabled).
+    UnsettledPromise,
+    /// The [`SsrPool`](crate::SsrPool) worker running the job stopped (e.g. the job panicked)
+    /// before replying.
+    WorkerUnavailable,
+    /// The [`Snapshot`](crate::Snapshot) could not be created, or its bytes were not created by
+    /// this build of the crate.
+    InvalidSnapshot(String),
+    /// V8 flags were given once V8 was already initialized, so they could not be applied.
+    AlreadyInitialized,
+}
+
+impl SsrError {
+    /// The JavaScript exception details carried by the error, if any.
+    pub fn js_error(&self) -> Option<&JsError> {
+        match self {
+            SsrError::Compile(err) | SsrError::Evaluation(err) | SsrError::PromiseRejected(err) => {
+                Some(err)
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn js_error_mut(&mut self) -> Option<&mut JsError> {
+        match self {
+            SsrError::Compile(err) | SsrError::Evaluation(err) | SsrError::PromiseRejected(err) => {
+                Some(err)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SsrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SsrError::Compile(err) => write!(f, "Failed to compile: {err}"),
+            SsrError::Evaluation(err) => write!(f, "Failed to evaluate: {err}"),
+            SsrError::EntryPointNotFound(name) => write!(f, "Entry point not found: {name}"),
+            SsrError::EntryNotFunction(name) => write!(f, "Entry point is not a function: {name}"),
+            SsrError::ExportNotFound(name) => write!(f, "Export not found: {name}"),
+            SsrError::PromiseRejected(err) => write!(f, "Promise rejected: {err}"),
+            SsrError::ResultNotStringifiable => {
+                write!(f, "Failed to parse the result to string")
+            }
+            SsrError::InvalidOutput(msg) => write!(f, "Invalid render output: {msg}"),
+            SsrError::UnsupportedModuleType(module_type) => {
+                write!(f, "Unsupported module type: {module_type}")
+            }
+            SsrError::InvalidSourceMap(msg) => write!(f, "Invalid source map: {msg}"),
+            SsrError::ModuleLoad(msg) => write!(f, "Failed to load module: {msg}"),
+            SsrError::Timeout(timeout) => write!(f, "Execution timed out after {timeout:?}"),
+            SsrError::Serialization(msg) => write!(f, "Failed to serialize: {msg}"),
+            SsrError::OutOfMemory => write!(f, "Heap limit reached"),
+            SsrError::UnsettledPromise => write!(f, "Promise never settled"),
+            SsrError::WorkerUnavailable => write!(f, "The pool worker stopped before replying"),
+            SsrError::InvalidSnapshot(msg) => write!(f, "Invalid snapshot: {msg}"),
+            SsrError::AlreadyInitialized => {
+                write!(f, "V8 is already initialized, the flags cannot be applied")
+            }
+        }
+    }
+}
+
+// The `JsError` carried by some variants is already part of the message, so it is not
+// reported again as the source; it stays reachable through `SsrError::js_error`.
+impl Error for SsrError {}
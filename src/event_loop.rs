@@ -0,0 +1,559 @@
+use crate::error::{JsError, SsrError};
+use crate::value;
+use serde_json::Value;
+use std::cell::{Cell, RefCell};
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::Poll;
+use std::thread;
+use std::time::{Duration, Instant};
+use v8::{MapFnTo, PromiseState};
+
+pub(crate) type BoxFuture<T> = Pin<Box<dyn Future<Output = T>>>;
+
+/// A Rust function exposed to JS as an async function, see
+/// [`SsrBuilder::async_function`](crate::SsrBuilder::async_function).
+pub(crate) type AsyncFunction = Rc<dyn Fn(Value) -> BoxFuture<Result<Value, String>>>;
+
+/// Work run on the isolate once the host work it waited for is done, e.g. settling a promise.
+type Completion = Box<dyn FnOnce(&mut v8::HandleScope)>;
+
+/// Intervals shorter than this would keep the loop busy without letting time pass.
+const MIN_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The longest timer delay in milliseconds, as in browsers and Node.
+const MAX_DELAY_MS: f64 = 2_147_483_647.0;
+
+/// How long intervals alone may keep an operation without a deadline waiting, before the
+/// promise it waits for is deemed unable to settle.
+const INTERVALS_ONLY_LIMIT: Duration = Duration::from_secs(30);
+
+/// A callback registered with `setTimeout` or `setInterval`.
+struct Timer {
+    id: u32,
+    /// When the timer fires, relative to the creation of the event loop.
+    due: Duration,
+    interval: Option<Duration>,
+    callback: v8::Global<v8::Function>,
+    args: Vec<v8::Global<v8::Value>>,
+}
+
+/// The timers and the host work JS promises are waiting for.
+///
+/// Nothing runs in the background: a blocking render runs the timers (sleeping until they are
+/// due) while it waits for a promise, and an async render additionally polls the host work
+/// with its own context, so that it is woken up when some of it completes instead of polling
+/// the promise in a loop. The event loop is stored in an isolate slot so that the native
+/// callbacks can reach it.
+pub(crate) struct EventLoop {
+    functions: RefCell<Vec<AsyncFunction>>,
+    tasks: RefCell<Vec<BoxFuture<Completion>>>,
+    timers: RefCell<Vec<Timer>>,
+    next_timer_id: Cell<u32>,
+    origin: Instant,
+    /// The current time when time is virtual.
+    virtual_now: Option<Cell<Duration>>,
+    /// The deadline of the running operation, past which waiting for a timer fails.
+    deadline: Cell<Option<Deadline>>,
+    /// Since when only intervals are pending, see [`EventLoop::check_stalled`].
+    intervals_only_since: Cell<Option<Duration>>,
+}
+
+impl EventLoop {
+    pub(crate) fn new(virtual_time: bool) -> Self {
+        EventLoop {
+            functions: RefCell::new(Vec::new()),
+            tasks: RefCell::new(Vec::new()),
+            timers: RefCell::new(Vec::new()),
+            next_timer_id: Cell::new(1),
+            origin: Instant::now(),
+            virtual_now: virtual_time.then(|| Cell::new(Duration::ZERO)),
+            deadline: Cell::new(None),
+            intervals_only_since: Cell::new(None),
+        }
+    }
+
+    /// Returns the event loop of the isolate `scope` belongs to.
+    pub(crate) fn get(scope: &mut v8::HandleScope) -> Rc<EventLoop> {
+        scope
+            .get_slot::<Rc<EventLoop>>()
+            .cloned()
+            .expect("The event loop is not installed")
+    }
+
+    /// Defines the timer functions and `queueMicrotask` on the global object of the current
+    /// context.
+    pub(crate) fn install_timers(
+        scope: &mut v8::ContextScope<'_, v8::HandleScope>,
+    ) -> Result<(), SsrError> {
+        set_function(scope, "setTimeout", set_timeout_callback)?;
+        set_function(scope, "setInterval", set_interval_callback)?;
+        set_function(scope, "clearTimeout", clear_timer_callback)?;
+        set_function(scope, "clearInterval", clear_timer_callback)?;
+        set_function(scope, "queueMicrotask", queue_microtask_callback)
+    }
+
+    /// Returns the native callbacks of the timer functions and of the async functions, see
+    /// [`crate::snapshot`].
+    pub(crate) fn callbacks() -> Vec<v8::FunctionCallback> {
+        vec![
+            set_timeout_callback.map_fn_to(),
+            set_interval_callback.map_fn_to(),
+            clear_timer_callback.map_fn_to(),
+            queue_microtask_callback.map_fn_to(),
+            async_function_callback.map_fn_to(),
+        ]
+    }
+
+    /// Defines the `functions` on the global object of the current context.
+    pub(crate) fn install_functions(
+        &self,
+        scope: &mut v8::ContextScope<'_, v8::HandleScope>,
+        functions: &[(String, AsyncFunction)],
+    ) -> Result<(), SsrError> {
+        let global = scope.get_current_context().global(scope);
+
+        for (name, function) in functions {
+            let index = {
+                let mut registered = self.functions.borrow_mut();
+                registered.push(function.clone());
+                registered.len() - 1
+            };
+
+            let data = v8::Integer::new_from_unsigned(scope, index as u32);
+            let function = v8::Function::builder(async_function_callback)
+                .data(data.into())
+                .build(scope)
+                .ok_or_else(|| {
+                    SsrError::Evaluation(JsError::new(format!(
+                        "Failed to create the async function {name}"
+                    )))
+                })?;
+            let key = v8::String::new(scope, name).unwrap();
+            global.set(scope, key.into(), function.into());
+        }
+
+        Ok(())
+    }
+
+    /// Sets the deadline of the operation about to run.
+    pub(crate) fn set_deadline(&self, deadline: Option<Deadline>) {
+        self.deadline.set(deadline);
+    }
+
+    /// Returns whether some host work or timer is still pending.
+    pub(crate) fn has_pending(&self) -> bool {
+        !self.tasks.borrow().is_empty() || !self.timers.borrow().is_empty()
+    }
+
+    /// Drops the host work and the timers left behind by an operation.
+    pub(crate) fn clear(&self) {
+        self.tasks.borrow_mut().clear();
+        self.timers.borrow_mut().clear();
+        self.intervals_only_since.set(None);
+    }
+
+    /// Fails with [`SsrError::UnsettledPromise`] once intervals alone kept an operation without
+    /// `deadline` waiting for [`INTERVALS_ONLY_LIMIT`].
+    ///
+    /// An interval could settle the promise on any of its runs, but one that did not after so
+    /// long most likely never will (e.g. a polling loop left behind by a library), and would
+    /// otherwise block the operation forever.
+    pub(crate) fn check_stalled(&self, deadline: Option<Deadline>) -> Result<(), SsrError> {
+        let intervals_only = self.tasks.borrow().is_empty()
+            && self
+                .timers
+                .borrow()
+                .iter()
+                .all(|timer| timer.interval.is_some());
+        if deadline.is_some() || !intervals_only {
+            self.intervals_only_since.set(None);
+            return Ok(());
+        }
+
+        let now = self.now();
+        let since = self.intervals_only_since.get().unwrap_or(now);
+        if now.saturating_sub(since) >= INTERVALS_ONLY_LIMIT {
+            return Err(SsrError::UnsettledPromise);
+        }
+        self.intervals_only_since.set(Some(since));
+        Ok(())
+    }
+
+    /// Runs the timers until `promise` settles.
+    pub(crate) fn settle<'s>(
+        &self,
+        scope: &mut v8::HandleScope<'s>,
+        promise: v8::Local<'s, v8::Promise>,
+    ) -> Result<v8::Local<'s, v8::Value>, SsrError> {
+        loop {
+            if let Some(value) = poll_promise(scope, promise)? {
+                return Ok(value);
+            }
+            if !self.run_next_timers(scope)? {
+                // Host work cannot make progress in a blocking operation: nothing is left that
+                // could settle the promise.
+                return Err(SsrError::UnsettledPromise);
+            }
+        }
+    }
+
+    /// Waits until the next timer is due and runs it along with the other due timers. Returns
+    /// `false` if there is no timer.
+    pub(crate) fn run_next_timers(&self, scope: &mut v8::HandleScope) -> Result<bool, SsrError> {
+        let Some(delay) = self.next_timer_delay() else {
+            return Ok(false);
+        };
+        self.check_stalled(self.deadline.get())?;
+
+        if let Some(deadline) = self.deadline.get() {
+            let remaining = deadline.remaining()?;
+            if self.virtual_now.is_none() && delay > remaining {
+                thread::sleep(remaining);
+                return Err(deadline.error());
+            }
+        }
+        self.advance(delay);
+
+        self.run_due_timers(scope)?;
+        Ok(true)
+    }
+
+    /// Waits until some host work completes or the next timer is due, and returns what has to
+    /// run on the isolate before [`EventLoop::run_due_timers`].
+    pub(crate) async fn next(&self) -> Vec<Completion> {
+        let delay = self.next_timer_delay();
+        if let (Some(delay), Some(_)) = (delay, &self.virtual_now) {
+            self.advance(delay);
+            return Vec::new();
+        }
+
+        let mut sleep = delay.map(|delay| Box::pin(tokio::time::sleep(delay)));
+        poll_fn(|cx| {
+            let mut completions = Vec::new();
+            self.tasks
+                .borrow_mut()
+                .retain_mut(|task| match task.as_mut().poll(cx) {
+                    Poll::Ready(completion) => {
+                        completions.push(completion);
+                        false
+                    }
+                    Poll::Pending => true,
+                });
+
+            let timer_due = sleep
+                .as_mut()
+                .is_some_and(|sleep| sleep.as_mut().poll(cx).is_ready());
+
+            if completions.is_empty() && !timer_due {
+                Poll::Pending
+            } else {
+                Poll::Ready(completions)
+            }
+        })
+        .await
+    }
+
+    /// Runs the timers that are due, in order, draining the microtask queue after each of them.
+    ///
+    /// Timers registered meanwhile wait for the next call, even with a zero delay, so that a
+    /// timer registering itself again cannot keep this call busy.
+    pub(crate) fn run_due_timers(&self, scope: &mut v8::HandleScope) -> Result<(), SsrError> {
+        let now = self.now();
+        let last_id = self.next_timer_id.get();
+
+        loop {
+            let timer = {
+                let mut timers = self.timers.borrow_mut();
+                let next = timers
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, timer)| timer.due <= now && timer.id < last_id)
+                    .min_by_key(|(_, timer)| (timer.due, timer.id))
+                    .map(|(index, _)| index);
+                let Some(index) = next else {
+                    return Ok(());
+                };
+
+                let timer = timers.remove(index);
+                if let Some(interval) = timer.interval {
+                    timers.push(Timer {
+                        due: now + interval,
+                        callback: timer.callback.clone(),
+                        args: timer.args.clone(),
+                        ..timer
+                    });
+                }
+                timer
+            };
+
+            let scope = &mut v8::TryCatch::new(scope);
+            let callback = v8::Local::new(scope, &timer.callback);
+            let args: Vec<v8::Local<v8::Value>> = timer
+                .args
+                .iter()
+                .map(|arg| v8::Local::new(scope, arg))
+                .collect();
+            let undef = v8::undefined(scope).into();
+
+            if callback.call(scope, undef, &args).is_none() {
+                return Err(SsrError::Evaluation(JsError::from_try_catch(
+                    scope,
+                    "Timer callback failed",
+                )));
+            }
+            scope.perform_microtask_checkpoint();
+        }
+    }
+
+    fn add_timer(
+        &self,
+        delay: Duration,
+        interval: Option<Duration>,
+        callback: v8::Global<v8::Function>,
+        args: Vec<v8::Global<v8::Value>>,
+    ) -> u32 {
+        let id = self.next_timer_id.get();
+        self.next_timer_id.set(id.wrapping_add(1).max(1));
+
+        self.timers.borrow_mut().push(Timer {
+            id,
+            due: self.now() + delay,
+            interval,
+            callback,
+            args,
+        });
+        id
+    }
+
+    fn remove_timer(&self, id: u32) {
+        self.timers.borrow_mut().retain(|timer| timer.id != id);
+    }
+
+    /// Returns the time left before the next timer is due.
+    fn next_timer_delay(&self) -> Option<Duration> {
+        let now = self.now();
+        self.timers
+            .borrow()
+            .iter()
+            .map(|timer| timer.due.saturating_sub(now))
+            .min()
+    }
+
+    fn now(&self) -> Duration {
+        match &self.virtual_now {
+            Some(now) => now.get(),
+            None => self.origin.elapsed(),
+        }
+    }
+
+    /// Lets `delay` pass: instantly when time is virtual, sleeping otherwise.
+    fn advance(&self, delay: Duration) {
+        match &self.virtual_now {
+            Some(now) => now.set(now.get() + delay),
+            None => thread::sleep(delay),
+        }
+    }
+
+    fn spawn(&self, task: impl Future<Output = Completion> + 'static) {
+        self.tasks.borrow_mut().push(Box::pin(task));
+    }
+}
+
+/// Drains the microtask queue and returns the promise value, or `None` if it is still pending.
+pub(crate) fn poll_promise<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    promise: v8::Local<'s, v8::Promise>,
+) -> Result<Option<v8::Local<'s, v8::Value>>, SsrError> {
+    if promise.state() == PromiseState::Pending {
+        scope.perform_microtask_checkpoint();
+    }
+
+    match promise.state() {
+        PromiseState::Pending => Ok(None),
+        PromiseState::Rejected => {
+            let reason = promise.result(scope);
+            Err(SsrError::PromiseRejected(JsError::from_exception(
+                scope, reason,
+            )))
+        }
+        PromiseState::Fulfilled => Ok(Some(promise.result(scope))),
+    }
+}
+
+fn set_function(
+    scope: &mut v8::ContextScope<'_, v8::HandleScope>,
+    name: &str,
+    callback: impl v8::MapFnTo<v8::FunctionCallback>,
+) -> Result<(), SsrError> {
+    let global = scope.get_current_context().global(scope);
+    let function = v8::Function::new(scope, callback)
+        .ok_or_else(|| SsrError::Evaluation(JsError::new(format!("Failed to create {name}"))))?;
+    let key = v8::String::new(scope, name).unwrap();
+    global.set(scope, key.into(), function.into());
+    Ok(())
+}
+
+fn set_timeout_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    rv: v8::ReturnValue,
+) {
+    add_timer(scope, args, rv, false);
+}
+
+fn set_interval_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    rv: v8::ReturnValue,
+) {
+    add_timer(scope, args, rv, true);
+}
+
+/// Implements `setTimeout(callback, delay, ...args)` and `setInterval`.
+fn add_timer(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+    repeat: bool,
+) {
+    let Ok(callback) = v8::Local::<v8::Function>::try_from(args.get(0)) else {
+        throw_type_error(scope, "The callback must be a function");
+        return;
+    };
+
+    let delay_ms = args.get(1).number_value(scope).unwrap_or_default();
+    let delay = if delay_ms > MAX_DELAY_MS {
+        // Like Node, a delay too long to be represented fires (almost) at once.
+        MIN_INTERVAL
+    } else if delay_ms > 0.0 {
+        Duration::from_secs_f64(delay_ms / 1000.0)
+    } else {
+        // Also covers `NaN`.
+        Duration::ZERO
+    };
+    let interval = repeat.then(|| delay.max(MIN_INTERVAL));
+
+    let callback = v8::Global::new(scope, callback);
+    let timer_args = (2..args.length())
+        .map(|index| v8::Global::new(scope, args.get(index)))
+        .collect();
+
+    let id =
+        EventLoop::get(scope).add_timer(interval.unwrap_or(delay), interval, callback, timer_args);
+    rv.set_uint32(id);
+}
+
+/// Implements `clearTimeout(id)` and `clearInterval(id)`.
+fn clear_timer_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    if let Some(id) = args.get(0).uint32_value(scope) {
+        EventLoop::get(scope).remove_timer(id);
+    }
+}
+
+fn queue_microtask_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    match v8::Local::<v8::Function>::try_from(args.get(0)) {
+        Ok(callback) => scope.enqueue_microtask(callback),
+        Err(_) => throw_type_error(scope, "The callback must be a function"),
+    }
+}
+
+fn throw_type_error(scope: &mut v8::HandleScope, msg: &str) {
+    let msg = v8::String::new(scope, msg).unwrap();
+    let error = v8::Exception::type_error(scope, msg);
+    scope.throw_exception(error);
+}
+
+/// Calls the async function registered at the index given as data, returning a promise settled
+/// by the event loop once its future completes.
+fn async_function_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let event_loop = EventLoop::get(scope);
+    let index = args.data().uint32_value(scope).unwrap_or_default() as usize;
+    // Functions restored from a snapshot are only backed by the ones registered again.
+    let Some(function) = event_loop.functions.borrow().get(index).cloned() else {
+        throw_type_error(scope, "The async function is not registered");
+        return;
+    };
+
+    let resolver = v8::PromiseResolver::new(scope).unwrap();
+    rv.set(resolver.get_promise(scope).into());
+
+    let future = match value::from_v8(scope, args.get(0)) {
+        Ok(arg) => function(arg),
+        Err(err) => {
+            reject(scope, resolver, &err.to_string());
+            return;
+        }
+    };
+
+    let resolver = v8::Global::new(scope, resolver);
+    event_loop.spawn(async move {
+        let result = future.await;
+        Box::new(move |scope: &mut v8::HandleScope| {
+            let resolver = v8::Local::new(scope, resolver);
+            match result
+                .and_then(|result| value::to_v8(scope, &result).map_err(|err| err.to_string()))
+            {
+                Ok(result) => {
+                    resolver.resolve(scope, result);
+                }
+                Err(msg) => reject(scope, resolver, &msg),
+            }
+        }) as Completion
+    });
+}
+
+fn reject(scope: &mut v8::HandleScope, resolver: v8::Local<v8::PromiseResolver>, msg: &str) {
+    let msg = v8::String::new(scope, msg).unwrap();
+    let error = v8::Exception::error(scope, msg);
+    resolver.reject(scope, error);
+}
+
+/// The point in time an operation must be done by.
+#[derive(Clone, Copy)]
+pub(crate) struct Deadline {
+    timeout: Duration,
+    at: Instant,
+}
+
+impl Deadline {
+    pub(crate) fn new(timeout: Option<Duration>) -> Option<Self> {
+        timeout.map(|timeout| Deadline {
+            timeout,
+            at: Instant::now() + timeout,
+        })
+    }
+
+    /// Returns the time left, or [`SsrError::Timeout`] once the deadline passed.
+    pub(crate) fn remaining(&self) -> Result<Duration, SsrError> {
+        self.at
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero())
+            .ok_or_else(|| self.error())
+    }
+
+    /// The error reported when the deadline passed, carrying the whole timeout.
+    pub(crate) fn error(&self) -> SsrError {
+        SsrError::Timeout(self.timeout)
+    }
+
+    /// Awaits `future`, failing with [`SsrError::Timeout`] if it does not complete in time.
+    pub(crate) async fn wait<F: Future>(&self, future: F) -> Result<F::Output, SsrError> {
+        tokio::time::timeout(self.remaining()?, future)
+            .await
+            .map_err(|_| self.error())
+    }
+}
@@ -0,0 +1,60 @@
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Aborts the running JS execution when the isolate heap gets close to its limit instead of
+/// letting V8 crash the whole process with a fatal out-of-memory error.
+pub(crate) struct HeapGuard {
+    handle: v8::IsolateHandle,
+    near_limit: AtomicBool,
+    exhausted: AtomicBool,
+}
+
+impl HeapGuard {
+    /// Registers the guard on `isolate`. The returned box must outlive the isolate.
+    pub(crate) fn install(isolate: &mut v8::OwnedIsolate) -> Box<Self> {
+        let guard = Box::new(HeapGuard {
+            handle: isolate.thread_safe_handle(),
+            near_limit: AtomicBool::new(false),
+            exhausted: AtomicBool::new(false),
+        });
+
+        isolate.add_near_heap_limit_callback(
+            near_heap_limit_callback,
+            &*guard as *const HeapGuard as *mut c_void,
+        );
+
+        guard
+    }
+
+    /// Unregisters the guard from `isolate`, which may then outlive it.
+    pub(crate) fn uninstall(self, isolate: &mut v8::OwnedIsolate) {
+        isolate.remove_near_heap_limit_callback(near_heap_limit_callback, 0);
+    }
+
+    /// Returns whether the heap limit was reached since the last call.
+    pub(crate) fn take_near_limit(&self) -> bool {
+        self.near_limit.swap(false, Ordering::SeqCst)
+    }
+
+    /// Returns whether the heap limit was ever reached.
+    pub(crate) fn is_exhausted(&self) -> bool {
+        self.exhausted.load(Ordering::SeqCst)
+    }
+}
+
+extern "C" fn near_heap_limit_callback(
+    data: *mut c_void,
+    current_heap_limit: usize,
+    _initial_heap_limit: usize,
+) -> usize {
+    // SAFETY: `data` is the `HeapGuard` registered in `HeapGuard::install`, which outlives
+    // the isolate.
+    let guard = unsafe { &*(data as *const HeapGuard) };
+
+    guard.near_limit.store(true, Ordering::SeqCst);
+    guard.exhausted.store(true, Ordering::SeqCst);
+    guard.handle.terminate_execution();
+
+    // Give V8 enough room to unwind the terminated execution.
+    current_heap_limit * 2
+}
@@ -19,19 +19,17 @@
 //! To render to string a bundled react project the application should perform the following calls.
 //!
 //! ```no_run
-//! use ssr_rs::Ssr;
+//! use ssr_rs::{ModuleType, Ssr};
 //! use std::fs::read_to_string;
 //!
-//! fn main() {
-//!     let source = read_to_string("./path/to/build.js").unwrap();
+//! let source = read_to_string("./path/to/build.js").unwrap();
 //!
-//!     let js = Ssr::new();
-//!     js.load(&source, "entryPoint", "cjs").unwrap();
+//! let js = Ssr::new();
+//! js.load(&source, "entryPoint", ModuleType::Cjs).unwrap();
 //!
-//!     let html = js.render_to_string(None).unwrap();
-//!    
-//!     assert_eq!(html, "<!doctype html><html>...</html>".to_string());
-//! }
+//! let html = js.render_to_string(None).unwrap();
+//!
+//! assert_eq!(html, "<!doctype html><html>...</html>".to_string());
 //! ```
 //!
 //! ## What is the "entryPoint"?
@@ -63,29 +61,42 @@
 //!
 //! > The exports results are managed by the bundler directly.
 //!
+//! ## Which `ModuleType`?
+//! - `ModuleType::Esm` for ES modules, the `entryPoint` being the name of the exported render function.
+//! - `ModuleType::Iife` for scripts such as the examples above.
+//! - `ModuleType::Cjs` for CommonJS bundles (e.g. webpack's `libraryTarget: "commonjs2"`), an empty `entryPoint` standing for `module.exports`.
+//! - `ModuleType::Auto` to let `ssr_rs` pick between `Esm` and `Cjs` from the presence of `import`/`export` statements.
+//!
+//! ## Web APIs
+//! With the `web-apis` feature enabled, `TextEncoder`, `TextDecoder`, `URL`, `URLSearchParams`,
+//! `atob` and `btoa` are defined on the global object, implemented in Rust.
+//!
+//! ```toml
+//! [dependencies]
+//! ssr_rs = { version = "0.5.5", features = ["web-apis"] }
+//! ```
+//!
 //! # Example with initial props
 //! ```no_run
-//! use ssr_rs::Ssr;
+//! use ssr_rs::{ModuleType, Ssr};
 //! use std::fs::read_to_string;
 //!
-//! fn main() {
-//!     let props = r##"{
-//!       "params": [
-//!            "hello",
-//!            "ciao",
-//!            "こんにちは"
-//!        ]
-//!     }"##;
+//! let props = r##"{
+//!   "params": [
+//!        "hello",
+//!        "ciao",
+//!        "こんにちは"
+//!    ]
+//! }"##;
 //!
-//!     let source = read_to_string("./path/to/build.js").unwrap();
+//! let source = read_to_string("./path/to/build.js").unwrap();
 //!
-//!     let js = Ssr::new();
-//!     js.load(&source, "entryPoint", "cjs").unwrap();
+//! let js = Ssr::new();
+//! js.load(&source, "entryPoint", ModuleType::Cjs).unwrap();
 //!
-//!     let html = js.render_to_string(Some(props)).unwrap();
-//!    
-//!     assert_eq!(html, "<!doctype html><html>...</html>".to_string());
-//! }
+//! let html = js.render_to_string(Some(props)).unwrap();
+//!
+//! assert_eq!(html, "<!doctype html><html>...</html>".to_string());
 //!```
 //!
 //! # Example with actix-web
@@ -95,31 +106,31 @@
 //! 1. rusty_v8 library have not implemented yet the V8 Locker API. Accessing Ssr struct from a different thread will make the V8 engine to panic.
 //! 2. Rendering HTML does not need shared state across threads.
 //!
-//! For the reasons above parallel computation is a better choice. Following actix-web setup:
+//! For the reasons above parallel computation is a better choice: a [`SsrPool`] runs one `Ssr`
+//! per worker thread behind a handle that can be shared as application state. Following
+//! actix-web setup:
 //!
 //! ```no_run
-//! use actix_web::{get, http::StatusCode, App, HttpResponse, HttpServer};
-//! use std::cell::RefCell;
+//! use actix_web::{get, http::StatusCode, web, App, HttpResponse, HttpServer};
 //! use std::fs::read_to_string;
+//! use std::num::NonZeroUsize;
 //!
-//! use ssr_rs::Ssr;
-//!
-//! thread_local! {
-//!    static SSR: RefCell<Ssr> = RefCell::new({
-//!        let ssr = Ssr::new();
-//!        ssr.load(
-//!            &read_to_string("./client/dist/ssr/index.js").unwrap(),
-//!            "SSR",
-//!            "cjs"
-//!        ).unwrap();
-//!        ssr
-//!    });
-//! }
+//! use ssr_rs::{ModuleType, Ssr, SsrPool};
 //!
 //! #[actix_web::main]
 //! async fn main() -> std::io::Result<()> {
-//!    HttpServer::new(|| {
+//!    let source = read_to_string("./client/dist/ssr/index.js").unwrap();
+//!    let pool = SsrPool::new(NonZeroUsize::new(4).unwrap(), move || {
+//!        Ssr::builder()
+//!            .source(&source, "SSR")
+//!            .module_type(ModuleType::Cjs)
+//!            .build()
+//!    })
+//!    .unwrap();
+//!
+//!    HttpServer::new(move || {
 //!        App::new()
+//!            .app_data(web::Data::new(pool.clone()))
 //!            .service(index)
 //!        })
 //!        .bind("127.0.0.1:8080")?
@@ -128,13 +139,48 @@
 //! }
 //!
 //! #[get("/")]
-//! async fn index() -> HttpResponse {
-//!    let result = SSR.with(|ssr| ssr.borrow_mut().render_to_string(None).unwrap());
+//! async fn index(pool: web::Data<SsrPool>) -> HttpResponse {
+//!    let result = pool.render(None).await.unwrap();
 //!
 //!    HttpResponse::build(StatusCode::OK)
 //!        .content_type("text/html; charset=utf-8")
 //!        .body(result)
 //! }
 //!```
+mod builder;
+mod builtins;
+mod code_cache;
+mod commonjs;
+mod console;
+mod error;
+mod event_loop;
+mod heap;
+mod loader;
+mod module_type;
+mod modules;
+mod output;
+mod pool;
+mod render_cache;
+mod snapshot;
+mod source_map;
 mod ssr;
+mod stream;
+mod value;
+mod watchdog;
+#[cfg(feature = "web-apis")]
+mod web_apis;
+
+pub use builder::SsrBuilder;
+pub use console::{ConsoleMessage, LogLevel};
+pub use error::{JsError, SsrError};
+pub use loader::{FsModuleLoader, InMemoryModuleLoader, ModuleLoader};
+pub use module_type::ModuleType;
+pub use output::RenderOutput;
+pub use pool::SsrPool;
+pub use render_cache::{
+    CacheStats, CacheStore, InMemoryStore, MemoryRenderCache, RenderCache, StoreRenderCache,
+};
+pub use snapshot::Snapshot;
+pub use source_map::{OriginalLocation, SourceMap};
 pub use ssr::Ssr;
+pub use stream::{ChunkStream, RenderStream};
@@ -0,0 +1,205 @@
+use crate::error::SsrError;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Resolves and loads the modules imported by a bundle, statically (ES modules) or with
+/// `import()` (any bundle, e.g. lazily loaded chunks).
+///
+/// Modules are identified by their resolved name, which is also the resource name of their
+/// code in JS stack traces. The bundle given to [`Ssr::load`](crate::Ssr::load) is named
+/// `module.js`, so relative imports in it resolve from the root of the loader.
+///
+/// Each module is compiled and evaluated once per [`Ssr`](crate::Ssr) instance, however many
+/// modules import it.
+pub trait ModuleLoader {
+    /// Resolves `specifier`, imported by the module named `referrer`, to the name of a module.
+    fn resolve(&self, specifier: &str, referrer: &str) -> Result<String, SsrError>;
+
+    /// Returns the source of the module named `name`, as returned by [`ModuleLoader::resolve`].
+    fn load(&self, name: &str) -> Result<String, SsrError>;
+}
+
+/// Serves modules from memory, named by their path, e.g. `assets/chunk-a1b2.js`.
+///
+/// Relative specifiers (`./`, `../`) and absolute paths (`/`) are resolved against the name of
+/// the importing module. Other specifiers (e.g. `react`) must match the name of a module.
+///
+/// ```no_run
+/// use ssr_rs::{InMemoryModuleLoader, ModuleType, Ssr};
+///
+/// let loader = InMemoryModuleLoader::new()
+///     .with_module("lib/greet.js", "export const greet = (name) => `Hello ${name}`;");
+///
+/// let ssr = Ssr::builder()
+///     .module_loader(loader)
+///     .source(
+///         r#"import { greet } from "./lib/greet.js"; export const render = () => greet("world");"#,
+///         "render",
+///     )
+///     .module_type(ModuleType::Esm)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryModuleLoader {
+    modules: HashMap<String, String>,
+}
+
+impl InMemoryModuleLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the module `name` with its `source`, replacing any module with the same name.
+    pub fn insert(&mut self, name: &str, source: &str) {
+        let name = normalize(name).unwrap_or_else(|| name.to_string());
+        self.modules.insert(name, source.to_string());
+    }
+
+    /// Same as [`InMemoryModuleLoader::insert`], for chaining.
+    pub fn with_module(mut self, name: &str, source: &str) -> Self {
+        self.insert(name, source);
+        self
+    }
+}
+
+impl ModuleLoader for InMemoryModuleLoader {
+    fn resolve(&self, specifier: &str, referrer: &str) -> Result<String, SsrError> {
+        match resolve_path(specifier, referrer) {
+            Some(name) => Ok(name),
+            None if self.modules.contains_key(specifier) => Ok(specifier.to_string()),
+            None => Err(not_found(specifier, referrer)),
+        }
+    }
+
+    fn load(&self, name: &str) -> Result<String, SsrError> {
+        self.modules
+            .get(name)
+            .cloned()
+            .ok_or_else(|| SsrError::ModuleLoad(format!("Module not found: {name}")))
+    }
+}
+
+/// Serves modules from the files of a directory, e.g. the `dist/server` output of Vite.
+///
+/// Relative specifiers (`./`, `../`) and absolute paths (`/`, from the directory) are resolved
+/// against the importing module, and cannot point outside of the directory. Bare specifiers
+/// (e.g. `react`) are not supported: bundle the dependencies or wrap this loader.
+///
+/// ```no_run
+/// use ssr_rs::{FsModuleLoader, ModuleType, Ssr};
+/// use std::fs::read_to_string;
+///
+/// let ssr = Ssr::builder()
+///     .module_loader(FsModuleLoader::new("./dist/server"))
+///     .source(&read_to_string("./dist/server/entry-server.js").unwrap(), "render")
+///     .module_type(ModuleType::Esm)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct FsModuleLoader {
+    root: PathBuf,
+}
+
+impl FsModuleLoader {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FsModuleLoader { root: root.into() }
+    }
+}
+
+impl ModuleLoader for FsModuleLoader {
+    fn resolve(&self, specifier: &str, referrer: &str) -> Result<String, SsrError> {
+        resolve_path(specifier, referrer).ok_or_else(|| not_found(specifier, referrer))
+    }
+
+    fn load(&self, name: &str) -> Result<String, SsrError> {
+        let path = self.root.join(name);
+        fs::read_to_string(&path).map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => {
+                SsrError::ModuleLoad(format!("Module not found: {}", path.display()))
+            }
+            _ => SsrError::ModuleLoad(format!("Failed to read {}: {err}", path.display())),
+        })
+    }
+}
+
+fn not_found(specifier: &str, referrer: &str) -> SsrError {
+    SsrError::ModuleLoad(format!("Cannot resolve {specifier} from {referrer}"))
+}
+
+/// Resolves a relative or absolute path specifier against the module named `referrer`.
+/// Returns `None` for bare specifiers and paths going above the root.
+pub(crate) fn resolve_path(specifier: &str, referrer: &str) -> Option<String> {
+    if let Some(path) = specifier.strip_prefix('/') {
+        return normalize(path);
+    }
+    if !specifier.starts_with("./") && !specifier.starts_with("../") {
+        return None;
+    }
+
+    let directory = referrer
+        .rsplit_once('/')
+        .map_or("", |(directory, _)| directory);
+    normalize(&format!("{directory}/{specifier}"))
+}
+
+/// Removes the `.` and `..` segments and the empty ones from `path`.
+fn normalize(path: &str) -> Option<String> {
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Some(segments.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_path() {
+        assert_eq!(
+            resolve_path("./chunk.js", "module.js").as_deref(),
+            Some("chunk.js")
+        );
+        assert_eq!(
+            resolve_path("../lib/a.js", "assets/pages/index.js").as_deref(),
+            Some("assets/lib/a.js")
+        );
+        assert_eq!(
+            resolve_path("/assets/a.js", "pages/index.js").as_deref(),
+            Some("assets/a.js")
+        );
+        assert_eq!(resolve_path("react", "module.js"), None);
+        assert_eq!(resolve_path("../../a.js", "pages/index.js"), None);
+    }
+
+    #[test]
+    fn test_in_memory_loader() {
+        let loader = InMemoryModuleLoader::new()
+            .with_module("./lib/a.js", "export const a = 1;")
+            .with_module("react", "export default {};");
+
+        let name = loader.resolve("./a.js", "lib/b.js").unwrap();
+        assert_eq!(name, "lib/a.js");
+        assert_eq!(loader.load(&name).unwrap(), "export const a = 1;");
+        assert_eq!(loader.resolve("react", "lib/b.js").unwrap(), "react");
+        assert!(matches!(
+            loader.resolve("vue", "module.js"),
+            Err(SsrError::ModuleLoad(_))
+        ));
+        assert!(matches!(
+            loader.load("lib/c.js"),
+            Err(SsrError::ModuleLoad(_))
+        ));
+    }
+}
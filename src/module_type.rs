@@ -0,0 +1,112 @@
+use crate::error::SsrError;
+use std::fmt;
+use std::str::FromStr;
+
+/// The format of the bundle given to [`Ssr::load`](crate::Ssr::load).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ModuleType {
+    /// A CommonJS bundle, run with `module`, `exports` and a `require` backed by the
+    /// [`ModuleLoader`](crate::ModuleLoader). The entry point is read from `module.exports`, or
+    /// from the scope of the bundle (e.g. `var SSR = ...`); when empty, `module.exports` itself
+    /// is the entry point.
+    Cjs,
+    /// An ES module; the entry point is one of its named exports.
+    Esm,
+    /// A plain script (e.g. an IIFE) evaluated in the global scope; the entry point is an
+    /// expression evaluated after it, or the completion value of the script when empty.
+    Iife,
+    /// Picks [`ModuleType::Esm`] when the source contains top-level `import`/`export`
+    /// statements and [`ModuleType::Cjs`] otherwise. A source picked as CommonJS which fails to
+    /// compile as such is loaded as an ES module instead, e.g. a minified module whose statements
+    /// were not told apart.
+    #[default]
+    Auto,
+}
+
+impl ModuleType {
+    /// Resolves [`ModuleType::Auto`] by inspecting `source`; any other variant is returned as is.
+    pub fn resolve(self, source: &str) -> ModuleType {
+        match self {
+            ModuleType::Auto if Self::has_module_syntax(source) => ModuleType::Esm,
+            ModuleType::Auto => ModuleType::Cjs,
+            module_type => module_type,
+        }
+    }
+
+    /// Looks for an `import` or `export` statement starting a line or following the end of
+    /// another statement (`;` or `}`), as in minified bundles. Dynamic `import()` and
+    /// `import.meta` are not statements and are ignored.
+    fn has_module_syntax(source: &str) -> bool {
+        source.split(['\n', ';', '}']).map(str::trim_start).any(|line| {
+            ["import", "export"].iter().any(|keyword| {
+                line.strip_prefix(keyword).is_some_and(|rest| {
+                    matches!(
+                        rest.chars().next(),
+                        Some(' ' | '\t' | '{' | '*' | '"' | '\'')
+                    )
+                })
+            })
+        })
+    }
+}
+
+impl FromStr for ModuleType {
+    type Err = SsrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cjs" | "commonjs" => Ok(ModuleType::Cjs),
+            "esm" | "module" => Ok(ModuleType::Esm),
+            "iife" | "script" => Ok(ModuleType::Iife),
+            "auto" => Ok(ModuleType::Auto),
+            _ => Err(SsrError::UnsupportedModuleType(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ModuleType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ModuleType::Cjs => "cjs",
+            ModuleType::Esm => "esm",
+            ModuleType::Iife => "iife",
+            ModuleType::Auto => "auto",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let esm = "function render() {}\nexport { render };";
+        let esm_default = "  export default function () {}";
+        let import = "import { h } from 'preact';\nconst a = 1;";
+        let cjs = "var SSR = { x: () => import('./chunk.js') };";
+        let minified = "var a=()=>1;export{a as render}";
+        let minified_import = "import{h}from\"preact\";function r(){}export default r";
+        let cjs_text = "var SSR = { x: () => 'export it' };";
+
+        assert_eq!(ModuleType::Auto.resolve(esm), ModuleType::Esm);
+        assert_eq!(ModuleType::Auto.resolve(esm_default), ModuleType::Esm);
+        assert_eq!(ModuleType::Auto.resolve(import), ModuleType::Esm);
+        assert_eq!(ModuleType::Auto.resolve(cjs), ModuleType::Cjs);
+        assert_eq!(ModuleType::Auto.resolve(minified), ModuleType::Esm);
+        assert_eq!(ModuleType::Auto.resolve(minified_import), ModuleType::Esm);
+        assert_eq!(ModuleType::Auto.resolve(cjs_text), ModuleType::Cjs);
+        assert_eq!(ModuleType::Iife.resolve(esm), ModuleType::Iife);
+    }
+
+    #[test]
+    fn test_unsupported_module_type() {
+        assert_eq!("esm".parse::<ModuleType>(), Ok(ModuleType::Esm));
+        assert_eq!("script".parse::<ModuleType>(), Ok(ModuleType::Iife));
+        assert_eq!(
+            "amd".parse::<ModuleType>(),
+            Err(SsrError::UnsupportedModuleType("amd".to_string()))
+        );
+    }
+}
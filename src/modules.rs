@@ -0,0 +1,394 @@
+use crate::builtins;
+use crate::code_cache::{self, CodeCache};
+use crate::commonjs;
+use crate::error::{JsError, SsrError};
+use crate::loader::ModuleLoader;
+use crate::ssr::Ssr;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::rc::Rc;
+use v8::MapFnTo;
+
+/// A compiled module along with the name it was registered under.
+type NamedModule = (v8::Global<v8::Module>, String);
+
+/// The ES modules compiled by an instance, by resolved name.
+///
+/// The imports of a module are all loaded before it is instantiated, so that the errors of the
+/// [`ModuleLoader`] are reported as is; the resolve callback handed to V8 then only looks the
+/// modules up. Modules loaded with `import()` join the same map. Stored in an isolate slot so
+/// that the callbacks can reach it.
+pub(crate) struct ModuleMap {
+    loader: Option<Rc<dyn ModuleLoader>>,
+    /// Whether the Node built-in module shims can be imported.
+    node_builtins: bool,
+    modules: RefCell<HashMap<String, v8::Global<v8::Module>>>,
+    /// The names of the modules by identity hash, to know what their imports are relative to.
+    /// Identity hashes are not unique, so the modules sharing one are told apart by handle.
+    names: RefCell<HashMap<i32, Vec<NamedModule>>>,
+}
+
+impl ModuleMap {
+    pub(crate) fn new(loader: Option<Rc<dyn ModuleLoader>>, node_builtins: bool) -> Self {
+        ModuleMap {
+            loader,
+            node_builtins,
+            modules: RefCell::new(HashMap::new()),
+            names: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the module map of the isolate `scope` belongs to.
+    pub(crate) fn get(scope: &mut v8::HandleScope) -> Rc<ModuleMap> {
+        scope
+            .get_slot::<Rc<ModuleMap>>()
+            .cloned()
+            .expect("The module map is not installed")
+    }
+
+    /// Compiles the module `name` from `source`, along with every module it imports, directly
+    /// or not, which is not compiled yet. A module compiled before under the same name is
+    /// replaced.
+    pub(crate) fn compile<'s>(
+        &self,
+        scope: &mut v8::HandleScope<'s>,
+        source: &str,
+        name: &str,
+        origin: &v8::ScriptOrigin,
+    ) -> Result<v8::Local<'s, v8::Module>, SsrError> {
+        let module = Self::compile_module(scope, source, origin)?;
+        self.register(scope, name, module);
+
+        let mut pending = vec![(module, name.to_string())];
+        while let Some((module, referrer)) = pending.pop() {
+            let requests = module.get_module_requests();
+
+            for index in 0..requests.length() {
+                let Some(request) = requests
+                    .get(scope, index)
+                    .and_then(|request| v8::Local::<v8::ModuleRequest>::try_from(request).ok())
+                else {
+                    continue;
+                };
+                let specifier = request.get_specifier().to_rust_string_lossy(scope);
+                let resolved = self.resolve(&specifier, &referrer)?;
+                if self.modules.borrow().contains_key(&resolved) {
+                    continue;
+                }
+                if builtins::source(&resolved).is_some() {
+                    // Built-in modules import nothing.
+                    self.compile_builtin(scope, &resolved)?;
+                    continue;
+                }
+
+                let source = self.loader()?.load(&resolved)?;
+                let origin = Ssr::script_origin(scope, &resolved, true);
+                let module = Self::compile_module(scope, &source, &origin)?;
+                self.register(scope, &resolved, module);
+                pending.push((module, resolved));
+            }
+        }
+
+        Ok(module)
+    }
+
+    /// Returns the module `specifier` imported by the module named `referrer`, compiling it
+    /// along with its imports if it is not compiled yet.
+    pub(crate) fn import<'s>(
+        &self,
+        scope: &mut v8::HandleScope<'s>,
+        specifier: &str,
+        referrer: &str,
+    ) -> Result<v8::Local<'s, v8::Module>, SsrError> {
+        let name = self.resolve(specifier, referrer)?;
+        if let Some(module) = self.modules.borrow().get(&name) {
+            return Ok(v8::Local::new(scope, module));
+        }
+        if builtins::source(&name).is_some() {
+            return self.compile_builtin(scope, &name);
+        }
+
+        let source = self.loader()?.load(&name)?;
+        let origin = Ssr::script_origin(scope, &name, true);
+        self.compile(scope, &source, &name, &origin)
+    }
+
+    /// Returns the native callback of the functions returning the namespace of a dynamically
+    /// imported module, see [`crate::snapshot`].
+    pub(crate) fn callbacks() -> Vec<v8::FunctionCallback> {
+        vec![namespace_callback.map_fn_to()]
+    }
+
+    /// Returns the address of the evaluation steps of the built-in modules, which V8 serializes
+    /// along with them, see [`crate::snapshot`].
+    pub(crate) fn evaluation_steps() -> *mut c_void {
+        let steps: v8::SyntheticModuleEvaluationSteps = builtin_evaluation_steps.map_fn_to();
+        steps as *mut c_void
+    }
+
+    /// The callback given to [`v8::Module::instantiate_module`].
+    pub(crate) fn resolve_callback<'a>(
+        context: v8::Local<'a, v8::Context>,
+        specifier: v8::Local<'a, v8::String>,
+        _import_attributes: v8::Local<'a, v8::FixedArray>,
+        referrer: v8::Local<'a, v8::Module>,
+    ) -> Option<v8::Local<'a, v8::Module>> {
+        let scope = &mut unsafe { v8::CallbackScope::new(context) };
+        let modules = Self::get(scope);
+        let specifier = specifier.to_rust_string_lossy(scope);
+
+        let referrer = modules.name_of(referrer).unwrap_or_default();
+        let module = modules.resolve(&specifier, &referrer).and_then(|resolved| {
+            modules
+                .modules
+                .borrow()
+                .get(&resolved)
+                .map(|module| v8::Local::new(scope, module))
+                .ok_or_else(|| SsrError::ModuleLoad(format!("Module not loaded: {resolved}")))
+        });
+
+        match module {
+            Ok(module) => Some(module),
+            Err(err) => {
+                let msg = v8::String::new(scope, &err.to_string()).unwrap();
+                let error = v8::Exception::error(scope, msg);
+                scope.throw_exception(error);
+                None
+            }
+        }
+    }
+
+    /// The callback handling `import()`, returning a promise resolved with the namespace of the
+    /// module once it is evaluated.
+    pub(crate) fn dynamic_import_callback<'s>(
+        scope: &mut v8::HandleScope<'s>,
+        _host_defined_options: v8::Local<'s, v8::Data>,
+        resource_name: v8::Local<'s, v8::Value>,
+        specifier: v8::Local<'s, v8::String>,
+        _import_attributes: v8::Local<'s, v8::FixedArray>,
+    ) -> Option<v8::Local<'s, v8::Promise>> {
+        let specifier = specifier.to_rust_string_lossy(scope);
+        let referrer = resource_name.to_rust_string_lossy(scope);
+        // The module, its namespace and the promise all live in this scope, the promise being
+        // the only handle escaping it.
+        let scope = &mut v8::EscapableHandleScope::new(scope);
+        let promise = {
+            let scope = &mut v8::TryCatch::new(scope);
+            Self::import_dynamic(scope.as_mut(), &specifier, &referrer)
+        }?;
+        Some(scope.escape(promise))
+    }
+
+    fn import_dynamic<'s>(
+        scope: &mut v8::TryCatch<'_, v8::HandleScope<'s>>,
+        specifier: &str,
+        referrer: &str,
+    ) -> Option<v8::Local<'s, v8::Promise>> {
+        let module = match Self::get(scope).import(scope, specifier, referrer) {
+            Ok(module) => module,
+            Err(err) => {
+                let msg = v8::String::new(scope, &err.to_string()).unwrap();
+                let error = v8::Exception::error(scope, msg);
+                return Self::settled(scope, error, false);
+            }
+        };
+
+        let evaluated = module
+            .instantiate_module(scope, Self::resolve_callback)
+            .and_then(|_| module.evaluate(scope));
+        let Some(evaluated) = evaluated else {
+            // Without an exception, the execution is being terminated.
+            let exception = scope.exception()?;
+            return Self::settled(scope, exception, false);
+        };
+
+        let namespace = v8::Local::new(scope, module.get_module_namespace());
+        match v8::Local::<v8::Promise>::try_from(evaluated) {
+            // Settled once the top-level `await`s of the module graph are done.
+            Ok(evaluated) => {
+                let namespace = v8::Function::builder(namespace_callback)
+                    .data(namespace)
+                    .build(scope)?;
+                evaluated.then(scope, namespace)
+            }
+            Err(_) => Self::settled(scope, namespace, true),
+        }
+    }
+
+    fn settled<'s>(
+        scope: &mut v8::HandleScope<'s>,
+        value: v8::Local<v8::Value>,
+        fulfilled: bool,
+    ) -> Option<v8::Local<'s, v8::Promise>> {
+        let resolver = v8::PromiseResolver::new(scope)?;
+        if fulfilled {
+            resolver.resolve(scope, value)?;
+        } else {
+            resolver.reject(scope, value)?;
+        }
+        Some(resolver.get_promise(scope))
+    }
+
+    pub(crate) fn resolve(&self, specifier: &str, referrer: &str) -> Result<String, SsrError> {
+        if let Some(name) = builtins::resolve(specifier).filter(|_| self.node_builtins) {
+            return Ok(name);
+        }
+        self.loader()?.resolve(specifier, referrer)
+    }
+
+    pub(crate) fn loader(&self) -> Result<&dyn ModuleLoader, SsrError> {
+        self.loader.as_deref().ok_or_else(|| {
+            SsrError::ModuleLoad(
+                "The bundle imports modules but no module loader is set".to_string(),
+            )
+        })
+    }
+
+    fn register(&self, scope: &mut v8::HandleScope, name: &str, module: v8::Local<v8::Module>) {
+        let module = v8::Global::new(scope, module);
+        let mut names = self.names.borrow_mut();
+        if let Some(replaced) = self
+            .modules
+            .borrow_mut()
+            .insert(name.to_string(), module.clone())
+        {
+            let hash = v8::Local::new(scope, &replaced).get_identity_hash().get();
+            if let Some(modules) = names.get_mut(&hash) {
+                modules.retain(|(module, _)| *module != replaced);
+            }
+        }
+
+        let hash = v8::Local::new(scope, &module).get_identity_hash().get();
+        names
+            .entry(hash)
+            .or_default()
+            .push((module, name.to_string()));
+    }
+
+    /// Returns the name `module` was registered under.
+    fn name_of(&self, module: v8::Local<v8::Module>) -> Option<String> {
+        self.names
+            .borrow()
+            .get(&module.get_identity_hash().get())?
+            .iter()
+            .find(|(registered, _)| *registered == module)
+            .map(|(_, name)| name.clone())
+    }
+
+    /// Creates the module exposing the exports of the built-in module `name`: its named exports
+    /// are the properties of `module.exports`, which is also the default export.
+    fn compile_builtin<'s>(
+        &self,
+        scope: &mut v8::HandleScope<'s>,
+        name: &str,
+    ) -> Result<v8::Local<'s, v8::Module>, SsrError> {
+        let exports = {
+            let scope = &mut v8::TryCatch::new(scope);
+            commonjs::require_builtin(scope, name).ok_or_else(|| {
+                SsrError::Evaluation(JsError::from_try_catch(
+                    scope,
+                    &format!("Failed to evaluate {name}"),
+                ))
+            })?
+        };
+
+        let export_names: Vec<_> = builtin_exports(scope, exports)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        let module_name = v8::String::new(scope, name).unwrap();
+        let module = v8::Module::create_synthetic_module(
+            scope,
+            module_name,
+            &export_names,
+            builtin_evaluation_steps,
+        );
+        self.register(scope, name, module);
+        Ok(module)
+    }
+
+    fn compile_module<'s>(
+        scope: &mut v8::HandleScope<'s>,
+        source: &str,
+        origin: &v8::ScriptOrigin,
+    ) -> Result<v8::Local<'s, v8::Module>, SsrError> {
+        let scope = &mut v8::TryCatch::new(scope);
+        let string = v8::String::new(scope, source)
+            .ok_or_else(|| SsrError::Compile(JsError::new("Failed to create V8 string")))?;
+        let entry = CodeCache::entry(scope, source);
+        let (mut compiled, options) = code_cache::source(entry.as_ref(), string, origin);
+        let module = v8::script_compiler::compile_module2(
+            scope,
+            &mut compiled,
+            options,
+            v8::script_compiler::NoCacheReason::NoReason,
+        )
+        .ok_or_else(|| {
+            SsrError::Compile(JsError::from_try_catch(scope, "Failed to compile module"))
+        })?;
+
+        if let Some(entry) = entry {
+            entry.update(&compiled, || {
+                module.get_unbound_module_script(scope).create_code_cache()
+            });
+        }
+        Ok(module)
+    }
+}
+
+/// Returns the module namespace given as data, once the evaluation promise is fulfilled.
+fn namespace_callback(
+    _scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    rv.set(args.data());
+}
+
+/// Sets the exports of a built-in module when it is evaluated.
+fn builtin_evaluation_steps<'a>(
+    context: v8::Local<'a, v8::Context>,
+    module: v8::Local<'a, v8::Module>,
+) -> Option<v8::Local<'a, v8::Value>> {
+    let scope = &mut unsafe { v8::CallbackScope::new(context) };
+    let name = ModuleMap::get(scope).name_of(module)?;
+    let exports = commonjs::require_builtin(scope, &name)?;
+
+    for (name, value) in builtin_exports(scope, exports) {
+        module.set_synthetic_module_export(scope, name, value)?;
+    }
+    Some(v8::undefined(scope).into())
+}
+
+/// The exports of the ES module wrapping a built-in module: `default` for `exports`, and its
+/// properties.
+fn builtin_exports<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    exports: v8::Local<'s, v8::Value>,
+) -> Vec<(v8::Local<'s, v8::String>, v8::Local<'s, v8::Value>)> {
+    let default = v8::String::new(scope, "default").unwrap();
+    let mut entries = vec![(default, exports)];
+
+    let Some(object) = exports.to_object(scope) else {
+        return entries;
+    };
+    let Some(keys) = object.get_own_property_names(scope, Default::default()) else {
+        return entries;
+    };
+    for index in 0..keys.length() {
+        let Some(key) = keys
+            .get_index(scope, index)
+            .and_then(|key| key.to_string(scope))
+        else {
+            continue;
+        };
+        if key.to_rust_string_lossy(scope) == "default" {
+            continue;
+        }
+        if let Some(value) = object.get(scope, key.into()) {
+            entries.push((key, value));
+        }
+    }
+    entries
+}
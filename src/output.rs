@@ -0,0 +1,309 @@
+use crate::error::SsrError;
+use serde_json::Value;
+use std::time::Duration;
+
+const DEFAULT_STATUS: u16 = 200;
+const DEFAULT_REDIRECT_STATUS: u16 = 302;
+
+/// The structured result of a render, filled from the object returned by the render function.
+///
+/// ```javascript
+/// export function render(props) {
+///   return {
+///     html: "<div>...</div>",        // or `body`
+///     head: "<title>Home</title>",
+///     css: ".a{}",                   // or Svelte's `{ code }` object
+///     status: 404,                   // or `statusCode`
+///     headers: { "cache-control": "no-store" },
+///     redirect: "/login",            // or `{ location: "/login", status: 301 }`
+///     cacheable: false,              // keeps the result out of the render cache
+///     maxAge: 60,                    // or caches it for 60 seconds at most
+///   };
+/// }
+/// ```
+///
+/// A render function returning a plain string fills [`RenderOutput::html`] only.
+///
+/// The render functions called by [`Ssr::render_to_string`](crate::Ssr::render_to_string) and
+/// the other cached renders may also return such an object (with an `html` property): its HTML
+/// is rendered, and its `cacheable` and `maxAge` hints apply to the render cache. These renders
+/// read nothing else from the object, and ignore the hints which are not a boolean and a
+/// non-negative number of seconds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderOutput {
+    pub html: String,
+    pub head: String,
+    pub css: String,
+    /// The HTTP status code, `200` unless set by the render function (`302` for redirects).
+    pub status: u16,
+    /// The response headers. Declare them as an array of `[name, value]` pairs to keep their
+    /// order or repeat a header.
+    pub headers: Vec<(String, String)>,
+    /// The location to redirect to, if any.
+    pub redirect: Option<String>,
+    /// Whether the result may be cached, `true` unless set by the render function.
+    pub cacheable: bool,
+    /// How long the result may be cached, if limited by the render function.
+    pub max_age: Option<Duration>,
+}
+
+impl Default for RenderOutput {
+    fn default() -> Self {
+        RenderOutput {
+            html: String::new(),
+            head: String::new(),
+            css: String::new(),
+            status: DEFAULT_STATUS,
+            headers: Vec::new(),
+            redirect: None,
+            cacheable: true,
+            max_age: None,
+        }
+    }
+}
+
+impl RenderOutput {
+    /// Returns `true` when the render asked for a redirect.
+    pub fn is_redirect(&self) -> bool {
+        self.redirect.is_some()
+    }
+
+    pub(crate) fn from_json(value: Value) -> Result<Self, SsrError> {
+        let mut object = match value {
+            Value::String(html) => {
+                return Ok(RenderOutput {
+                    html,
+                    ..Default::default()
+                })
+            }
+            Value::Object(object) => object,
+            other => {
+                return Err(Self::invalid(format!(
+                    "expected a string or an object, got {other}"
+                )))
+            }
+        };
+
+        let mut output = RenderOutput::default();
+
+        if let Some(html) = object.remove("html").or_else(|| object.remove("body")) {
+            output.html = Self::string_field("html", html)?;
+        }
+        if let Some(head) = object.remove("head") {
+            output.head = Self::string_field("head", head)?;
+        }
+        if let Some(css) = object.remove("css") {
+            output.css = match css {
+                Value::Object(mut css) => {
+                    Self::string_field("css", css.remove("code").unwrap_or(Value::Null))?
+                }
+                css => Self::string_field("css", css)?,
+            };
+        }
+        if let Some(headers) = object.remove("headers") {
+            output.headers = Self::headers_field(headers)?;
+        }
+
+        let mut redirect_status = None;
+        match object.remove("redirect") {
+            None | Some(Value::Null) => {}
+            Some(Value::String(location)) => output.redirect = Some(location),
+            Some(Value::Object(mut redirect)) => {
+                output.redirect = Some(Self::string_field(
+                    "redirect.location",
+                    redirect.remove("location").unwrap_or(Value::Null),
+                )?);
+                redirect_status = redirect
+                    .remove("status")
+                    .map(Self::status_field)
+                    .transpose()?;
+            }
+            Some(other) => return Err(Self::invalid(format!("invalid redirect {other}"))),
+        }
+
+        match object.remove("cacheable") {
+            None | Some(Value::Null) => {}
+            Some(Value::Bool(cacheable)) => output.cacheable = cacheable,
+            Some(other) => {
+                return Err(Self::invalid(format!(
+                    "cacheable must be a boolean, got {other}"
+                )))
+            }
+        }
+        output.max_age = object
+            .remove("maxAge")
+            .filter(|max_age| !max_age.is_null())
+            .map(Self::max_age_field)
+            .transpose()?;
+
+        let status = object
+            .remove("status")
+            .or_else(|| object.remove("statusCode"))
+            .filter(|status| !status.is_null())
+            .map(Self::status_field)
+            .transpose()?;
+
+        output.status = match (redirect_status.or(status), &output.redirect) {
+            (Some(status), _) => status,
+            (None, Some(_)) => DEFAULT_REDIRECT_STATUS,
+            (None, None) => DEFAULT_STATUS,
+        };
+
+        Ok(output)
+    }
+
+    fn string_field(name: &str, value: Value) -> Result<String, SsrError> {
+        match value {
+            Value::String(value) => Ok(value),
+            Value::Null => Ok(String::new()),
+            other => Err(Self::invalid(format!(
+                "{name} must be a string, got {other}"
+            ))),
+        }
+    }
+
+    fn status_field(value: Value) -> Result<u16, SsrError> {
+        value
+            .as_u64()
+            .and_then(|status| u16::try_from(status).ok())
+            .filter(|status| (100..=999).contains(status))
+            .ok_or_else(|| Self::invalid(format!("invalid status {value}")))
+    }
+
+    /// Reads a number of seconds.
+    fn max_age_field(value: Value) -> Result<Duration, SsrError> {
+        value
+            .as_f64()
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            .ok_or_else(|| Self::invalid(format!("invalid maxAge {value}")))
+    }
+
+    fn headers_field(value: Value) -> Result<Vec<(String, String)>, SsrError> {
+        let header_value = |name: &str, value: Value| match value {
+            Value::String(value) => Ok(value),
+            Value::Number(value) => Ok(value.to_string()),
+            other => Err(Self::invalid(format!(
+                "header {name} must be a string, got {other}"
+            ))),
+        };
+
+        match value {
+            Value::Null => Ok(Vec::new()),
+            Value::Object(headers) => headers
+                .into_iter()
+                .map(|(name, value)| Ok((name.clone(), header_value(&name, value)?)))
+                .collect(),
+            // `[["set-cookie", "a=1"], ["set-cookie", "b=2"]]` for repeated headers.
+            Value::Array(headers) => headers
+                .into_iter()
+                .map(|pair| match pair {
+                    Value::Array(mut pair) if pair.len() == 2 => {
+                        let value = pair.pop().unwrap();
+                        let name = Self::string_field("header name", pair.pop().unwrap())?;
+                        let value = header_value(&name, value)?;
+                        Ok((name, value))
+                    }
+                    other => Err(Self::invalid(format!("invalid header {other}"))),
+                })
+                .collect(),
+            other => Err(Self::invalid(format!("invalid headers {other}"))),
+        }
+    }
+
+    fn invalid(msg: String) -> SsrError {
+        SsrError::InvalidOutput(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_string() {
+        let output = RenderOutput::from_json(json!("<div></div>")).unwrap();
+
+        assert_eq!(output.html, "<div></div>");
+        assert_eq!(output.status, 200);
+        assert!(!output.is_redirect());
+    }
+
+    #[test]
+    fn test_from_object() {
+        let output = RenderOutput::from_json(json!({
+            "body": "<div></div>",
+            "head": "<title>Not found</title>",
+            "css": { "code": ".a{}" },
+            "statusCode": 404,
+            "headers": { "cache-control": "no-store", "x-count": 1 },
+        }))
+        .unwrap();
+
+        assert_eq!(
+            output,
+            RenderOutput {
+                html: "<div></div>".to_string(),
+                head: "<title>Not found</title>".to_string(),
+                css: ".a{}".to_string(),
+                status: 404,
+                headers: vec![
+                    ("cache-control".to_string(), "no-store".to_string()),
+                    ("x-count".to_string(), "1".to_string()),
+                ],
+                redirect: None,
+                cacheable: true,
+                max_age: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_cache_hints() {
+        let output =
+            RenderOutput::from_json(json!({ "html": "<div></div>", "maxAge": 1.5 })).unwrap();
+        assert!(output.cacheable);
+        assert_eq!(output.max_age, Some(Duration::from_millis(1500)));
+
+        let output = RenderOutput::from_json(json!({ "cacheable": false })).unwrap();
+        assert!(!output.cacheable);
+        assert_eq!(output.max_age, None);
+
+        assert!(matches!(
+            RenderOutput::from_json(json!({ "maxAge": -1 })),
+            Err(SsrError::InvalidOutput(_))
+        ));
+        assert!(matches!(
+            RenderOutput::from_json(json!({ "cacheable": "no" })),
+            Err(SsrError::InvalidOutput(_))
+        ));
+    }
+
+    #[test]
+    fn test_redirect() {
+        let output = RenderOutput::from_json(json!({ "redirect": "/login" })).unwrap();
+        assert_eq!(output.redirect.as_deref(), Some("/login"));
+        assert_eq!(output.status, 302);
+
+        let output = RenderOutput::from_json(json!({
+            "redirect": { "location": "/new", "status": 301 },
+            "headers": [["set-cookie", "a=1"], ["set-cookie", "b=2"]],
+        }))
+        .unwrap();
+        assert_eq!(output.redirect.as_deref(), Some("/new"));
+        assert_eq!(output.status, 301);
+        assert_eq!(output.headers.len(), 2);
+    }
+
+    #[test]
+    fn test_invalid_output() {
+        assert!(matches!(
+            RenderOutput::from_json(json!(42)),
+            Err(SsrError::InvalidOutput(_))
+        ));
+        assert!(matches!(
+            RenderOutput::from_json(json!({ "status": 42 })),
+            Err(SsrError::InvalidOutput(_))
+        ));
+    }
+}
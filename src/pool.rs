@@ -0,0 +1,326 @@
+use crate::error::SsrError;
+use crate::ssr::Ssr;
+use crate::stream::{ChunkSender, ChunkStream};
+use bytes::Bytes;
+use serde::Serialize;
+use std::num::NonZeroUsize;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+use std::thread;
+use tokio::sync::oneshot;
+
+type Job = Box<dyn FnOnce(&mut Worker) + Send>;
+type Factory = dyn Fn() -> Result<Ssr, SsrError> + Send + Sync;
+
+/// What a worker thread owns: its instance and the runtime driving its async renders.
+struct Worker {
+    ssr: Ssr,
+    runtime: tokio::runtime::Runtime,
+}
+
+/// A pool of worker threads, each owning an [`Ssr`] instance created by a factory.
+///
+/// [`Ssr`] is bound to the thread that created it. The pool hides that behind a handle that
+/// is `Clone + Send + Sync`, so it can be shared as application state: each render is handed
+/// to the next free worker and awaited without blocking the caller.
+///
+/// ```no_run
+/// use ssr_rs::{Ssr, SsrPool};
+/// use std::fs::read_to_string;
+/// use std::num::NonZeroUsize;
+///
+/// # async fn run() {
+/// let source = read_to_string("./path/to/build.js").unwrap();
+///
+/// let pool = SsrPool::new(NonZeroUsize::new(4).unwrap(), move || {
+///     Ssr::builder().source(&source, "SSR").build()
+/// })
+/// .unwrap();
+///
+/// let html = pool.render(None).await.unwrap();
+/// # }
+/// ```
+///
+/// A worker whose instance reached its heap limit (see [`Ssr::needs_recycle`]) or panicked
+/// replaces it with a new one from the factory. The workers stop once every handle is dropped.
+#[derive(Clone)]
+pub struct SsrPool {
+    jobs: mpsc::Sender<Job>,
+    size: usize,
+}
+
+impl SsrPool {
+    /// Starts `size` workers, each creating its instance with `factory`, and waits for them to
+    /// be ready. Fails with the first error returned by `factory`.
+    pub fn new<F>(size: NonZeroUsize, factory: F) -> Result<Self, SsrError>
+    where
+        F: Fn() -> Result<Ssr, SsrError> + Send + Sync + 'static,
+    {
+        let factory: Arc<Factory> = Arc::new(factory);
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let (ready, started) = mpsc::channel();
+
+        for index in 0..size.get() {
+            let factory = factory.clone();
+            let receiver = receiver.clone();
+            let ready = ready.clone();
+
+            thread::Builder::new()
+                .name(format!("ssr-worker-{index}"))
+                .spawn(move || Self::work(&*factory, &receiver, ready))
+                .map_err(|_| SsrError::WorkerUnavailable)?;
+        }
+        drop(ready);
+
+        for _ in 0..size.get() {
+            started.recv().map_err(|_| SsrError::WorkerUnavailable)??;
+        }
+
+        Ok(SsrPool {
+            jobs,
+            size: size.get(),
+        })
+    }
+
+    /// Returns the number of workers.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Renders with [`Ssr::render`] on the next free worker.
+    pub async fn render(&self, params: Option<&str>) -> Result<String, SsrError> {
+        let params = params.map(str::to_string);
+        self.dispatch(move |worker| {
+            worker
+                .runtime
+                .block_on(worker.ssr.render(params.as_deref()))
+        })
+        .await?
+    }
+
+    /// Renders with [`Ssr::render_with_async`] on the next free worker.
+    pub async fn render_with<P: Serialize + ?Sized>(&self, props: &P) -> Result<String, SsrError> {
+        let props =
+            serde_json::to_value(props).map_err(|err| SsrError::Serialization(err.to_string()))?;
+        self.dispatch(move |worker| {
+            worker
+                .runtime
+                .block_on(worker.ssr.render_with_async(&props))
+        })
+        .await?
+    }
+
+    /// Renders with [`Ssr::render_to_stream`] on the next free worker, which sends the chunks
+    /// as they are produced, see [`ChunkStream`].
+    ///
+    /// Errors, including [`SsrError::WorkerUnavailable`] when the job panics or no worker is
+    /// left, are the last item of the stream.
+    pub fn render_to_stream(&self, params: Option<&str>) -> ChunkStream {
+        let (sender, stream) = ChunkStream::channel();
+        let params = params.map(str::to_string);
+
+        let chunks = sender.clone();
+        let job: Job = Box::new(move |worker| {
+            let forwarded = panic::catch_unwind(AssertUnwindSafe(|| {
+                Self::forward(&worker.ssr, params.as_deref(), &chunks)
+            }));
+            if let Err(panic) = forwarded {
+                let _ = chunks.blocking_send(Err(SsrError::WorkerUnavailable));
+                // Let the worker replace its instance.
+                panic::resume_unwind(panic);
+            }
+        });
+        if self.jobs.send(job).is_err() {
+            let _ = sender.try_send(Err(SsrError::WorkerUnavailable));
+        }
+        stream
+    }
+
+    fn forward(ssr: &Ssr, params: Option<&str>, chunks: &ChunkSender) {
+        let stream = match ssr.render_to_stream(params) {
+            Ok(stream) => stream,
+            Err(err) => {
+                let _ = chunks.blocking_send(Err(err));
+                return;
+            }
+        };
+        for chunk in stream {
+            // The consumer dropped the stream, the rest of the render is not needed.
+            if chunks.blocking_send(chunk.map(Bytes::from)).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Runs `f` with the instance of the next free worker, e.g. to call another render method.
+    ///
+    /// Fails with [`SsrError::WorkerUnavailable`] if `f` panics.
+    pub async fn run<F, R>(&self, f: F) -> Result<R, SsrError>
+    where
+        F: FnOnce(&Ssr) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.dispatch(move |worker| f(&worker.ssr)).await
+    }
+
+    async fn dispatch<F, R>(&self, job: F) -> Result<R, SsrError>
+    where
+        F: FnOnce(&mut Worker) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (reply, result) = oneshot::channel();
+        self.jobs
+            .send(Box::new(move |worker| {
+                let _ = reply.send(job(worker));
+            }))
+            .map_err(|_| SsrError::WorkerUnavailable)?;
+
+        // The reply is dropped without being sent when the job panics.
+        result.await.map_err(|_| SsrError::WorkerUnavailable)
+    }
+
+    fn work(
+        factory: &Factory,
+        receiver: &Mutex<mpsc::Receiver<Job>>,
+        ready: mpsc::Sender<Result<(), SsrError>>,
+    ) {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .expect("Failed to create the worker runtime");
+
+        let mut worker = match factory() {
+            Ok(ssr) => Worker { ssr, runtime },
+            Err(err) => {
+                let _ = ready.send(Err(err));
+                return;
+            }
+        };
+        let _ = ready.send(Ok(()));
+
+        loop {
+            // The lock is released once a job is received so that the next free worker can wait
+            // for the following one.
+            let job = receiver
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .recv();
+            let Ok(job) = job else {
+                // Every pool handle was dropped.
+                return;
+            };
+
+            let panicked = panic::catch_unwind(AssertUnwindSafe(|| job(&mut worker))).is_err();
+
+            if panicked || worker.ssr.needs_recycle() {
+                // Keep the current instance if a new one cannot be created.
+                if let Ok(ssr) = factory() {
+                    worker.ssr = ssr;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module_type::ModuleType;
+    use futures_core::Stream;
+
+    fn create_pool(size: usize) -> SsrPool {
+        SsrPool::new(NonZeroUsize::new(size).unwrap(), || {
+            Ssr::builder()
+                .source(
+                    r##"var SSR = { render: (props) => `<p>${props ?? "hello"}</p>` };"##,
+                    "SSR",
+                )
+                .module_type(ModuleType::Cjs)
+                .build()
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_pool_render() {
+        let pool = create_pool(2);
+        assert_eq!(pool.size(), 2);
+
+        let renders: Vec<_> = (0..8)
+            .map(|i| {
+                let pool = pool.clone();
+                tokio::spawn(async move { pool.render(Some(&i.to_string())).await })
+            })
+            .collect();
+        for (i, render) in renders.into_iter().enumerate() {
+            assert_eq!(render.await.unwrap().unwrap(), format!("<p>{i}</p>"));
+        }
+
+        assert_eq!(
+            pool.render_with(&serde_json::json!("props")).await.unwrap(),
+            "<p>props</p>"
+        );
+        assert_eq!(
+            pool.run(|ssr| ssr.exports()).await.unwrap(),
+            vec!["render".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pool_worker_panic() {
+        let pool = create_pool(1);
+
+        let result: Result<(), _> = pool.run(|_| panic!("boom")).await;
+        assert_eq!(result, Err(SsrError::WorkerUnavailable));
+        assert_eq!(pool.render(None).await.unwrap(), "<p>hello</p>");
+    }
+
+    #[tokio::test]
+    async fn test_pool_render_to_stream() {
+        let pool = SsrPool::new(NonZeroUsize::new(1).unwrap(), || {
+            Ssr::builder()
+                .source(
+                    r##"var SSR = {
+                        render: async (props, write) => {
+                            write("<p>");
+                            await new Promise((resolve) => setTimeout(resolve, 1));
+                            write(props);
+                            write("</p>");
+                        },
+                    };"##,
+                    "SSR",
+                )
+                .module_type(ModuleType::Cjs)
+                .build()
+        })
+        .unwrap();
+
+        let mut stream = pool.render_to_stream(Some("hello"));
+        let mut chunks = Vec::new();
+        while let Some(chunk) =
+            std::future::poll_fn(|cx| std::pin::Pin::new(&mut stream).poll_next(cx)).await
+        {
+            chunks.push(chunk.unwrap());
+        }
+        assert_eq!(chunks, vec!["<p>", "hello", "</p>"]);
+
+        let result: Result<(), _> = pool.run(|_| panic!("boom")).await;
+        assert_eq!(result, Err(SsrError::WorkerUnavailable));
+        let mut stream = pool.render_to_stream(None);
+        let chunk = std::future::poll_fn(|cx| std::pin::Pin::new(&mut stream).poll_next(cx)).await;
+        assert_eq!(chunk.unwrap().unwrap(), "<p>");
+    }
+
+    #[test]
+    fn test_pool_factory_error() {
+        let pool = SsrPool::new(NonZeroUsize::new(2).unwrap(), || {
+            Ssr::builder()
+                .source("var SSR = {", "SSR")
+                .module_type(ModuleType::Cjs)
+                .build()
+        });
+
+        assert!(matches!(pool, Err(SsrError::Compile(_))));
+    }
+}
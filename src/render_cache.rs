@@ -0,0 +1,689 @@
+use lru::LruCache;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// The number of rendered results kept by default.
+const DEFAULT_MAX_ENTRIES: usize = 100;
+
+/// The prefix of the keys written by a [`StoreRenderCache`] by default.
+const DEFAULT_NAMESPACE: &str = "ssr:";
+
+/// Derives the key of the props in the render cache, see
+/// [`SsrBuilder::cache_key`](crate::SsrBuilder::cache_key).
+pub(crate) type CacheKeyFn = Rc<dyn Fn(&Value) -> Option<String>>;
+
+/// Where an [`Ssr`](crate::Ssr) instance caches its rendered results, see
+/// [`SsrBuilder::render_cache_backend`](crate::SsrBuilder::render_cache_backend).
+///
+/// A result is cached with a cache key made of the key of the props it was rendered for and of
+/// the render function(s) called, as the same props rendered by different functions give
+/// different results. The cache key starts with the props key followed by `\0`.
+///
+/// Implementations are shared between threads, e.g. by the instances of a
+/// [`SsrPool`](crate::SsrPool), so that a page rendered by one of them is cached for all.
+pub trait RenderCache: Send + Sync {
+    /// Returns the result cached for `cache_key`, if any.
+    fn get(&self, cache_key: &str) -> Option<String>;
+
+    /// Caches `rendered` for `cache_key`, rendered for the props `key`.
+    ///
+    /// `max_age` is set when the render function limited how long its result may be cached
+    /// (see [`RenderOutput::max_age`](crate::RenderOutput::max_age)): it must not be served
+    /// afterwards.
+    fn insert(&self, cache_key: &str, key: &str, rendered: &str, max_age: Option<Duration>);
+
+    /// Removes the results cached for the props `key`, whatever the render function, see
+    /// [`Ssr::invalidate`](crate::Ssr::invalidate).
+    fn invalidate(&self, key: &str);
+
+    /// Removes all the cached results.
+    fn invalidate_all(&self);
+
+    /// Returns the statistics of the cache. The default implementation returns all zeros.
+    fn stats(&self) -> CacheStats {
+        CacheStats::default()
+    }
+}
+
+/// The statistics of a render cache, see [`Ssr::cache_stats`](crate::Ssr::cache_stats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The renders served from the cache.
+    pub hits: u64,
+    /// The renders not found in the cache (or found expired).
+    pub misses: u64,
+    /// The results dropped to stay within the bounds of the cache, or because they expired.
+    pub evictions: u64,
+    /// The number of cached results.
+    pub entries: usize,
+    /// The size of the cached results and of their keys, in bytes.
+    pub bytes: usize,
+}
+
+/// Returns the JSON serialization of `props` with the object keys sorted and without the
+/// properties named after one of `ignored`, at any depth: semantically equal props give the
+/// same key. See [`SsrBuilder::cache_key_ignoring`](crate::SsrBuilder::cache_key_ignoring).
+pub(crate) fn canonical_key(props: &Value, ignored: &[String]) -> String {
+    let mut key = String::new();
+    write_canonical(props, ignored, &mut key);
+    key
+}
+
+fn write_canonical(value: &Value, ignored: &[String], key: &mut String) {
+    match value {
+        Value::Array(values) => {
+            key.push('[');
+            for (index, value) in values.iter().enumerate() {
+                if index > 0 {
+                    key.push(',');
+                }
+                write_canonical(value, ignored, key);
+            }
+            key.push(']');
+        }
+        Value::Object(object) => {
+            let mut entries: Vec<_> = object
+                .iter()
+                .filter(|(name, _)| !ignored.contains(name))
+                .collect();
+            entries.sort_by_key(|(name, _)| *name);
+
+            key.push('{');
+            for (index, (name, value)) in entries.into_iter().enumerate() {
+                if index > 0 {
+                    key.push(',');
+                }
+                key.push_str(&Value::from(name.as_str()).to_string());
+                key.push(':');
+                write_canonical(value, ignored, key);
+            }
+            key.push('}');
+        }
+        scalar => key.push_str(&scalar.to_string()),
+    }
+}
+
+/// The bounds of a [`MemoryRenderCache`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CacheLimits {
+    pub(crate) max_entries: NonZeroUsize,
+    pub(crate) max_bytes: Option<usize>,
+    pub(crate) ttl: Option<Duration>,
+}
+
+impl Default for CacheLimits {
+    fn default() -> Self {
+        CacheLimits {
+            max_entries: NonZeroUsize::new(DEFAULT_MAX_ENTRIES).unwrap(),
+            max_bytes: None,
+            ttl: None,
+        }
+    }
+}
+
+/// An in-process [`RenderCache`], dropping the least recently used results past its bounds.
+///
+/// This is the cache of an instance unless [`SsrBuilder::render_cache_backend`] is used. Share
+/// one between instances to render each page once for all of them:
+///
+/// ```no_run
+/// use ssr_rs::{MemoryRenderCache, Ssr, SsrPool};
+/// use std::num::NonZeroUsize;
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let cache = Arc::new(
+///     MemoryRenderCache::new(NonZeroUsize::new(1000).unwrap()).ttl(Duration::from_secs(60)),
+/// );
+///
+/// let pool = SsrPool::new(NonZeroUsize::new(4).unwrap(), move || {
+///     Ssr::builder()
+///         .render_cache_backend(cache.clone())
+///         .source("var SSR = {x: () => '<p></p>'};", "SSR")
+///         .build()
+/// })
+/// .unwrap();
+/// ```
+///
+/// [`SsrBuilder::render_cache_backend`]: crate::SsrBuilder::render_cache_backend
+pub struct MemoryRenderCache {
+    entries: Mutex<Entries>,
+}
+
+impl Default for MemoryRenderCache {
+    fn default() -> Self {
+        Self::with_limits(CacheLimits::default())
+    }
+}
+
+impl MemoryRenderCache {
+    /// Creates a cache keeping up to `max_entries` results.
+    pub fn new(max_entries: NonZeroUsize) -> Self {
+        Self::with_limits(CacheLimits {
+            max_entries,
+            ..Default::default()
+        })
+    }
+
+    /// Limits the total size of the cached results. A result larger than `max_bytes` on its own
+    /// is not cached.
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.entries_mut().limits.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Makes the results expire `ttl` after they were cached.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.entries_mut().limits.ttl = Some(ttl);
+        self
+    }
+
+    pub(crate) fn with_limits(limits: CacheLimits) -> Self {
+        MemoryRenderCache {
+            entries: Mutex::new(Entries::new(limits)),
+        }
+    }
+
+    fn entries_mut(&mut self) -> &mut Entries {
+        self.entries
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl RenderCache for MemoryRenderCache {
+    fn get(&self, cache_key: &str) -> Option<String> {
+        self.lock().get(cache_key, Instant::now())
+    }
+
+    fn insert(&self, cache_key: &str, key: &str, rendered: &str, max_age: Option<Duration>) {
+        self.lock().insert(
+            cache_key.to_string(),
+            key.to_string(),
+            rendered.to_string(),
+            max_age,
+            Instant::now(),
+        );
+    }
+
+    fn invalidate(&self, key: &str) {
+        self.lock().invalidate(key);
+    }
+
+    fn invalidate_all(&self) {
+        self.lock().clear();
+    }
+
+    fn stats(&self) -> CacheStats {
+        self.lock().stats()
+    }
+}
+
+/// A key-value store holding rendered results out of the process, e.g. Redis or memcached,
+/// adapted to a [`RenderCache`] by [`StoreRenderCache`].
+pub trait CacheStore: Send + Sync {
+    /// Returns the value stored for `key`, if any.
+    fn get(&self, key: &str) -> Option<String>;
+
+    /// Stores `value` for `key`, to be dropped after `ttl` if any.
+    fn set(&self, key: &str, value: &str, ttl: Option<Duration>);
+
+    /// Removes the values whose key starts with `prefix`.
+    fn remove_prefix(&self, prefix: &str);
+}
+
+/// A [`RenderCache`] backed by a [`CacheStore`].
+///
+/// The cache keys are prefixed with a namespace, `ssr:` by default, so that the store can be
+/// shared with other data: invalidating all the results only removes the keys of the
+/// namespace. The statistics only count the hits and misses of this process.
+///
+/// ```no_run
+/// use ssr_rs::{InMemoryStore, Ssr, StoreRenderCache};
+/// use std::sync::Arc;
+///
+/// let cache = Arc::new(StoreRenderCache::new(InMemoryStore::new()).namespace("pages:"));
+///
+/// let ssr = Ssr::builder()
+///     .render_cache_backend(cache)
+///     .source("var SSR = {x: () => '<p></p>'};", "SSR")
+///     .build()
+///     .unwrap();
+/// ```
+pub struct StoreRenderCache<S> {
+    store: S,
+    namespace: String,
+    ttl: Option<Duration>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<S: CacheStore> StoreRenderCache<S> {
+    pub fn new(store: S) -> Self {
+        StoreRenderCache {
+            store,
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            ttl: None,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Sets the prefix of the keys written to the store.
+    pub fn namespace(mut self, namespace: &str) -> Self {
+        self.namespace = namespace.to_string();
+        self
+    }
+
+    /// Makes the results expire `ttl` after they were cached.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Returns the underlying store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+}
+
+impl<S: CacheStore> RenderCache for StoreRenderCache<S> {
+    fn get(&self, cache_key: &str) -> Option<String> {
+        let rendered = self.store.get(&format!("{}{cache_key}", self.namespace));
+        let counter = match rendered {
+            Some(_) => &self.hits,
+            None => &self.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        rendered
+    }
+
+    fn insert(&self, cache_key: &str, _key: &str, rendered: &str, max_age: Option<Duration>) {
+        self.store.set(
+            &format!("{}{cache_key}", self.namespace),
+            rendered,
+            self.ttl.into_iter().chain(max_age).min(),
+        );
+    }
+
+    fn invalidate(&self, key: &str) {
+        self.store
+            .remove_prefix(&format!("{}{key}\u{0}", self.namespace));
+    }
+
+    fn invalidate_all(&self) {
+        self.store.remove_prefix(&self.namespace);
+    }
+
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            ..Default::default()
+        }
+    }
+}
+
+/// A [`CacheStore`] in memory, standing in for an external store in tests.
+#[derive(Debug, Default)]
+pub struct InMemoryStore {
+    values: Mutex<HashMap<String, (String, Option<Instant>)>>,
+}
+
+impl InMemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of values stored, expired ones included.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, (String, Option<Instant>)>> {
+        self.values.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl CacheStore for InMemoryStore {
+    fn get(&self, key: &str) -> Option<String> {
+        let mut values = self.lock();
+        match values.get(key) {
+            Some((_, Some(expires_at))) if Instant::now() >= *expires_at => {
+                values.remove(key);
+                None
+            }
+            entry => entry.map(|(value, _)| value.clone()),
+        }
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Option<Duration>) {
+        let expires_at = ttl.map(|ttl| Instant::now() + ttl);
+        self.lock()
+            .insert(key.to_string(), (value.to_string(), expires_at));
+    }
+
+    fn remove_prefix(&self, prefix: &str) {
+        self.lock().retain(|key, _| !key.starts_with(prefix));
+    }
+}
+
+struct Entry {
+    /// The props key the result was rendered for, see [`Entries::invalidate`].
+    key: String,
+    rendered: String,
+    expires_at: Option<Instant>,
+}
+
+impl Entry {
+    fn size(&self, cache_key: &str) -> usize {
+        cache_key.len() + self.rendered.len()
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|expires_at| now >= expires_at)
+    }
+}
+
+/// The results of a [`MemoryRenderCache`], dropping the least recently used ones past its
+/// [`CacheLimits`].
+struct Entries {
+    entries: LruCache<String, Entry>,
+    limits: CacheLimits,
+    bytes: usize,
+    stats: CacheStats,
+}
+
+impl Entries {
+    fn new(limits: CacheLimits) -> Self {
+        Entries {
+            entries: LruCache::new(limits.max_entries),
+            limits,
+            bytes: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Returns the result cached for `cache_key`, unless it expired at `now`.
+    fn get(&mut self, cache_key: &str, now: Instant) -> Option<String> {
+        let expired = match self.entries.get(cache_key) {
+            Some(entry) if !entry.is_expired(now) => {
+                self.stats.hits += 1;
+                return Some(entry.rendered.clone());
+            }
+            Some(_) => true,
+            None => false,
+        };
+
+        if expired {
+            self.remove(cache_key);
+            self.stats.evictions += 1;
+        }
+        self.stats.misses += 1;
+        None
+    }
+
+    /// Caches `rendered` for `cache_key`, rendered at `now` for the props `key`, for `max_age`
+    /// at most.
+    ///
+    /// A result larger than the byte limit on its own is not cached.
+    fn insert(
+        &mut self,
+        cache_key: String,
+        key: String,
+        rendered: String,
+        max_age: Option<Duration>,
+        now: Instant,
+    ) {
+        let ttl = self.limits.ttl.into_iter().chain(max_age).min();
+        let entry = Entry {
+            key,
+            rendered,
+            expires_at: ttl.map(|ttl| now + ttl),
+        };
+        let size = entry.size(&cache_key);
+        if self
+            .limits
+            .max_bytes
+            .is_some_and(|max_bytes| size > max_bytes)
+        {
+            self.remove(&cache_key);
+            return;
+        }
+
+        self.bytes += size;
+        if let Some((evicted_key, evicted)) = self.entries.push(cache_key.clone(), entry) {
+            self.bytes -= evicted.size(&evicted_key);
+            // `push` also returns the entry it replaced.
+            if evicted_key != cache_key {
+                self.stats.evictions += 1;
+            }
+        }
+
+        while self
+            .limits
+            .max_bytes
+            .is_some_and(|max_bytes| self.bytes > max_bytes)
+        {
+            let Some((evicted_key, evicted)) = self.entries.pop_lru() else {
+                break;
+            };
+            self.bytes -= evicted.size(&evicted_key);
+            self.stats.evictions += 1;
+        }
+    }
+
+    /// Removes the results cached for the props `key`, whatever the render function, and
+    /// returns how many there were.
+    fn invalidate(&mut self, key: &str) -> usize {
+        let cache_keys: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.key == key)
+            .map(|(cache_key, _)| cache_key.clone())
+            .collect();
+        for cache_key in &cache_keys {
+            self.remove(cache_key);
+        }
+        cache_keys.len()
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.bytes = 0;
+    }
+
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.entries.len(),
+            bytes: self.bytes,
+            ..self.stats
+        }
+    }
+
+    fn remove(&mut self, cache_key: &str) {
+        if let Some(entry) = self.entries.pop(cache_key) {
+            self.bytes -= entry.size(cache_key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entries(max_entries: usize, max_bytes: Option<usize>, ttl: Option<Duration>) -> Entries {
+        Entries::new(CacheLimits {
+            max_entries: NonZeroUsize::new(max_entries).unwrap(),
+            max_bytes,
+            ttl,
+        })
+    }
+
+    fn insert(entries: &mut Entries, key: &str, rendered: &str, now: Instant) {
+        entries.insert(
+            key.to_string(),
+            key.to_string(),
+            rendered.to_string(),
+            None,
+            now,
+        );
+    }
+
+    #[test]
+    fn test_max_entries() {
+        let now = Instant::now();
+        let mut cache = entries(2, None, None);
+        insert(&mut cache, "a", "1", now);
+        insert(&mut cache, "b", "2", now);
+        assert_eq!(cache.get("a", now).as_deref(), Some("1"));
+
+        // `b` is the least recently used.
+        insert(&mut cache, "c", "3", now);
+        assert_eq!(cache.get("b", now), None);
+        assert_eq!(cache.get("a", now).as_deref(), Some("1"));
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 2,
+                misses: 1,
+                evictions: 1,
+                entries: 2,
+                bytes: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn test_max_bytes() {
+        let now = Instant::now();
+        let mut cache = entries(10, Some(10), None);
+        insert(&mut cache, "a", "1234", now);
+        insert(&mut cache, "b", "1234", now);
+        assert_eq!(cache.stats().bytes, 10);
+
+        insert(&mut cache, "c", "12", now);
+        assert_eq!(cache.get("a", now), None);
+        assert_eq!(cache.stats().bytes, 8);
+
+        // Too large to be cached at all.
+        insert(&mut cache, "d", "1234567890", now);
+        assert_eq!(cache.get("d", now), None);
+        assert_eq!(cache.stats().entries, 2);
+
+        // Replacing an entry accounts for the size of the previous one.
+        insert(&mut cache, "b", "1", now);
+        assert_eq!(cache.stats().bytes, 5);
+    }
+
+    #[test]
+    fn test_ttl() {
+        let now = Instant::now();
+        let mut cache = entries(10, None, Some(Duration::from_secs(60)));
+        insert(&mut cache, "a", "1", now);
+
+        assert_eq!(
+            cache.get("a", now + Duration::from_secs(59)).as_deref(),
+            Some("1")
+        );
+        assert_eq!(cache.get("a", now + Duration::from_secs(60)), None);
+        assert_eq!(cache.stats().entries, 0);
+        assert_eq!(cache.stats().evictions, 1);
+
+        // The max age declared by the render function shortens the TTL.
+        cache.insert(
+            "b".into(),
+            "b".into(),
+            "2".into(),
+            Some(Duration::from_secs(10)),
+            now,
+        );
+        assert_eq!(cache.get("b", now + Duration::from_secs(10)), None);
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let now = Instant::now();
+        let mut cache = entries(10, None, None);
+        cache.insert("a\0x".into(), "a".into(), "1".into(), None, now);
+        cache.insert("a\0y".into(), "a".into(), "2".into(), None, now);
+        insert(&mut cache, "b", "3", now);
+
+        assert_eq!(cache.invalidate("a"), 2);
+        assert_eq!(cache.invalidate("a"), 0);
+        assert_eq!(cache.stats().entries, 1);
+        assert_eq!(cache.stats().bytes, 2);
+
+        cache.clear();
+        assert_eq!(cache.stats().entries, 0);
+        assert_eq!(cache.stats().bytes, 0);
+    }
+
+    #[test]
+    fn test_canonical_key() {
+        let ignored = ["csrf".to_string(), "now".to_string()];
+        let a = json!({ "b": [1, { "y": 2, "x": 1, "now": 3 }], "a": "é", "csrf": "t1" });
+        let b = json!({ "a": "é", "b": [1, { "x": 1, "y": 2, "now": 4 }], "csrf": "t2" });
+
+        assert_eq!(
+            canonical_key(&a, &ignored),
+            r#"{"a":"é","b":[1,{"x":1,"y":2}]}"#
+        );
+        assert_eq!(canonical_key(&a, &ignored), canonical_key(&b, &ignored));
+        assert_eq!(canonical_key(&json!("{}"), &ignored), r#""{}""#);
+    }
+
+    #[test]
+    fn test_store_render_cache() {
+        let store = InMemoryStore::new();
+        store.set("other", "kept", None);
+        let cache = StoreRenderCache::new(store);
+
+        cache.insert("a\0x", "a", "1", None);
+        cache.insert("a\0y", "a", "2", None);
+        cache.insert("ab\0x", "ab", "3", None);
+        assert_eq!(cache.get("a\0x").as_deref(), Some("1"));
+        assert_eq!(cache.store().get("ssr:a\0y").as_deref(), Some("2"));
+
+        // Only the results of the props `a`, not `ab`.
+        cache.invalidate("a");
+        assert_eq!(cache.get("a\0x"), None);
+        assert_eq!(cache.get("ab\0x").as_deref(), Some("3"));
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 2,
+                misses: 1,
+                ..Default::default()
+            }
+        );
+
+        cache.invalidate_all();
+        assert_eq!(cache.store().len(), 1);
+        assert_eq!(cache.store().get("other").as_deref(), Some("kept"));
+    }
+
+    #[test]
+    fn test_in_memory_store_ttl() {
+        let store = InMemoryStore::new();
+        store.set("a", "1", Some(Duration::ZERO));
+        store.set("b", "2", Some(Duration::from_secs(60)));
+        assert_eq!(store.get("a"), None);
+        assert_eq!(store.get("b").as_deref(), Some("2"));
+        assert_eq!(store.len(), 1);
+    }
+}
@@ -0,0 +1,203 @@
+use crate::code_cache;
+use crate::commonjs;
+use crate::console::Console;
+use crate::error::SsrError;
+use crate::event_loop::EventLoop;
+use crate::modules::ModuleMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+/// A V8 startup snapshot of an instance with its bundle loaded, created with
+/// [`SsrBuilder::create_snapshot`](crate::SsrBuilder::create_snapshot).
+///
+/// Loading a bundle parses and runs it again on every new instance. An instance built from a
+/// snapshot with [`SsrBuilder::snapshot`](crate::SsrBuilder::snapshot) instead starts with the
+/// heap the bundle left behind, which makes cold starts and the growth of a
+/// [`SsrPool`](crate::SsrPool) much faster. Cloning a snapshot does not copy its bytes.
+///
+/// ```no_run
+/// use ssr_rs::{Snapshot, Ssr};
+/// use std::fs::read_to_string;
+///
+/// let source = read_to_string("./path/to/build.js").unwrap();
+/// let snapshot = Ssr::builder()
+///     .source(&source, "SSR")
+///     .create_snapshot()
+///     .unwrap();
+/// snapshot.write("./ssr.snapshot").unwrap();
+///
+/// // Later on, e.g. in another process running the same binary.
+/// let snapshot = Snapshot::read("./ssr.snapshot").unwrap();
+/// let ssr = Ssr::builder().snapshot(snapshot).build().unwrap();
+/// let html = ssr.render_to_string(None).unwrap();
+/// ```
+///
+/// Only the JS heap is part of the snapshot. The globals defined by the builder creating it are
+/// kept, but the Rust side of the instance comes from the builder restoring it: the async
+/// functions must be registered again (in the same order), and the source map of the bundle
+/// given again. The ES modules imported by the bundle are kept, although a dynamic `import()`
+/// of one of them after the restore evaluates it again.
+///
+/// The bytes of a snapshot can only be restored by the build of the crate (and of V8) which
+/// created them, and must be complete and intact, which [`Snapshot::from_bytes`] checks.
+#[derive(Clone)]
+pub struct Snapshot {
+    blob: Arc<[u8]>,
+}
+
+impl Snapshot {
+    pub(crate) fn new(blob: &[u8]) -> Self {
+        Snapshot {
+            blob: Arc::from(blob),
+        }
+    }
+
+    /// Returns the data V8 creates an isolate from.
+    pub(crate) fn blob(&self) -> Arc<[u8]> {
+        self.blob.clone()
+    }
+
+    /// Reads a snapshot from the bytes returned by [`Snapshot::to_bytes`].
+    ///
+    /// Fails with [`SsrError::InvalidSnapshot`] if they were created by another build, or were
+    /// truncated or corrupted since: V8 does not check the data, and aborts the process when it
+    /// cannot restore it.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SsrError> {
+        let invalid = |msg: &str| SsrError::InvalidSnapshot(msg.to_string());
+
+        let (header, blob) = bytes
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .map(|end| (&bytes[..end], &bytes[end + 1..]))
+            .ok_or_else(|| invalid("Missing header"))?;
+        let (length, checksum) = std::str::from_utf8(header)
+            .ok()
+            .and_then(|header| header.strip_prefix(&build()))
+            .and_then(|fields| fields.strip_prefix(' '))
+            .and_then(|fields| fields.split_once(' '))
+            .ok_or_else(|| invalid("Not created by this build of ssr_rs"))?;
+        let length: usize = length.parse().map_err(|_| invalid("Invalid length"))?;
+        let checksum =
+            u64::from_str_radix(checksum, 16).map_err(|_| invalid("Invalid checksum"))?;
+
+        if blob.is_empty() || blob.len() != length {
+            return Err(SsrError::InvalidSnapshot(format!(
+                "Expected {length} bytes of data, got {}",
+                blob.len()
+            )));
+        }
+        if code_cache::fnv1a(blob.iter().copied()) != checksum {
+            return Err(invalid("Checksum mismatch, the data is corrupted"));
+        }
+        Ok(Snapshot::new(blob))
+    }
+
+    /// Returns the bytes of the snapshot, prefixed with a header line naming the build which
+    /// created it, along with the length and the checksum of the data.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let checksum = code_cache::fnv1a(self.blob.iter().copied());
+        let mut bytes = format!("{} {} {checksum:016x}\n", build(), self.blob.len()).into_bytes();
+        bytes.extend_from_slice(&self.blob);
+        bytes
+    }
+
+    /// Reads a snapshot from a file written by [`Snapshot::write`], see
+    /// [`Snapshot::from_bytes`].
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, SsrError> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)
+            .map_err(|err| SsrError::InvalidSnapshot(format!("{}: {err}", path.display())))?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Writes the bytes of the snapshot to a file, see [`Snapshot::to_bytes`].
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+}
+
+impl fmt::Debug for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Snapshot")
+            .field("bytes", &self.blob.len())
+            .finish()
+    }
+}
+
+/// Names the build able to restore a snapshot, at the start of its header.
+fn build() -> String {
+    let features = if cfg!(feature = "web-apis") {
+        " web-apis"
+    } else {
+        ""
+    };
+    format!(
+        "ssr_rs {} v8 {}{features}",
+        env!("CARGO_PKG_VERSION"),
+        v8::V8::get_version()
+    )
+}
+
+/// Returns the native callbacks the functions of a snapshot may point to.
+///
+/// V8 serializes them as their index in this list, which is the same for the isolates creating
+/// and restoring the snapshots of a build.
+pub(crate) fn external_references() -> &'static v8::ExternalReferences {
+    static EXTERNAL_REFERENCES: OnceLock<v8::ExternalReferences> = OnceLock::new();
+
+    EXTERNAL_REFERENCES.get_or_init(|| {
+        let mut callbacks = Console::callbacks();
+        callbacks.extend(EventLoop::callbacks());
+        callbacks.extend(ModuleMap::callbacks());
+        callbacks.extend(commonjs::callbacks());
+        #[cfg(feature = "web-apis")]
+        callbacks.extend(crate::web_apis::callbacks());
+
+        let mut references: Vec<_> = callbacks
+            .into_iter()
+            .map(|function| v8::ExternalReference { function })
+            .collect();
+        references.push(v8::ExternalReference {
+            pointer: ModuleMap::evaluation_steps(),
+        });
+        v8::ExternalReferences::new(&references)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bytes() {
+        let snapshot = Snapshot::new(b"blob");
+        let bytes = snapshot.to_bytes();
+        assert!(bytes.starts_with(b"ssr_rs "));
+        assert_eq!(&*Snapshot::from_bytes(&bytes).unwrap().blob, b"blob");
+
+        let other = [b"ssr_rs 0.0.0 v8 0.0.0 4 0\n".as_slice(), b"blob"].concat();
+        assert!(matches!(
+            Snapshot::from_bytes(&other),
+            Err(SsrError::InvalidSnapshot(_))
+        ));
+        assert!(Snapshot::from_bytes(&bytes[..bytes.len() - 4]).is_err());
+        assert!(Snapshot::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Snapshot::read("./missing.snapshot").is_err());
+    }
+
+    #[test]
+    fn test_corrupted_bytes() {
+        let snapshot = Snapshot::new(b"blob");
+        let mut bytes = snapshot.to_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+
+        assert_eq!(
+            Snapshot::from_bytes(&bytes).unwrap_err(),
+            SsrError::InvalidSnapshot("Checksum mismatch, the data is corrupted".to_string())
+        );
+    }
+}
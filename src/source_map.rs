@@ -0,0 +1,371 @@
+use crate::error::{JsError, SsrError};
+use base64::Engine;
+use serde::Deserialize;
+
+const INLINE_PREFIXES: [&str; 2] = ["//# sourceMappingURL=", "//@ sourceMappingURL="];
+
+/// A parsed [source map v3](https://sourcemaps.info/spec.html) used to translate the
+/// positions of a (usually minified) bundle back to the original sources.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceMap {
+    sources: Vec<String>,
+    names: Vec<String>,
+    lines: Vec<Vec<Mapping>>,
+}
+
+/// A position in one of the original sources of a [`SourceMap`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OriginalLocation {
+    pub source: String,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column number.
+    pub column: usize,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Mapping {
+    generated_column: u32,
+    source: u32,
+    original_line: u32,
+    original_column: u32,
+    name: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSourceMap {
+    version: u8,
+    #[serde(default)]
+    source_root: Option<String>,
+    #[serde(default)]
+    sources: Vec<Option<String>>,
+    #[serde(default)]
+    names: Vec<String>,
+    mappings: String,
+}
+
+impl SourceMap {
+    /// Parses the JSON content of a `.map` file.
+    pub fn parse(json: &str) -> Result<Self, SsrError> {
+        let raw: RawSourceMap = serde_json::from_str(json)
+            .map_err(|err| SsrError::InvalidSourceMap(err.to_string()))?;
+
+        if raw.version != 3 {
+            return Err(SsrError::InvalidSourceMap(format!(
+                "Unsupported source map version: {}",
+                raw.version
+            )));
+        }
+
+        let source_root = raw
+            .source_root
+            .filter(|root| !root.is_empty())
+            .map(|root| {
+                if root.ends_with('/') {
+                    root
+                } else {
+                    format!("{root}/")
+                }
+            })
+            .unwrap_or_default();
+        let sources = raw
+            .sources
+            .into_iter()
+            .map(|source| format!("{source_root}{}", source.unwrap_or_default()))
+            .collect();
+
+        Ok(SourceMap {
+            sources,
+            names: raw.names,
+            lines: Self::decode_mappings(&raw.mappings)?,
+        })
+    }
+
+    /// Extracts and parses an inline `//# sourceMappingURL=data:...;base64,...` comment.
+    ///
+    /// Returns `None` when the source has no inline source map; source maps referenced by
+    /// URL must be read by the caller and handed over explicitly.
+    pub fn from_inline(source: &str) -> Option<Result<Self, SsrError>> {
+        let url = INLINE_PREFIXES
+            .iter()
+            .filter_map(|prefix| {
+                source
+                    .rfind(prefix)
+                    .map(|index| &source[index + prefix.len()..])
+            })
+            .next()?
+            .split_whitespace()
+            .next()?;
+
+        let data = url.strip_prefix("data:")?;
+        let (_, payload) = data.split_once(";base64,")?;
+
+        Some(
+            base64::engine::general_purpose::STANDARD
+                .decode(payload)
+                .map_err(|err| SsrError::InvalidSourceMap(err.to_string()))
+                .and_then(|bytes| {
+                    String::from_utf8(bytes)
+                        .map_err(|err| SsrError::InvalidSourceMap(err.to_string()))
+                })
+                .and_then(|json| Self::parse(&json)),
+        )
+    }
+
+    /// Looks up the original position of the 1-based `line` and `column` of the bundle.
+    pub fn lookup(&self, line: usize, column: usize) -> Option<OriginalLocation> {
+        let mappings = self.lines.get(line.checked_sub(1)?)?;
+        let column = column.checked_sub(1)? as u32;
+
+        let index = mappings.partition_point(|mapping| mapping.generated_column <= column);
+        let mapping = mappings.get(index.checked_sub(1)?)?;
+
+        Some(OriginalLocation {
+            source: self.sources.get(mapping.source as usize)?.clone(),
+            line: mapping.original_line as usize + 1,
+            column: mapping.original_column as usize + 1,
+            name: mapping
+                .name
+                .and_then(|name| self.names.get(name as usize).cloned()),
+        })
+    }
+
+    /// Rewrites every `file_name:line:column` occurrence of `stack` to its original position.
+    pub fn rewrite_stack(&self, file_name: &str, stack: &str) -> String {
+        let mut result = String::with_capacity(stack.len());
+        let mut rest = stack;
+
+        while let Some(index) = rest.find(file_name) {
+            let (before, after) = rest.split_at(index);
+            result.push_str(before);
+
+            let is_boundary = !matches!(
+                result.chars().next_back(),
+                Some(c) if c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '/' | '\\')
+            );
+            let position = after[file_name.len()..]
+                .strip_prefix(':')
+                .and_then(Self::parse_position);
+
+            match position.filter(|_| is_boundary) {
+                Some((line, column, consumed)) => match self.lookup(line, column) {
+                    Some(original) => {
+                        result.push_str(&format!(
+                            "{}:{}:{}",
+                            original.source, original.line, original.column
+                        ));
+                        rest = &after[file_name.len() + 1 + consumed..];
+                    }
+                    None => {
+                        result.push_str(file_name);
+                        rest = &after[file_name.len()..];
+                    }
+                },
+                None => {
+                    result.push_str(file_name);
+                    rest = &after[file_name.len()..];
+                }
+            }
+        }
+
+        result.push_str(rest);
+        result
+    }
+
+    /// Maps the location and stack trace of `error` when it originates from `file_name`.
+    pub(crate) fn apply(&self, file_name: &str, error: &mut JsError) {
+        if let Some(stack) = &error.stack {
+            error.stack = Some(self.rewrite_stack(file_name, stack));
+        }
+
+        if error.script_name.as_deref() != Some(file_name) {
+            return;
+        }
+        if let (Some(line), Some(column)) = (error.line, error.column) {
+            if let Some(original) = self.lookup(line, column) {
+                error.script_name = Some(original.source);
+                error.line = Some(original.line);
+                error.column = Some(original.column);
+            }
+        }
+    }
+
+    /// Parses `<line>:<column>` returning the values and the number of bytes consumed.
+    fn parse_position(input: &str) -> Option<(usize, usize, usize)> {
+        let line_len = input.chars().take_while(char::is_ascii_digit).count();
+        let line = input[..line_len].parse().ok()?;
+        let rest = input[line_len..].strip_prefix(':')?;
+        let column_len = rest.chars().take_while(char::is_ascii_digit).count();
+        let column = rest[..column_len].parse().ok()?;
+
+        Some((line, column, line_len + 1 + column_len))
+    }
+
+    fn decode_mappings(mappings: &str) -> Result<Vec<Vec<Mapping>>, SsrError> {
+        let mut lines = Vec::new();
+        let mut source = 0i64;
+        let mut original_line = 0i64;
+        let mut original_column = 0i64;
+        let mut name = 0i64;
+
+        for line in mappings.split(';') {
+            let mut generated_column = 0i64;
+            let mut decoded = Vec::new();
+
+            for segment in line.split(',').filter(|segment| !segment.is_empty()) {
+                let fields = Self::decode_vlq(segment)?;
+                generated_column += fields[0];
+
+                // Segments with a single field have no original position.
+                if fields.len() < 4 {
+                    continue;
+                }
+                source += fields[1];
+                original_line += fields[2];
+                original_column += fields[3];
+                let segment_name = if fields.len() >= 5 {
+                    name += fields[4];
+                    Some(name as u32)
+                } else {
+                    None
+                };
+
+                decoded.push(Mapping {
+                    generated_column: generated_column as u32,
+                    source: source as u32,
+                    original_line: original_line as u32,
+                    original_column: original_column as u32,
+                    name: segment_name,
+                });
+            }
+
+            decoded.sort_by_key(|mapping| mapping.generated_column);
+            lines.push(decoded);
+        }
+
+        Ok(lines)
+    }
+
+    fn decode_vlq(segment: &str) -> Result<Vec<i64>, SsrError> {
+        let mut fields = Vec::with_capacity(5);
+        let mut value = 0i64;
+        let mut shift = 0;
+
+        for byte in segment.bytes() {
+            let digit = match byte {
+                b'A'..=b'Z' => byte - b'A',
+                b'a'..=b'z' => byte - b'a' + 26,
+                b'0'..=b'9' => byte - b'0' + 52,
+                b'+' => 62,
+                b'/' => 63,
+                _ => {
+                    return Err(SsrError::InvalidSourceMap(format!(
+                        "Invalid mapping segment: {segment}"
+                    )))
+                }
+            } as i64;
+
+            // A field wider than an `i64` can only come from a corrupt mapping.
+            value = (shift <= 60)
+                .then(|| (digit & 0b11111).checked_mul(1 << shift))
+                .flatten()
+                .and_then(|bits| value.checked_add(bits))
+                .ok_or_else(|| {
+                    SsrError::InvalidSourceMap(format!("Mapping segment overflows: {segment}"))
+                })?;
+            if digit & 0b100000 != 0 {
+                shift += 5;
+                continue;
+            }
+
+            fields.push(if value & 1 == 1 {
+                -(value >> 1)
+            } else {
+                value >> 1
+            });
+            value = 0;
+            shift = 0;
+        }
+
+        if shift != 0 || fields.is_empty() {
+            return Err(SsrError::InvalidSourceMap(format!(
+                "Invalid mapping segment: {segment}"
+            )));
+        }
+
+        Ok(fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two generated lines: `a` at 1:1 maps to src/App.tsx 1:1 and `b` at 2:5
+    // maps to src/App.tsx 3:3 with the name `render`.
+    const MAP: &str = r#"{
+        "version": 3,
+        "sourceRoot": "webpack:///",
+        "sources": ["src/App.tsx"],
+        "names": ["render"],
+        "mappings": "AAAA;IAEEA"
+    }"#;
+
+    #[test]
+    fn test_lookup() {
+        let map = SourceMap::parse(MAP).unwrap();
+
+        assert_eq!(
+            map.lookup(2, 7),
+            Some(OriginalLocation {
+                source: "webpack:///src/App.tsx".to_string(),
+                line: 3,
+                column: 3,
+                name: Some("render".to_string()),
+            })
+        );
+        assert_eq!(map.lookup(2, 1), None);
+        assert_eq!(map.lookup(3, 1), None);
+    }
+
+    #[test]
+    fn test_rewrite_stack() {
+        let map = SourceMap::parse(MAP).unwrap();
+        let stack = "Error: boom\n    at render (module.js:2:5)\n    at other.js:2:5";
+
+        assert_eq!(
+            map.rewrite_stack("module.js", stack),
+            "Error: boom\n    at render (webpack:///src/App.tsx:3:3)\n    at other.js:2:5"
+        );
+    }
+
+    #[test]
+    fn test_from_inline() {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(MAP);
+        let source =
+            format!("var a;\n//# sourceMappingURL=data:application/json;base64,{encoded}\n");
+
+        let map = SourceMap::from_inline(&source).unwrap().unwrap();
+        assert_eq!(map, SourceMap::parse(MAP).unwrap());
+
+        assert!(SourceMap::from_inline("var a;\n//# sourceMappingURL=a.js.map").is_none());
+        assert!(SourceMap::from_inline("var a;").is_none());
+    }
+
+    #[test]
+    fn test_overlong_segment() {
+        let overlong = MAP.replace("IAEEA", &format!("{}A", "g".repeat(13)));
+        assert!(matches!(
+            SourceMap::parse(&overlong),
+            Err(SsrError::InvalidSourceMap(_))
+        ));
+
+        let overflowing = MAP.replace("IAEEA", &format!("{}A", "/".repeat(13)));
+        assert!(matches!(
+            SourceMap::parse(&overflowing),
+            Err(SsrError::InvalidSourceMap(_))
+        ));
+    }
+}
@@ -1,19 +1,124 @@
+use crate::builder::SsrBuilder;
+use crate::code_cache::{self, CodeCache};
+use crate::commonjs::{self, CommonJs};
+use crate::console::{Console, ConsoleMessage};
+use crate::error::{JsError, SsrError};
+use crate::event_loop::{self, Deadline, EventLoop};
+use crate::heap::HeapGuard;
+use crate::module_type::ModuleType;
+use crate::modules::ModuleMap;
+use crate::output::RenderOutput;
+use crate::render_cache::{CacheKeyFn, CacheStats, MemoryRenderCache, RenderCache};
+use crate::snapshot::{self, Snapshot};
+use crate::source_map::SourceMap;
+use crate::stream::RenderStream;
+use crate::value;
+use crate::watchdog::Watchdog;
 use lru::LruCache;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
 use std::rc::Rc;
-use std::sync::Once;
-use v8::{Context, Function, Local, PromiseState, Value};
+use std::sync::{Arc, Once};
+use std::time::{Duration, Instant};
+use v8::{Context, Function, Local, Value};
 
 static V8_INIT: Once = Once::new();
 
+/// The props handed to the render functions.
+enum Props<'a> {
+    None,
+    /// Passed as a JS string.
+    Str(&'a str),
+    /// Converted to the equivalent JS value.
+    Json(serde_json::Value),
+}
+
+impl<'a> From<Option<&'a str>> for Props<'a> {
+    fn from(params: Option<&'a str>) -> Self {
+        params.map_or(Props::None, Props::Str)
+    }
+}
+
+impl Props<'_> {
+    fn json<P: Serialize + ?Sized>(props: &P) -> Result<Self, SsrError> {
+        serde_json::to_value(props)
+            .map(Props::Json)
+            .map_err(|err| SsrError::Serialization(err.to_string()))
+    }
+
+    /// Returns the props handed to the cache-key function: the props string is parsed as JSON
+    /// when it is valid JSON.
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            Props::None => serde_json::Value::Null,
+            Props::Str(params) => serde_json::from_str(params)
+                .unwrap_or_else(|_| serde_json::Value::String(params.to_string())),
+            Props::Json(value) => value.clone(),
+        }
+    }
+
+    /// Returns the key of the props in the render cache, see [`Ssr::invalidate`].
+    fn cache_key(&self) -> String {
+        match self {
+            Props::None => String::new(),
+            Props::Str(params) => params.to_string(),
+            Props::Json(value) => value.to_string(),
+        }
+    }
+}
+
+/// The render functions found in the bundle, in the order they were defined.
+#[derive(Default)]
+struct Exports(Vec<(String, v8::Global<Function>)>);
+
+impl Exports {
+    /// Adds `func`, replacing (in place) a previous function with the same name.
+    fn insert(&mut self, name: String, func: v8::Global<Function>) {
+        match self.0.iter_mut().find(|(existing, _)| *existing == name) {
+            Some((_, existing)) => *existing = func,
+            None => self.0.push((name, func)),
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&v8::Global<Function>> {
+        self.0
+            .iter()
+            .find(|(existing, _)| existing == name)
+            .map(|(_, func)| func)
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.0.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    fn functions(&self) -> impl Iterator<Item = &v8::Global<Function>> {
+        self.0.iter().map(|(_, func)| func)
+    }
+}
+
+/// The resource name given to the loaded bundle, as it appears in JS stack traces.
+const MODULE_FILE_NAME: &str = "module.js";
+
+/// The global holding the render functions of a snapshot until it is restored.
+const SNAPSHOT_EXPORTS: &str = "__ssr_rs_exports";
+
 pub struct Ssr {
     isolate: Rc<RefCell<v8::OwnedIsolate>>,
     context: v8::Global<Context>,
-    fn_map: Rc<RefCell<HashMap<String, v8::Global<Function>>>>,
+    fn_map: Rc<RefCell<Exports>>,
     script_cache: Rc<RefCell<LruCache<String, v8::Global<v8::UnboundScript>>>>,
     loaded_scripts: Rc<RefCell<HashMap<String, ()>>>,
-    render_cache: Rc<RefCell<HashMap<String, String>>>,
+    render_cache: Option<Arc<dyn RenderCache>>,
+    cache_key: Option<CacheKeyFn>,
+    source_maps: Rc<RefCell<HashMap<String, SourceMap>>>,
+    timeout: Cell<Option<Duration>>,
+    event_loop: Rc<EventLoop>,
+    console: Rc<Console>,
+    watchdog: Watchdog,
+    // Declared after `isolate` so that it is dropped after it.
+    heap_guard: Box<HeapGuard>,
 }
 
 impl Default for Ssr {
@@ -24,63 +129,341 @@ impl Default for Ssr {
 
 impl Ssr {
     pub fn init() {
+        V8_INIT.call_once(Self::initialize_v8);
+    }
+
+    /// Initializes V8 with command line flags, e.g. `"--stack-size=2000 --no-opt"`.
+    ///
+    /// The flags are process wide and V8 only reads them once, so this has to be called before
+    /// any instance is created (or [`Ssr::init`] called). Returns
+    /// [`SsrError::AlreadyInitialized`] otherwise, the flags being ignored.
+    pub fn init_with_flags(flags: &str) -> Result<(), SsrError> {
+        let mut initialized = false;
         V8_INIT.call_once(|| {
-            let platform = v8::new_default_platform(0, false).make_shared();
-            v8::V8::initialize_platform(platform);
-            v8::V8::initialize();
+            v8::V8::set_flags_from_string(flags);
+            Self::initialize_v8();
+            initialized = true;
         });
+        if initialized {
+            Ok(())
+        } else {
+            Err(SsrError::AlreadyInitialized)
+        }
+    }
+
+    fn initialize_v8() {
+        let platform = v8::new_default_platform(0, false).make_shared();
+        v8::V8::initialize_platform(platform);
+        v8::V8::initialize();
     }
 
     pub fn new() -> Self {
+        Self::from_builder(SsrBuilder::new()).expect("Failed to create the Ssr instance")
+    }
+
+    /// Returns a [`SsrBuilder`] to configure the instance before creating it.
+    pub fn builder() -> SsrBuilder {
+        SsrBuilder::new()
+    }
+
+    /// Creates an instance whose isolate heap starts at `initial_bytes` and may grow up to
+    /// `max_bytes`.
+    ///
+    /// A render that exhausts the heap is aborted with [`SsrError::OutOfMemory`] instead of
+    /// crashing the process, and the instance is marked for recycling
+    /// (see [`Ssr::needs_recycle`]).
+    pub fn with_heap_limits(initial_bytes: usize, max_bytes: usize) -> Self {
+        Self::from_builder(SsrBuilder::new().heap_limits(initial_bytes, max_bytes))
+            .expect("Failed to create the Ssr instance")
+    }
+
+    pub(crate) fn from_builder(builder: SsrBuilder) -> Result<Self, SsrError> {
+        let mut params = Self::create_params(&builder);
+        if let Some(snapshot) = &builder.snapshot {
+            params = params
+                .snapshot_blob(snapshot.blob())
+                .external_references(&**snapshot::external_references());
+        }
+
+        Self::from_isolate(v8::Isolate::new(params), builder)
+    }
+
+    /// Creates an instance whose isolate is serialized by [`Ssr::into_snapshot`].
+    pub(crate) fn snapshot_creator(builder: SsrBuilder) -> Result<Self, SsrError> {
+        let params = Self::create_params(&builder);
+        let isolate =
+            v8::Isolate::snapshot_creator(Some(snapshot::external_references()), Some(params));
+        Self::from_isolate(isolate, builder)
+    }
+
+    /// Initializes V8 if needed, returning the parameters of the isolate of `builder`.
+    fn create_params(builder: &SsrBuilder) -> v8::CreateParams {
         Self::init();
 
-        let mut isolate = v8::Isolate::new(v8::CreateParams::default());
+        let mut params = v8::CreateParams::default();
+        if let Some((initial_bytes, max_bytes)) = builder.heap_limits {
+            params = params.heap_limits(initial_bytes, max_bytes);
+        }
+        params
+    }
+
+    fn from_isolate(mut isolate: v8::OwnedIsolate, builder: SsrBuilder) -> Result<Self, SsrError> {
+        let watchdog = Watchdog::new(isolate.thread_safe_handle());
+        let heap_guard = HeapGuard::install(&mut isolate);
+        let event_loop = Rc::new(EventLoop::new(builder.virtual_time));
+        isolate.set_slot(event_loop.clone());
+        let console = Rc::new(Console::new(builder.console.clone()));
+        isolate.set_slot(console.clone());
+        isolate.set_slot(Rc::new(ModuleMap::new(
+            builder.module_loader.clone(),
+            builder.node_builtins,
+        )));
+        isolate.set_slot(Rc::new(CommonJs::default()));
+        if let Some(dir) = &builder.code_cache_dir {
+            isolate.set_slot(Rc::new(CodeCache::new(dir.clone())));
+        }
+        isolate.set_host_import_module_dynamically_callback(ModuleMap::dynamic_import_callback);
 
+        let mut fn_map = Exports::default();
         let global_context = {
             let handle_scope = &mut v8::HandleScope::new(&mut isolate);
+            // Restored from the snapshot, if any, along with the functions defined below.
             let context = v8::Context::new(handle_scope, v8::ContextOptions::default());
-            v8::Global::new(handle_scope, context)
+            let scope = &mut v8::ContextScope::new(handle_scope, context);
+            if builder.snapshot.is_none() {
+                EventLoop::install_timers(scope)?;
+                Console::install(scope)?;
+                #[cfg(feature = "web-apis")]
+                crate::web_apis::install(scope)?;
+            }
+            Self::install_globals(scope, &builder.globals)?;
+            event_loop.install_functions(scope, &builder.async_functions)?;
+            if builder.snapshot.is_some() {
+                Self::restore_exports(scope, &mut fn_map)?;
+            }
+            v8::Global::new(scope, context)
         };
 
-        Ssr {
+        Ok(Ssr {
             isolate: Rc::new(RefCell::new(isolate)),
             context: global_context,
-            fn_map: Rc::new(RefCell::new(HashMap::new())),
-            script_cache: Rc::new(RefCell::new(LruCache::new(
-                std::num::NonZeroUsize::new(100).unwrap(),
-            ))),
+            fn_map: Rc::new(RefCell::new(fn_map)),
+            script_cache: Rc::new(RefCell::new(LruCache::new(builder.script_cache_size))),
             loaded_scripts: Rc::new(RefCell::new(HashMap::new())),
-            render_cache: Rc::new(RefCell::new(HashMap::new())),
+            render_cache: builder.render_cache.then(|| {
+                builder.render_cache_backend.clone().unwrap_or_else(|| {
+                    Arc::new(MemoryRenderCache::with_limits(builder.render_cache_limits))
+                })
+            }),
+            cache_key: builder.cache_key.clone(),
+            source_maps: Rc::new(RefCell::new(HashMap::new())),
+            timeout: Cell::new(builder.timeout),
+            event_loop,
+            console,
+            watchdog,
+            heap_guard,
+        })
+    }
+
+    fn install_globals(
+        scope: &mut v8::ContextScope<'_, v8::HandleScope>,
+        globals: &[(String, serde_json::Value)],
+    ) -> Result<(), SsrError> {
+        let global = scope.get_current_context().global(scope);
+
+        for (name, value) in globals {
+            let key = v8::String::new(scope, name).unwrap();
+            let value = value::to_v8(scope, value)?;
+            global.set(scope, key.into(), value);
+        }
+
+        Ok(())
+    }
+
+    /// Registers the render functions kept on the global object by [`Ssr::into_snapshot`].
+    fn restore_exports(
+        scope: &mut v8::ContextScope<'_, v8::HandleScope>,
+        fn_map: &mut Exports,
+    ) -> Result<(), SsrError> {
+        let global = scope.get_current_context().global(scope);
+        let key = v8::String::new(scope, SNAPSHOT_EXPORTS).unwrap();
+        let exports = global
+            .get(scope, key.into())
+            .filter(|exports| exports.is_object())
+            .ok_or_else(|| {
+                SsrError::InvalidSnapshot("The render functions are missing".to_string())
+            })?;
+        global.delete(scope, key.into());
+
+        Self::register_exports(scope, exports, "", fn_map)
+    }
+
+    /// Serializes the heap of an instance created with [`Ssr::snapshot_creator`], keeping its
+    /// render functions on the global object until [`Ssr::restore_exports`].
+    pub(crate) fn into_snapshot(self) -> Result<Snapshot, SsrError> {
+        let Ssr {
+            isolate,
+            context,
+            fn_map,
+            script_cache,
+            event_loop,
+            heap_guard,
+            ..
+        } = self;
+        let mut isolate = Rc::into_inner(isolate)
+            .expect("The isolate is only owned by the instance")
+            .into_inner();
+
+        {
+            let scope = &mut v8::HandleScope::with_context(&mut isolate, &context);
+            let context = Local::new(scope, &context);
+            let exports = v8::Object::new(scope);
+            for (name, function) in &fn_map.borrow().0 {
+                let key = v8::String::new(scope, name).unwrap();
+                let function = Local::new(scope, function);
+                exports.set(scope, key.into(), function.into());
+            }
+
+            let global = context.global(scope);
+            let key = v8::String::new(scope, SNAPSHOT_EXPORTS).unwrap();
+            global.set(scope, key.into(), exports.into());
+            scope.set_default_context(context);
         }
+
+        // V8 cannot serialize a heap still referenced by global handles.
+        drop((context, fn_map, script_cache, event_loop));
+        isolate.remove_slot::<Rc<EventLoop>>();
+        isolate.remove_slot::<Rc<ModuleMap>>();
+        isolate.remove_slot::<Rc<CommonJs>>();
+        heap_guard.uninstall(&mut isolate);
+
+        let blob = isolate
+            .create_blob(v8::FunctionCodeHandling::Keep)
+            .ok_or_else(|| SsrError::InvalidSnapshot("V8 failed to create it".to_string()))?;
+        Ok(Snapshot::new(&blob))
+    }
+
+    /// Returns `true` once the isolate reached its heap limit.
+    ///
+    /// The instance keeps working, but the heap limit has been raised to let the aborted
+    /// render unwind, so it should be dropped and replaced by a fresh one.
+    pub fn needs_recycle(&self) -> bool {
+        self.heap_guard.is_exhausted()
+    }
+
+    /// Runs `f`, returning the messages logged with `console` meanwhile along with its output.
+    ///
+    /// The captured messages are not sent to the sink set with [`SsrBuilder::console`]. This
+    /// gives the logs of a single render, e.g. to attach them to the span of the request when
+    /// the render runs on a [`SsrPool`](crate::SsrPool) worker:
+    ///
+    /// ```no_run
+    /// # use ssr_rs::Ssr;
+    /// # let ssr = Ssr::new();
+    /// let (html, logs) = ssr.capture_console(|ssr| ssr.render_to_string(None));
+    /// for log in logs {
+    ///     tracing::info!(level = %log.level, "{}", log.message);
+    /// }
+    /// ```
+    pub fn capture_console<R>(&self, f: impl FnOnce(&Self) -> R) -> (R, Vec<ConsoleMessage>) {
+        self.console.capture(|| f(self))
+    }
+
+    /// Sets the maximum time a single `load` or render may run JS code before its execution is
+    /// terminated with [`SsrError::Timeout`]. `None` (the default) disables the limit.
+    ///
+    /// The instance stays usable after a timeout.
+    pub fn set_timeout(&self, timeout: Option<Duration>) {
+        self.timeout.set(timeout);
+    }
+
+    pub fn load(
+        &self,
+        source: &str,
+        entry_point: &str,
+        module_type: ModuleType,
+    ) -> Result<(), SsrError> {
+        self.load_with_source_map(source, entry_point, module_type, None)
     }
 
-    pub fn load(&self, source: &str, entry_point: &str, module_type: &str) -> Result<(), String> {
+    /// Same as [`Ssr::load`], additionally using `source_map` (the content of the bundle `.map`
+    /// file) to rewrite the location and stack trace of JS errors to the original sources.
+    ///
+    /// When `source_map` is `None` the inline `//# sourceMappingURL=data:...` comment of the
+    /// bundle is used, if any.
+    pub fn load_with_source_map(
+        &self,
+        source: &str,
+        entry_point: &str,
+        module_type: ModuleType,
+        source_map: Option<&str>,
+    ) -> Result<(), SsrError> {
         if self.loaded_scripts.borrow().contains_key(source) {
             return Ok(());
         }
 
-        let mut isolate = self.isolate.borrow_mut();
-        let context = &self.context;
-        let mut scope = v8::HandleScope::with_context(&mut *isolate, context);
-        let context = Local::new(&mut scope, context);
-        let mut scope = v8::ContextScope::new(&mut scope, context);
+        let source_map = match source_map {
+            Some(source_map) => Some(SourceMap::parse(source_map)?),
+            None => SourceMap::from_inline(source).transpose()?,
+        };
+        if let Some(source_map) = source_map {
+            self.source_maps
+                .borrow_mut()
+                .insert(MODULE_FILE_NAME.to_string(), source_map);
+        }
 
-        match module_type {
-            "esm" => Self::load_esm(
-                &mut scope,
-                source,
-                entry_point,
-                &mut self.fn_map.borrow_mut(),
-            ),
-            "cjs" => Self::load_cjs(
-                &mut scope,
-                source,
-                entry_point,
-                &mut self.fn_map.borrow_mut(),
-                &mut self.script_cache.borrow_mut(),
-            ),
-            _ => Err("Unsupported module type".to_string()),
-        }?;
+        self.event_loop.clear();
+        let loaded = self.run_guarded(self.timeout.get(), || {
+            let mut isolate = self.isolate.borrow_mut();
+            let context = &self.context;
+            let mut scope = v8::HandleScope::with_context(&mut *isolate, context);
+            let context = Local::new(&mut scope, context);
+            let mut scope = v8::ContextScope::new(&mut scope, context);
+
+            match module_type.resolve(source) {
+                ModuleType::Esm => Self::load_esm(
+                    &mut scope,
+                    source,
+                    entry_point,
+                    &mut self.fn_map.borrow_mut(),
+                ),
+                ModuleType::Cjs => {
+                    let loaded = Self::load_cjs(
+                        &mut scope,
+                        source,
+                        entry_point,
+                        &mut self.fn_map.borrow_mut(),
+                    );
+                    match loaded {
+                        // The detection missed the module syntax which CommonJS cannot compile.
+                        Err(SsrError::Compile(err)) if module_type == ModuleType::Auto => {
+                            Self::load_esm(
+                                &mut scope,
+                                source,
+                                entry_point,
+                                &mut self.fn_map.borrow_mut(),
+                            )
+                            .map_err(|esm_err| match esm_err {
+                                SsrError::Compile(_) => SsrError::Compile(err),
+                                esm_err => esm_err,
+                            })
+                        }
+                        loaded => loaded,
+                    }
+                }
+                ModuleType::Iife => Self::load_iife(
+                    &mut scope,
+                    source,
+                    entry_point,
+                    &mut self.fn_map.borrow_mut(),
+                    &mut self.script_cache.borrow_mut(),
+                ),
+                ModuleType::Auto => unreachable!("resolved from the source"),
+            }
+        });
+        // Timers still pending once the bundle is loaded are dropped.
+        self.event_loop.clear();
+        loaded.map_err(|err| self.apply_source_maps(err))?;
 
         self.loaded_scripts
             .borrow_mut()
@@ -88,28 +471,40 @@ impl Ssr {
         Ok(())
     }
 
+    /// Sets the source map of the bundle the instance was restored with from a snapshot.
+    pub(crate) fn set_source_map(&self, source_map: &str) -> Result<(), SsrError> {
+        let source_map = SourceMap::parse(source_map)?;
+        self.source_maps
+            .borrow_mut()
+            .insert(MODULE_FILE_NAME.to_string(), source_map);
+        Ok(())
+    }
+
     fn load_esm(
         scope: &mut v8::ContextScope<'_, v8::HandleScope>,
         source: &str,
         entry_point: &str,
-        fn_map: &mut std::collections::HashMap<String, v8::Global<Function>>,
-    ) -> Result<(), String> {
-        Self::load_module(scope, source, "module.js")?;
+        fn_map: &mut Exports,
+    ) -> Result<(), SsrError> {
+        Self::load_module(scope, source, MODULE_FILE_NAME)?;
         let global = scope.get_current_context().global(scope);
         let exports_str = v8::String::new(scope, "exports").unwrap();
         let exports = global
             .get(scope, exports_str.into())
-            .ok_or("No exports found")?;
-        let exports_obj = exports.to_object(scope).ok_or("Exports is not an object")?;
-        let entry_point = v8::String::new(scope, entry_point).unwrap();
+            .ok_or_else(|| SsrError::Evaluation(JsError::new("No exports found")))?;
+        let exports_obj = exports
+            .to_object(scope)
+            .ok_or_else(|| SsrError::Evaluation(JsError::new("Exports is not an object")))?;
+        let entry_point_str = v8::String::new(scope, entry_point).unwrap();
         let entry_func = exports_obj
-            .get(scope, entry_point.into())
-            .ok_or("Entry point not found in exports")?;
+            .get(scope, entry_point_str.into())
+            .filter(|value| !value.is_undefined())
+            .ok_or_else(|| SsrError::EntryPointNotFound(entry_point.to_string()))?;
         if let Ok(func) = v8::Local::<v8::Function>::try_from(entry_func) {
-            fn_map.insert("default".to_string(), v8::Global::new(scope, func));
+            fn_map.insert(entry_point.to_string(), v8::Global::new(scope, func));
             Ok(())
         } else {
-            Err("Entry point is not a function".to_string())
+            Err(SsrError::EntryNotFunction(entry_point.to_string()))
         }
     }
 
@@ -117,252 +512,836 @@ impl Ssr {
         scope: &mut v8::ContextScope<'_, v8::HandleScope>,
         source: &str,
         entry_point: &str,
-        fn_map: &mut std::collections::HashMap<String, v8::Global<Function>>,
-        script_cache: &mut LruCache<String, v8::Global<v8::UnboundScript>>,
-    ) -> Result<(), String> {
-        Self::load_commonjs(scope, source, "module.js")?;
-        let code = format!("{source};{entry_point}");
-        let script = Self::compile_script(scope, &code, script_cache)?;
+        fn_map: &mut Exports,
+    ) -> Result<(), SsrError> {
+        let scope = &mut v8::TryCatch::new(scope);
+
+        // Bundles such as webpack's `var` library declare the entry point in the scope of the
+        // module: a getter returned after the source reads it from there.
+        let getter = match entry_point {
+            "" => String::new(),
+            entry_point => format!("\n;return function () {{ return {entry_point}; }};"),
+        };
+        let function =
+            commonjs::compile(scope, source, MODULE_FILE_NAME, &getter).ok_or_else(|| {
+                SsrError::Compile(JsError::from_try_catch(scope, "Failed to compile module"))
+            })?;
+        let (module, getter) =
+            commonjs::call(scope, function, MODULE_FILE_NAME).ok_or_else(|| {
+                SsrError::Evaluation(JsError::from_try_catch(scope, "Failed to run module"))
+            })?;
+
+        let exports = commonjs::exports(scope, module);
+        if entry_point.is_empty() {
+            return Self::register_exports(scope, exports, "default", fn_map);
+        }
 
-        let result = script.run(scope).ok_or("Failed to run script")?;
-        let object = result
+        let key = v8::String::new(scope, entry_point).unwrap();
+        let exported = exports
             .to_object(scope)
-            .ok_or("Invalid JS: The script does not return any object after being executed")?;
+            .and_then(|exports| exports.get(scope, key.into()))
+            .filter(|value| !value.is_undefined());
+        let entry = match exported {
+            Some(entry) => Some(entry),
+            None => v8::Local::<v8::Function>::try_from(getter)
+                .ok()
+                .and_then(|getter| {
+                    let undef = v8::undefined(scope).into();
+                    getter.call(scope, undef, &[])
+                }),
+        };
+
+        match entry.filter(|entry| !entry.is_null_or_undefined()) {
+            Some(entry) => Self::register_exports(scope, entry, entry_point, fn_map),
+            None => Err(SsrError::EntryPointNotFound(entry_point.to_string())),
+        }
+    }
+
+    fn load_iife(
+        scope: &mut v8::ContextScope<'_, v8::HandleScope>,
+        source: &str,
+        entry_point: &str,
+        fn_map: &mut Exports,
+        script_cache: &mut LruCache<String, v8::Global<v8::UnboundScript>>,
+    ) -> Result<(), SsrError> {
+        // The entry point goes on its own line so that a trailing line comment
+        // (e.g. `//# sourceMappingURL=`) does not swallow it.
+        let code = format!("{source}\n;{entry_point}");
+        let script = Self::compile_script(scope, &code, MODULE_FILE_NAME, script_cache)?;
+
+        let scope = &mut v8::TryCatch::new(scope);
+        let result = script.run(scope).ok_or_else(|| {
+            SsrError::Evaluation(JsError::from_try_catch(scope, "Failed to run script"))
+        })?;
+        if result.is_null_or_undefined() {
+            return Err(SsrError::EntryPointNotFound(entry_point.to_string()));
+        }
+
+        Self::register_exports(scope, result, entry_point, fn_map)
+    }
+
+    /// Registers the render functions of the entry point: the functions it holds, or itself when
+    /// it is a function. Fails with [`SsrError::EntryPointNotFound`] if it holds no function, so
+    /// that a bundle loaded with the wrong entry point or module type does not render nothing.
+    fn register_exports(
+        scope: &mut v8::HandleScope,
+        entry: Local<Value>,
+        entry_point: &str,
+        fn_map: &mut Exports,
+    ) -> Result<(), SsrError> {
+        let name = match entry_point {
+            "" => "default",
+            entry_point => entry_point,
+        };
+        if let Ok(func) = Local::<Function>::try_from(entry) {
+            fn_map.insert(name.to_string(), v8::Global::new(scope, func));
+            return Ok(());
+        }
+
+        let object = entry.to_object(scope).ok_or_else(|| {
+            SsrError::Evaluation(JsError::new(
+                "Invalid JS: The script does not return any object after being executed",
+            ))
+        })?;
 
         let props = object
             .get_own_property_names(scope, Default::default())
             .unwrap();
+        let mut registered = false;
         for i in 0..props.length() {
             let key = props.get_index(scope, i).unwrap();
             let key_str = key.to_string(scope).unwrap().to_rust_string_lossy(scope);
             let val = object.get(scope, key).unwrap();
             if let Ok(func) = v8::Local::<v8::Function>::try_from(val) {
                 fn_map.insert(key_str, v8::Global::new(scope, func));
+                registered = true;
             }
         }
 
-        Ok(())
+        if registered {
+            Ok(())
+        } else {
+            Err(SsrError::EntryPointNotFound(name.to_string()))
+        }
     }
 
-    pub fn render_to_string(&self, params: Option<&str>) -> Result<String, String> {
-        let cache_key = match params {
-            Some(p) => p.to_string(),
-            None => "".to_string(),
-        };
+    pub fn render_to_string(&self, params: Option<&str>) -> Result<String, SsrError> {
+        self.render_cached(None, &Props::from(params), self.timeout.get())
+    }
 
-        if let Some(cached_result) = self.render_cache.borrow().get(&cache_key) {
-            return Ok(cached_result.clone());
-        }
+    /// Calls only the render function exported as `name`, see [`Ssr::exports`].
+    pub fn render_export(&self, name: &str, params: Option<&str>) -> Result<String, SsrError> {
+        self.render_cached(Some(name), &Props::from(params), self.timeout.get())
+    }
 
-        let fn_map = self.fn_map.borrow();
-        let results: Vec<Result<String, String>> = fn_map
-            .iter()
-            .map(|(_, func)| {
-                let mut isolate = self.isolate.borrow_mut();
-                let context = &self.context;
-                let mut scope = v8::HandleScope::with_context(&mut *isolate, context);
-                let context = Local::new(&mut scope, context);
-                let mut scope = v8::ContextScope::new(&mut scope, context);
-
-                let params: Local<Value> = match params {
-                    Some(p) => v8::String::new(&mut scope, p).unwrap().into(),
-                    None => v8::undefined(&mut scope).into(),
-                };
+    /// Same as [`Ssr::render_to_string`] but hands `props` to the render functions as a JS value
+    /// (object, array, ...) instead of a JSON string they would have to parse.
+    pub fn render_with<P: Serialize + ?Sized>(&self, props: &P) -> Result<String, SsrError> {
+        self.render_cached(None, &Props::json(props)?, self.timeout.get())
+    }
 
-                let undef = v8::undefined(&mut scope).into();
+    /// Same as [`Ssr::render_export`] with `props` handed over as a JS value, see
+    /// [`Ssr::render_with`].
+    pub fn render_export_with<P: Serialize + ?Sized>(
+        &self,
+        name: &str,
+        props: &P,
+    ) -> Result<String, SsrError> {
+        self.render_cached(Some(name), &Props::json(props)?, self.timeout.get())
+    }
 
-                let func = Local::new(&mut scope, func);
-                let result = func
-                    .call(&mut scope, undef, &[params])
-                    .ok_or("Failed to call function")?;
+    /// Calls the render function exported as `name` with `props` as a JS value and deserializes
+    /// the returned JS value into `T`.
+    ///
+    /// The conversion follows the `JSON.stringify` semantics. The result is never cached.
+    pub fn render_export_as<T, P>(&self, name: &str, props: &P) -> Result<T, SsrError>
+    where
+        T: DeserializeOwned,
+        P: Serialize + ?Sized,
+    {
+        let mut results = self
+            .call_exports(
+                Some(name),
+                &Props::json(props)?,
+                self.timeout.get(),
+                value::from_v8,
+            )
+            .map_err(|err| self.apply_source_maps(err))?;
+
+        serde_json::from_value(results.remove(0))
+            .map_err(|err| SsrError::Serialization(err.to_string()))
+    }
 
-                if result.is_promise() {
-                    let promise = v8::Local::<v8::Promise>::try_from(result)
-                        .map_err(|_| "Failed to cast main function to promise")?;
+    /// Calls the render function exported as `name` and reads the returned object as a
+    /// [`RenderOutput`], see its documentation for the expected shape. The result is never cached.
+    pub fn render_output(
+        &self,
+        name: &str,
+        params: Option<&str>,
+    ) -> Result<RenderOutput, SsrError> {
+        self.render_output_props(name, &Props::from(params))
+    }
 
-                    while promise.state() == PromiseState::Pending {
-                        scope.perform_microtask_checkpoint();
-                    }
+    /// Same as [`Ssr::render_output`] with `props` handed over as a JS value, see
+    /// [`Ssr::render_with`].
+    pub fn render_output_with<P: Serialize + ?Sized>(
+        &self,
+        name: &str,
+        props: &P,
+    ) -> Result<RenderOutput, SsrError> {
+        self.render_output_props(name, &Props::json(props)?)
+    }
 
-                    let result = promise.result(&mut scope);
-                    if result.is_null_or_undefined() {
-                        return Err("Promise rejected".to_string());
-                    }
+    fn render_output_props(&self, name: &str, props: &Props) -> Result<RenderOutput, SsrError> {
+        let mut results = self
+            .call_exports(Some(name), props, self.timeout.get(), value::from_v8)
+            .map_err(|err| self.apply_source_maps(err))?;
 
-                    Ok(result
-                        .to_string(&mut scope)
-                        .ok_or("Failed to parse the result to string")?
-                        .to_rust_string_lossy(&mut scope))
-                } else {
-                    Ok(result
-                        .to_string(&mut scope)
-                        .ok_or("Failed to parse the result to string")?
-                        .to_rust_string_lossy(&mut scope))
-                }
-            })
-            .collect();
+        RenderOutput::from_json(results.remove(0))
+    }
 
-        let rendered = results
-            .into_iter()
-            .collect::<Result<Vec<String>, String>>()?
-            .join("");
+    /// Calls the render functions (see [`Ssr::render_to_string`]) one after the other and
+    /// returns their output as a stream of byte chunks, see [`RenderStream`].
+    ///
+    /// ```no_run
+    /// use ssr_rs::{ModuleType, Ssr};
+    /// use std::io::Write;
+    ///
+    /// let source = r#"var SSR = {
+    ///     render: async (props, write) => {
+    ///         write("<div>shell</div>");
+    ///         write(await Promise.resolve("<div>content</div>"));
+    ///     },
+    /// };"#;
+    ///
+    /// let ssr = Ssr::new();
+    /// ssr.load(source, "SSR", ModuleType::Cjs).unwrap();
+    ///
+    /// let mut out = std::io::stdout();
+    /// for chunk in ssr.render_to_stream(None).unwrap() {
+    ///     out.write_all(&chunk.unwrap()).unwrap();
+    ///     out.flush().unwrap();
+    /// }
+    /// ```
+    ///
+    /// The result is never cached.
+    pub fn render_to_stream(&self, params: Option<&str>) -> Result<RenderStream<'_>, SsrError> {
+        let functions = self.fn_map.borrow().functions().cloned().collect();
+        RenderStream::new(self, functions, params)
+    }
 
-        self.render_cache
-            .borrow_mut()
-            .insert(cache_key, rendered.clone());
-        Ok(rendered)
+    /// Same as [`Ssr::render_to_stream`] for the render function exported as `name` only.
+    pub fn render_export_to_stream(
+        &self,
+        name: &str,
+        params: Option<&str>,
+    ) -> Result<RenderStream<'_>, SsrError> {
+        let func = self
+            .fn_map
+            .borrow()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| SsrError::ExportNotFound(name.to_string()))?;
+        RenderStream::new(self, VecDeque::from([func]), params)
     }
 
-    fn compile_script<'s>(
-        scope: &mut v8::ContextScope<'s, v8::HandleScope>,
-        source: &str,
-        script_cache: &mut LruCache<String, v8::Global<v8::UnboundScript>>,
-    ) -> Result<v8::Local<'s, v8::Script>, String> {
-        if script_cache.get(source).is_some() {
-            // If the script is in the cache, compile it again (we can't return the cached version directly)
-            let source = v8::String::new(scope, source).ok_or("Failed to create V8 string")?;
-            return v8::Script::compile(scope, source, None)
-                .ok_or_else(|| "Failed to compile cached script".to_string());
+    /// Returns the names of the render functions found in the loaded bundles.
+    ///
+    /// The order is the property order of the entry point object (i.e. the definition order for
+    /// non-numeric keys), which is also the order [`Ssr::render_to_string`] concatenates their
+    /// results in.
+    pub fn exports(&self) -> Vec<String> {
+        self.fn_map.borrow().names()
+    }
+
+    /// Same as [`Ssr::render_to_string`] with a deadline overriding the one set with
+    /// [`Ssr::set_timeout`] for this call only.
+    pub fn render_to_string_with_timeout(
+        &self,
+        params: Option<&str>,
+        timeout: Duration,
+    ) -> Result<String, SsrError> {
+        self.render_cached(None, &Props::from(params), Some(timeout))
+    }
+
+    /// Same as [`Ssr::render_to_string`] without blocking on the promises returned by the render
+    /// functions: while they wait for host work (see [`SsrBuilder::async_function`]), the render
+    /// yields to the async runtime and is woken up once that work completes.
+    ///
+    /// It must run within a tokio runtime. The timeout set with [`Ssr::set_timeout`] covers the
+    /// whole render, waits included.
+    ///
+    /// ```no_run
+    /// use ssr_rs::{ModuleType, Ssr};
+    /// use std::time::Duration;
+    ///
+    /// # async fn run() {
+    /// let ssr = Ssr::builder()
+    ///     .async_function("fetchUser", |id: u32| async move {
+    ///         tokio::time::sleep(Duration::from_millis(10)).await;
+    ///         Ok::<_, String>(format!("user {id}"))
+    ///     })
+    ///     .source(
+    ///         "var SSR = { render: async () => `<p>${await fetchUser(1)}</p>` };",
+    ///         "SSR",
+    ///     )
+    ///     .module_type(ModuleType::Cjs)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(ssr.render(None).await.unwrap(), "<p>user 1</p>");
+    /// # }
+    /// ```
+    pub async fn render(&self, params: Option<&str>) -> Result<String, SsrError> {
+        self.render_async(None, &Props::from(params)).await
+    }
+
+    /// Same as [`Ssr::render`] with `props` handed over as a JS value, see
+    /// [`Ssr::render_with`].
+    pub async fn render_with_async<P: Serialize + ?Sized>(
+        &self,
+        props: &P,
+    ) -> Result<String, SsrError> {
+        self.render_async(None, &Props::json(props)?).await
+    }
+
+    /// Calls the `export` render function, or all of them concatenating the results when `None`.
+    fn render_cached(
+        &self,
+        export: Option<&str>,
+        props: &Props,
+        timeout: Option<Duration>,
+    ) -> Result<String, SsrError> {
+        let keys = self.cache_keys(export, props);
+        if let Some(cached_result) = keys
+            .as_ref()
+            .and_then(|(cache_key, _)| self.cached(cache_key))
+        {
+            return Ok(cached_result);
         }
 
-        let source = v8::String::new(scope, source).ok_or("Failed to create V8 string")?;
-        let script = v8::Script::compile(scope, source, None).ok_or("Failed to compile script")?;
+        let outputs = self
+            .call_exports(export, props, timeout, Self::to_output)
+            .map_err(|err| self.apply_source_maps(err))?;
 
-        let unbound_script = script.get_unbound_script(scope);
-        script_cache.put(
-            source.to_rust_string_lossy(scope),
-            v8::Global::new(scope, unbound_script),
-        );
+        Ok(self.cache(keys, outputs))
+    }
 
-        Ok(script)
+    /// Same as [`Ssr::render_cached`], waiting for the promises on the event loop.
+    async fn render_async(
+        &self,
+        export: Option<&str>,
+        props: &Props<'_>,
+    ) -> Result<String, SsrError> {
+        let keys = self.cache_keys(export, props);
+        if let Some(cached_result) = keys
+            .as_ref()
+            .and_then(|(cache_key, _)| self.cached(cache_key))
+        {
+            return Ok(cached_result);
+        }
+
+        self.event_loop.clear();
+        let results = self.call_exports_async(export, props).await;
+        self.event_loop.clear();
+
+        let outputs = results.map_err(|err| self.apply_source_maps(err))?;
+        Ok(self.cache(keys, outputs))
     }
 
-    fn load_module(
-        scope: &mut v8::ContextScope<'_, v8::HandleScope>,
-        source: &str,
-        file_name: &str,
-    ) -> Result<(), String> {
-        let source_str = v8::String::new(scope, source).unwrap();
-        let file_name_str = v8::String::new(scope, file_name).unwrap();
+    /// Returns the key the result of the `export` render function (or of all of them when
+    /// `None`) is cached with for `props` (see [`RenderCache`]) along with the key of the props,
+    /// or `None` when the cache-key function rules out caching it.
+    fn cache_keys(&self, export: Option<&str>, props: &Props) -> Option<(String, String)> {
+        self.render_cache.as_ref()?;
+        let key = match &self.cache_key {
+            Some(cache_key) => cache_key(&props.to_json())?,
+            None => props.cache_key(),
+        };
 
-        let origin = v8::ScriptOrigin::new(
-            scope,
-            file_name_str.into(),
-            0,
-            0,
-            false,
-            0,
-            None,
-            false,
-            false,
-            true,
-            None,
-        );
-        let mut source = v8::script_compiler::Source::new(source_str, Some(&origin));
-        let module = v8::script_compiler::compile_module(scope, &mut source)
-            .ok_or("Failed to compile module")?;
+        // Keeps `{}` given as a value apart from `"{}"` given as a string.
+        let kind = if matches!(props, Props::Json(_)) {
+            "\u{1}"
+        } else {
+            ""
+        };
+        let cache_key = format!("{key}\u{0}{kind}{}", export.unwrap_or_default());
+        Some((cache_key, key))
+    }
 
-        module
-            .instantiate_module(scope, |_, _, _, _| None)
-            .ok_or("Failed to instantiate module")?;
+    fn cached(&self, cache_key: &str) -> Option<String> {
+        self.render_cache
+            .as_ref()
+            .and_then(|render_cache| render_cache.get(cache_key))
+    }
 
-        let result = module.evaluate(scope).ok_or("Failed to evaluate module")?;
+    /// Concatenates the results of the render functions, caching them with `keys` unless one of
+    /// them is not cacheable.
+    fn cache(&self, keys: Option<(String, String)>, outputs: Vec<RenderOutput>) -> String {
+        let cacheable = outputs.iter().all(|output| output.cacheable);
+        let max_age = outputs.iter().filter_map(|output| output.max_age).min();
+        let rendered: String = outputs.into_iter().map(|output| output.html).collect();
 
-        if result.is_promise() {
-            let promise = v8::Local::<v8::Promise>::try_from(result).unwrap();
-            while promise.state() == v8::PromiseState::Pending {
-                scope.perform_microtask_checkpoint();
-            }
-            if promise.state() != v8::PromiseState::Fulfilled {
-                return Err("Module evaluation promise rejected".to_string());
+        if let (Some(render_cache), Some((cache_key, key))) = (&self.render_cache, keys) {
+            if cacheable && max_age != Some(Duration::ZERO) {
+                render_cache.insert(&cache_key, &key, &rendered, max_age);
             }
         }
+        rendered
+    }
 
-        // Set the exports object in the global scope
-        let global = scope.get_current_context().global(scope);
-        let exports = module.get_module_namespace();
-        let exports_str = v8::String::new(scope, "exports").unwrap();
+    /// Removes the results cached for the props `key`, whatever the render function.
+    ///
+    /// The key of the props is the one returned by the function set with
+    /// [`SsrBuilder::cache_key`]. Without one, it is the string given to
+    /// [`Ssr::render_to_string`] (an empty string for `None`), or the JSON serialization of the
+    /// props given to [`Ssr::render_with`], e.g. `{"id":1}`. With a cache shared between
+    /// instances (see [`SsrBuilder::render_cache_backend`]), the results are removed for all of
+    /// them.
+    pub fn invalidate(&self, key: &str) {
+        if let Some(render_cache) = &self.render_cache {
+            render_cache.invalidate(key);
+        }
+    }
 
-        // Create all necessary values before the call to set
-        let exports_val = exports;
-        let exports_str_val: v8::Local<v8::Value> = exports_str.into();
+    /// Removes all the cached results.
+    pub fn invalidate_all(&self) {
+        if let Some(render_cache) = &self.render_cache {
+            render_cache.invalidate_all();
+        }
+    }
 
-        global
-            .set(scope, exports_str_val, exports_val)
-            .ok_or("Failed to set exports")?;
+    /// Returns the statistics of the render cache, all zeros when it is disabled.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.render_cache
+            .as_ref()
+            .map(|render_cache| render_cache.stats())
+            .unwrap_or_default()
+    }
 
-        Ok(())
+    /// Calls the `export` render function (or all of them when `None`) with `props` and converts
+    /// the (awaited) results with `convert`.
+    fn call_exports<R>(
+        &self,
+        export: Option<&str>,
+        props: &Props,
+        timeout: Option<Duration>,
+        convert: impl Fn(&mut v8::HandleScope, Local<Value>) -> Result<R, SsrError>,
+    ) -> Result<Vec<R>, SsrError> {
+        let functions = self.export_functions(export)?;
+
+        self.event_loop.clear();
+        let results = self.run_guarded(timeout, || {
+            functions
+                .iter()
+                .map(|func| {
+                    self.with_context(|scope| {
+                        let result = Self::call_function(scope, func, props)?;
+
+                        let result = if result.is_promise() {
+                            let promise =
+                                v8::Local::<v8::Promise>::try_from(result).map_err(|_| {
+                                    SsrError::Evaluation(JsError::new(
+                                        "Failed to cast main function to promise",
+                                    ))
+                                })?;
+
+                            Self::settle_promise(scope, promise)?
+                        } else {
+                            result
+                        };
+
+                        let scope: &mut v8::HandleScope = scope;
+                        convert(scope, result)
+                    })
+                })
+                .collect::<Result<Vec<R>, SsrError>>()
+        });
+
+        // Nothing polls the host work started by a blocking render, and the timers it left
+        // behind are dropped.
+        self.event_loop.clear();
+        results
     }
 
-    fn load_commonjs(
-        scope: &mut v8::ContextScope<'_, v8::HandleScope>,
-        source: &str,
-        file_name: &str,
-    ) -> Result<(), String> {
-        let source_str = format!("(function(require, module, exports) {{{}}})", source);
-        let source_script = v8::String::new(scope, &source_str).unwrap();
-        let file_name_str = v8::String::new(scope, file_name).unwrap();
+    /// Same as [`Ssr::call_exports`] for string results, awaiting the host work the promises
+    /// wait for instead of failing with [`SsrError::UnsettledPromise`].
+    ///
+    /// The isolate is only borrowed while JS code runs, never across an `.await`.
+    async fn call_exports_async(
+        &self,
+        export: Option<&str>,
+        props: &Props<'_>,
+    ) -> Result<Vec<RenderOutput>, SsrError> {
+        let deadline = Deadline::new(self.timeout.get());
+        let mut results = Vec::new();
+
+        for func in self.export_functions(export)? {
+            let result = self.run_step(deadline, |scope| {
+                let result = Self::call_function(scope, &func, props)?;
+                Ok(v8::Global::new(scope, result))
+            })?;
+
+            loop {
+                let rendered = self.run_step(deadline, |scope| {
+                    let result = Local::new(scope, &result);
+                    let result = match v8::Local::<v8::Promise>::try_from(result) {
+                        Ok(promise) => match event_loop::poll_promise(scope, promise)? {
+                            Some(result) => result,
+                            None => return Ok(None),
+                        },
+                        Err(_) => result,
+                    };
+                    Self::to_output(scope, result).map(Some)
+                })?;
+
+                if let Some(rendered) = rendered {
+                    results.push(rendered);
+                    break;
+                }
+                if !self.event_loop.has_pending() {
+                    return Err(SsrError::UnsettledPromise);
+                }
+                self.event_loop.check_stalled(deadline)?;
 
-        let origin = v8::ScriptOrigin::new(
-            scope,
-            file_name_str.into(),
-            0,
-            0,
-            false,
-            0,
-            None,
-            false,
-            false,
-            false,
-            None,
-        );
-        let script = v8::Script::compile(scope, source_script, Some(&origin))
-            .ok_or("Failed to compile script")?;
+                let completions = match deadline {
+                    Some(deadline) => deadline.wait(self.event_loop.next()).await?,
+                    None => self.event_loop.next().await,
+                };
+                self.run_step(deadline, |scope| {
+                    for completion in completions {
+                        completion(scope);
+                    }
+                    self.event_loop.run_due_timers(scope)
+                })?;
+            }
+        }
 
-        script.run(scope).ok_or("Failed to run script")?;
+        Ok(results)
+    }
 
-        Ok(())
+    fn export_functions(
+        &self,
+        export: Option<&str>,
+    ) -> Result<Vec<v8::Global<Function>>, SsrError> {
+        let fn_map = self.fn_map.borrow();
+        match export {
+            Some(export) => Ok(vec![fn_map
+                .get(export)
+                .cloned()
+                .ok_or_else(|| SsrError::ExportNotFound(export.to_string()))?]),
+            None => Ok(fn_map.functions().cloned().collect()),
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::sync::Once;
+    fn call_function<'s>(
+        scope: &mut v8::TryCatch<v8::HandleScope<'s>>,
+        func: &v8::Global<Function>,
+        props: &Props,
+    ) -> Result<Local<'s, Value>, SsrError> {
+        let params: Local<Value> = match props {
+            Props::None => v8::undefined(scope).into(),
+            Props::Str(p) => v8::String::new(scope, p).unwrap().into(),
+            Props::Json(value) => value::to_v8(scope, value)?,
+        };
 
-    static INIT: Once = Once::new();
+        let undef = v8::undefined(scope).into();
 
-    pub fn init_test() {
-        INIT.call_once(|| {
-            Ssr::init();
+        let func = Local::new(scope, func);
+        func.call(scope, undef, &[params]).ok_or_else(|| {
+            SsrError::Evaluation(JsError::from_try_catch(scope, "Failed to call function"))
         })
     }
 
-    fn create_ssr(source: &str, entry_point: &str, module_type: &str) -> Ssr {
-        let ssr = Ssr::new();
-        ssr.load(source, entry_point, module_type).unwrap();
-        ssr
+    fn to_rust_string(
+        scope: &mut v8::HandleScope,
+        value: Local<Value>,
+    ) -> Result<String, SsrError> {
+        Ok(value
+            .to_string(scope)
+            .ok_or(SsrError::ResultNotStringifiable)?
+            .to_rust_string_lossy(scope))
     }
 
-    #[test]
-    fn test_render_simple_html() {
-        init_test();
+    /// Converts the result of a render function: a string, or an object with an `html`
+    /// property. Only the cache hints of such an object are read besides its HTML, and those of
+    /// the wrong type are ignored, so that a render never fails because of them.
+    fn to_output(
+        scope: &mut v8::HandleScope,
+        value: Local<Value>,
+    ) -> Result<RenderOutput, SsrError> {
+        let html_key = v8::String::new(scope, "html").unwrap();
+        let object = v8::Local::<v8::Object>::try_from(value)
+            .ok()
+            .filter(|object| {
+                !value.is_string_object() && object.has(scope, html_key.into()).unwrap_or(false)
+            });
+        let Some(object) = object else {
+            return Ok(RenderOutput {
+                html: Self::to_rust_string(scope, value)?,
+                ..Default::default()
+            });
+        };
 
-        let source = r##"var SSR = {x: () => "<html><body>Hello, world!</body></html>"};"##;
+        let mut output = RenderOutput::default();
+        if let Some(html) = object
+            .get(scope, html_key.into())
+            .filter(|html| !html.is_null_or_undefined())
+        {
+            output.html = Self::to_rust_string(scope, html)?;
+        }
 
-        let ssr = create_ssr(source, "SSR", "cjs");
-        let html = ssr.render_to_string(None).unwrap();
+        let key = v8::String::new(scope, "cacheable").unwrap();
+        if let Some(cacheable) = object
+            .get(scope, key.into())
+            .filter(|cacheable| cacheable.is_boolean())
+        {
+            output.cacheable = cacheable.boolean_value(scope);
+        }
 
-        assert_eq!(html, "<html><body>Hello, world!</body></html>");
+        let key = v8::String::new(scope, "maxAge").unwrap();
+        output.max_age = object
+            .get(scope, key.into())
+            .filter(|max_age| max_age.is_number())
+            .and_then(|max_age| max_age.number_value(scope))
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok());
+
+        Ok(output)
     }
 
-    #[test]
+    /// Runs `f` terminating its JS execution when `timeout` expires or the heap limit is
+    /// reached.
+    fn run_guarded<R>(
+        &self,
+        timeout: Option<Duration>,
+        f: impl FnOnce() -> Result<R, SsrError>,
+    ) -> Result<R, SsrError> {
+        let guard = timeout.map(|timeout| self.watchdog.arm(Instant::now() + timeout));
+        self.event_loop.set_deadline(Deadline::new(timeout));
+        let result = f();
+        self.event_loop.set_deadline(None);
+        let timed_out = guard.is_some_and(|guard| guard.disarm());
+        let out_of_memory = self.heap_guard.take_near_limit();
+
+        if timed_out || out_of_memory {
+            // Let the next call run: the termination only had to unwind the current one.
+            self.isolate.borrow_mut().cancel_terminate_execution();
+        }
+        if out_of_memory {
+            return Err(SsrError::OutOfMemory);
+        }
+        if let (true, Some(timeout)) = (timed_out, timeout) {
+            return Err(SsrError::Timeout(timeout));
+        }
+        result
+    }
+
+    /// Runs `f` in the context of the instance, guarded like the renders and with the JS errors
+    /// mapped through the loaded source maps.
+    pub(crate) fn run_in_context<R>(
+        &self,
+        f: impl FnOnce(&mut v8::TryCatch<v8::HandleScope>) -> Result<R, SsrError>,
+    ) -> Result<R, SsrError> {
+        self.run_guarded(self.timeout.get(), || self.with_context(f))
+            .map_err(|err| self.apply_source_maps(err))
+    }
+
+    /// Runs a step of an async render, guarded with the time left before `deadline`.
+    fn run_step<R>(
+        &self,
+        deadline: Option<Deadline>,
+        f: impl FnOnce(&mut v8::TryCatch<v8::HandleScope>) -> Result<R, SsrError>,
+    ) -> Result<R, SsrError> {
+        let timeout = deadline.map(|deadline| deadline.remaining()).transpose()?;
+
+        self.run_guarded(timeout, || self.with_context(f))
+            .map_err(|err| match (err, deadline) {
+                (SsrError::Timeout(_), Some(deadline)) => deadline.error(),
+                (err, _) => err,
+            })
+    }
+
+    fn with_context<R>(
+        &self,
+        f: impl FnOnce(&mut v8::TryCatch<v8::HandleScope>) -> Result<R, SsrError>,
+    ) -> Result<R, SsrError> {
+        let mut isolate = self.isolate.borrow_mut();
+        let mut scope = v8::HandleScope::with_context(&mut *isolate, &self.context);
+        let context = Local::new(&mut scope, &self.context);
+        let mut scope = v8::ContextScope::new(&mut scope, context);
+        let scope = &mut v8::TryCatch::new(&mut scope);
+        f(scope)
+    }
+
+    /// Returns the promise value once settled, running the timers it may wait for.
+    ///
+    /// Host work does not make progress during a blocking operation, so a promise waiting for
+    /// nothing else fails with [`SsrError::UnsettledPromise`] instead of spinning forever.
+    pub(crate) fn settle_promise<'s>(
+        scope: &mut v8::HandleScope<'s>,
+        promise: Local<'s, v8::Promise>,
+    ) -> Result<Local<'s, Value>, SsrError> {
+        EventLoop::get(scope).settle(scope, promise)
+    }
+
+    pub(crate) fn event_loop(&self) -> &EventLoop {
+        &self.event_loop
+    }
+
+    /// Rewrites the JS error carried by `err` (if any) with the loaded source maps.
+    fn apply_source_maps(&self, mut err: SsrError) -> SsrError {
+        if let Some(js_error) = err.js_error_mut() {
+            for (file_name, source_map) in self.source_maps.borrow().iter() {
+                source_map.apply(file_name, js_error);
+            }
+        }
+        err
+    }
+
+    pub(crate) fn script_origin<'s>(
+        scope: &mut v8::HandleScope<'s>,
+        file_name: &str,
+        is_module: bool,
+    ) -> v8::ScriptOrigin<'s> {
+        let file_name_str = v8::String::new(scope, file_name).unwrap();
+
+        v8::ScriptOrigin::new(
+            scope,
+            file_name_str.into(),
+            0,
+            0,
+            false,
+            0,
+            None,
+            false,
+            false,
+            is_module,
+            None,
+        )
+    }
+
+    fn compile_script<'s>(
+        scope: &mut v8::ContextScope<'s, v8::HandleScope>,
+        source: &str,
+        file_name: &str,
+        script_cache: &mut LruCache<String, v8::Global<v8::UnboundScript>>,
+    ) -> Result<v8::Local<'s, v8::Script>, SsrError> {
+        let scope = &mut v8::TryCatch::new(scope);
+        if let Some(unbound_script) = script_cache.get(source) {
+            let unbound_script = Local::new(scope, unbound_script);
+            return Ok(unbound_script.bind_to_current_context(scope));
+        }
+
+        let origin = Self::script_origin(scope, file_name, false);
+        let string = v8::String::new(scope, source)
+            .ok_or_else(|| SsrError::Compile(JsError::new("Failed to create V8 string")))?;
+        let entry = CodeCache::entry(scope, source);
+        let (mut compiled, options) = code_cache::source(entry.as_ref(), string, &origin);
+        let script = v8::script_compiler::compile(
+            scope,
+            &mut compiled,
+            options,
+            v8::script_compiler::NoCacheReason::NoReason,
+        )
+        .ok_or_else(|| {
+            SsrError::Compile(JsError::from_try_catch(scope, "Failed to compile script"))
+        })?;
+
+        let unbound_script = script.get_unbound_script(scope);
+        if let Some(entry) = entry {
+            entry.update(&compiled, || unbound_script.create_code_cache());
+        }
+        script_cache.put(source.to_string(), v8::Global::new(scope, unbound_script));
+
+        Ok(script)
+    }
+
+    fn load_module(
+        scope: &mut v8::ContextScope<'_, v8::HandleScope>,
+        source: &str,
+        file_name: &str,
+    ) -> Result<(), SsrError> {
+        let scope = &mut v8::TryCatch::new(scope);
+        let origin = Self::script_origin(scope, file_name, true);
+        let module = ModuleMap::get(scope).compile(scope, source, file_name, &origin)?;
+
+        module
+            .instantiate_module(scope, ModuleMap::resolve_callback)
+            .ok_or_else(|| {
+                SsrError::Evaluation(JsError::from_try_catch(
+                    scope,
+                    "Failed to instantiate module",
+                ))
+            })?;
+
+        let result = module.evaluate(scope).ok_or_else(|| {
+            SsrError::Evaluation(JsError::from_try_catch(scope, "Failed to evaluate module"))
+        })?;
+
+        if result.is_promise() {
+            let promise = v8::Local::<v8::Promise>::try_from(result).unwrap();
+            Self::settle_promise(scope, promise)?;
+        }
+
+        // Set the exports object in the global scope
+        let global = scope.get_current_context().global(scope);
+        let exports = module.get_module_namespace();
+        let exports_str = v8::String::new(scope, "exports").unwrap();
+
+        // Create all necessary values before the call to set
+        let exports_val = exports;
+        let exports_str_val: v8::Local<v8::Value> = exports_str.into();
+
+        global
+            .set(scope, exports_str_val, exports_val)
+            .ok_or_else(|| SsrError::Evaluation(JsError::new("Failed to set exports")))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::LogLevel;
+    use crate::loader::InMemoryModuleLoader;
+    use crate::render_cache::{InMemoryStore, StoreRenderCache};
+    use std::num::NonZeroUsize;
+    use std::sync::Once;
+
+    static INIT: Once = Once::new();
+
+    pub fn init_test() {
+        INIT.call_once(|| {
+            Ssr::init();
+        })
+    }
+
+    fn create_ssr(source: &str, entry_point: &str, module_type: ModuleType) -> Ssr {
+        let ssr = Ssr::new();
+        ssr.load(source, entry_point, module_type).unwrap();
+        ssr
+    }
+
+    #[test]
+    fn test_render_simple_html() {
+        init_test();
+
+        let source = r##"var SSR = {x: () => "<html><body>Hello, world!</body></html>"};"##;
+
+        let ssr = create_ssr(source, "SSR", ModuleType::Cjs);
+        let html = ssr.render_to_string(None).unwrap();
+
+        assert_eq!(html, "<html><body>Hello, world!</body></html>");
+    }
+
+    #[test]
     fn test_render_with_params() {
         init_test();
 
         let source = r##"var SSR = {x: (params) => `<html><body>${params}</body></html>`};"##;
 
-        let ssr = create_ssr(source, "SSR", "cjs");
+        let ssr = create_ssr(source, "SSR", ModuleType::Cjs);
         let params = r#"{"message": "Hello, parameters!"}"#;
         let html = ssr.render_to_string(Some(params)).unwrap();
 
@@ -382,20 +1361,1174 @@ mod tests {
         }
         "##;
 
-        let ssr = create_ssr(source, "render", "esm");
+        let ssr = create_ssr(source, "render", ModuleType::Esm);
         let html = ssr.render_to_string(None).unwrap();
 
         assert_eq!(html, "<html><body>ESM Hello, world!</body></html>");
     }
 
+    #[test]
+    fn test_esm_imports() {
+        init_test();
+
+        let loader = InMemoryModuleLoader::new()
+            .with_module("pages/home.js", r#"import { layout } from "../layout.js"; export const home = () => layout("home");"#)
+            .with_module("layout.js", r#"import { count } from "./pages/counter.js"; export const layout = (page) => `<main>${page} ${count()}</main>`;"#)
+            .with_module("pages/counter.js", "let n = 0; export const count = () => ++n;");
+
+        let source = r##"
+        import { home } from "./pages/home.js";
+        import { count } from "/pages/counter.js";
+        export function render() {
+            count();
+            return home();
+        }
+        "##;
+
+        let ssr = Ssr::builder()
+            .module_loader(loader)
+            .source(source, "render")
+            .module_type(ModuleType::Esm)
+            .render_cache(false)
+            .build()
+            .unwrap();
+        // Both imports of the counter share the same instance.
+        assert_eq!(ssr.render_to_string(None).unwrap(), "<main>home 2</main>");
+
+        let missing = Ssr::builder()
+            .module_loader(InMemoryModuleLoader::new())
+            .source(
+                r#"import "./missing.js"; export const render = () => "";"#,
+                "render",
+            )
+            .module_type(ModuleType::Esm)
+            .build();
+        assert!(matches!(missing, Err(SsrError::ModuleLoad(_))));
+
+        let without_loader = Ssr::builder()
+            .source(
+                r#"import "./a.js"; export const render = () => "";"#,
+                "render",
+            )
+            .module_type(ModuleType::Esm)
+            .build();
+        assert!(matches!(without_loader, Err(SsrError::ModuleLoad(_))));
+    }
+
+    #[test]
+    fn test_dynamic_import() {
+        init_test();
+
+        let loader = InMemoryModuleLoader::new()
+            .with_module(
+                "pages/lazy.js",
+                r#"import { wrap } from "./wrap.js"; export const page = () => wrap("lazy");"#,
+            )
+            .with_module(
+                "pages/wrap.js",
+                "export const wrap = (html) => `<div>${html}</div>`;",
+            )
+            .with_module(
+                "pages/awaited.js",
+                r#"export const page = await Promise.resolve(() => "awaited");"#,
+            )
+            .with_module("pages/broken.js", r#"throw new Error("broken");"#);
+
+        let esm = r##"
+        export async function render(name) {
+            const { page } = await import(`./pages/${name}.js`);
+            return page();
+        }
+        "##;
+        let ssr = Ssr::builder()
+            .module_loader(loader.clone())
+            .source(esm, "render")
+            .module_type(ModuleType::Esm)
+            .render_cache(false)
+            .build()
+            .unwrap();
+        assert_eq!(
+            ssr.render_to_string(Some("lazy")).unwrap(),
+            "<div>lazy</div>"
+        );
+        // Imported again, the module already evaluated is reused.
+        assert_eq!(
+            ssr.render_to_string(Some("lazy")).unwrap(),
+            "<div>lazy</div>"
+        );
+        assert_eq!(ssr.render_to_string(Some("awaited")).unwrap(), "awaited");
+        assert!(matches!(
+            ssr.render_to_string(Some("missing")),
+            Err(SsrError::PromiseRejected(_))
+        ));
+        match ssr.render_to_string(Some("broken")) {
+            Err(SsrError::PromiseRejected(err)) => assert!(err.message.contains("broken")),
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        let cjs = r##"var SSR = {
+            render: () => import("./pages/lazy.js").then(({ page }) => page()),
+        };"##;
+        let ssr = Ssr::builder()
+            .module_loader(loader)
+            .source(cjs, "SSR")
+            .module_type(ModuleType::Cjs)
+            .build()
+            .unwrap();
+        assert_eq!(ssr.render_to_string(None).unwrap(), "<div>lazy</div>");
+    }
+
+    #[test]
+    fn test_commonjs() {
+        init_test();
+
+        let loader = InMemoryModuleLoader::new()
+            .with_module(
+                "lib/index.js",
+                r#"const { name } = require("../config.json");
+                const { layout } = require("./layout");
+                exports.page = () => layout(name);"#,
+            )
+            .with_module(
+                "lib/layout.js",
+                r#"const lib = require("./index.js");
+                module.exports = { layout: (html) => `<main>${html} ${typeof lib.page}</main>` };"#,
+            )
+            .with_module("config.json", r#"{ "name": "app" }"#)
+            .with_module(
+                "lib/tag.min.js",
+                r#"exports.tag = (html) => `<b>${html}</b>`;"#,
+            );
+
+        let commonjs2 = r##"
+        const { page } = require("./lib");
+        const { tag } = require("./lib/tag.min");
+        const thisIsExports = this === exports;
+        module.exports = {
+            render: () => page(),
+            tag: () => tag("min"),
+            file: () => `${__filename} ${__dirname} ${thisIsExports}`,
+        };
+        "##;
+        let ssr = Ssr::builder()
+            .module_loader(loader)
+            .source(commonjs2, "")
+            .module_type(ModuleType::Cjs)
+            .build()
+            .unwrap();
+        // The cyclic require gets the partial exports of lib/index.js.
+        assert_eq!(
+            ssr.render_export("render", None).unwrap(),
+            "<main>app undefined</main>"
+        );
+        assert_eq!(ssr.render_export("file", None).unwrap(), "module.js . true");
+        assert_eq!(ssr.render_export("tag", None).unwrap(), "<b>min</b>");
+
+        let named = r##"exports.SSR = { render: () => "<div>named</div>" };"##;
+        let ssr = create_ssr(named, "SSR", ModuleType::Cjs);
+        assert_eq!(ssr.render_to_string(None).unwrap(), "<div>named</div>");
+
+        let function = r##"module.exports = () => "<div>function</div>";"##;
+        let ssr = create_ssr(function, "", ModuleType::Cjs);
+        assert_eq!(ssr.exports(), vec!["default"]);
+        assert_eq!(ssr.render_to_string(None).unwrap(), "<div>function</div>");
+
+        let ssr = Ssr::new();
+        assert_eq!(
+            ssr.load(named, "Missing", ModuleType::Cjs),
+            Err(SsrError::EntryPointNotFound("Missing".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_node_builtins() {
+        init_test();
+
+        let cjs = r##"
+        const { format } = require("util");
+        const path = require("node:path");
+        const { Buffer } = require("buffer");
+        const { Readable, Writable } = require("stream");
+
+        module.exports = {
+            render: () => new Promise((resolve) => {
+                const chunks = [];
+                const writable = new Writable({
+                    write(chunk, encoding, callback) {
+                        chunks.push(chunk);
+                        callback();
+                    },
+                });
+                writable.on("finish", () => resolve(Buffer.concat(chunks).toString()));
+                Readable.from([
+                    Buffer.from(format("<p>%s</p>", path.join("a", "../b"))),
+                    Buffer.from("PGI+YmFzZTY0PC9iPg==", "base64"),
+                ]).pipe(writable);
+            }),
+        };
+        "##;
+        let ssr = Ssr::builder()
+            .node_builtins(true)
+            .source(cjs, "")
+            .module_type(ModuleType::Cjs)
+            .build()
+            .unwrap();
+        assert_eq!(ssr.render_to_string(None).unwrap(), "<p>b</p><b>base64</b>");
+
+        let esm = r##"
+        import EventEmitter, { once } from "node:events";
+        import { basename } from "path";
+
+        export async function render() {
+            const emitter = new EventEmitter();
+            setTimeout(() => emitter.emit("ready", basename("/a/b.js", ".js")), 0);
+            const [name] = await once(emitter, "ready");
+            const { Buffer, atob, btoa } = await import("buffer");
+            return Buffer.from(name).toString("hex") + btoa("hi") + atob("aGk");
+        }
+        "##;
+        let ssr = Ssr::builder()
+            .node_builtins(true)
+            .source(esm, "render")
+            .module_type(ModuleType::Esm)
+            .build()
+            .unwrap();
+        assert_eq!(ssr.render_to_string(None).unwrap(), "62aGk=hi");
+
+        let disabled = Ssr::builder()
+            .source(r#"require("path");"#, "")
+            .module_type(ModuleType::Cjs)
+            .build();
+        assert!(matches!(disabled, Err(SsrError::Evaluation(_))));
+    }
+
+    #[cfg(feature = "web-apis")]
+    #[test]
+    fn test_web_apis() {
+        init_test();
+
+        let source = r##"var SSR = {
+            encoding: () => {
+                const bytes = new TextEncoder().encode("héllo €");
+                const decoder = new TextDecoder();
+                const text = decoder.decode(bytes.subarray(0, 8), { stream: true })
+                    + decoder.decode(bytes.subarray(8));
+                return `${bytes.length}:${text}`;
+            },
+            url: () => {
+                const url = new URL("../b?x=1#top", "https://example.com/a/c");
+                url.searchParams.append("y", "a b");
+                url.hash = "";
+                return `${url.href} ${url.origin} ${url.searchParams.get("x")}`;
+            },
+            base64: () => {
+                let error;
+                try {
+                    btoa("€");
+                } catch (err) {
+                    error = err.name;
+                }
+                return `${btoa("hi")} ${atob("aGk")} ${error}`;
+            },
+        };"##;
+
+        let ssr = create_ssr(source, "SSR", ModuleType::Cjs);
+        assert_eq!(ssr.render_export("encoding", None).unwrap(), "10:héllo €");
+        assert_eq!(
+            ssr.render_export("url", None).unwrap(),
+            "https://example.com/b?x=1&y=a+b https://example.com 1"
+        );
+        assert_eq!(
+            ssr.render_export("base64", None).unwrap(),
+            "aGk= hi InvalidCharacterError"
+        );
+
+        let invalid = r##"var SSR = {x: () => new URL("not a url").href};"##;
+        let ssr = create_ssr(invalid, "SSR", ModuleType::Cjs);
+        assert!(matches!(
+            ssr.render_to_string(None),
+            Err(SsrError::Evaluation(_))
+        ));
+    }
+
     #[test]
     fn test_invalid_js() {
         init_test();
 
         let source = r##"var SSR = {x: () => { throw new Error("Test error"); }};"##;
 
-        let ssr = create_ssr(source, "SSR", "cjs");
+        let ssr = create_ssr(source, "SSR", ModuleType::Cjs);
         let result = ssr.render_to_string(None);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_entry_not_function() {
+        init_test();
+
+        let source = r##"export const render = "<html></html>";"##;
+
+        let ssr = Ssr::new();
+        let result = ssr.load(source, "render", ModuleType::Esm);
+
+        assert_eq!(
+            result,
+            Err(SsrError::EntryNotFunction("render".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_entry_without_functions() {
+        init_test();
+
+        // An IIFE bundle loaded as CommonJS: `module.exports` stays empty.
+        let iife = r##"(function (exports) {
+            exports.Index = () => "<div>iife</div>";
+            return exports;
+        })({});"##;
+        let ssr = Ssr::new();
+        assert_eq!(
+            ssr.load(iife, "", ModuleType::Cjs),
+            Err(SsrError::EntryPointNotFound("default".to_string()))
+        );
+
+        let source = r##"var SSR = { title: "<html></html>" };"##;
+        let ssr = Ssr::new();
+        assert_eq!(
+            ssr.load(source, "SSR", ModuleType::Cjs),
+            Err(SsrError::EntryPointNotFound("SSR".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_js_exception_details() {
+        init_test();
+
+        let source = r##"var SSR = {x: () => { throw new Error("Test error"); }};"##;
+
+        let ssr = create_ssr(source, "SSR", ModuleType::Cjs);
+        let err = match ssr.render_to_string(None) {
+            Err(SsrError::Evaluation(err)) => err,
+            other => panic!("unexpected result: {other:?}"),
+        };
+
+        assert_eq!(err.message, "Error: Test error");
+        assert!(err.stack.unwrap().contains("Test error"));
+        assert_eq!(err.line, Some(1));
+        assert!(err.column.is_some());
+    }
+
+    #[test]
+    fn test_source_mapped_exception() {
+        use base64::Engine;
+
+        init_test();
+
+        let source_map =
+            r#"{"version":3,"sources":["src/App.tsx"],"names":[],"mappings":"AAAA;EACE"}"#;
+        let source = format!(
+            "var SSR = {{x: () => {{\n  throw new Error(\"boom\"); }}}};\n//# sourceMappingURL=data:application/json;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(source_map)
+        );
+
+        let ssr = create_ssr(&source, "SSR", ModuleType::Cjs);
+        let err = match ssr.render_to_string(None) {
+            Err(SsrError::Evaluation(err)) => err,
+            other => panic!("unexpected result: {other:?}"),
+        };
+
+        assert_eq!(err.script_name.as_deref(), Some("src/App.tsx"));
+        assert_eq!(err.line, Some(2));
+        assert!(err.stack.unwrap().contains("src/App.tsx:2:3"));
+    }
+
+    #[test]
+    fn test_render_timeout() {
+        init_test();
+
+        let source = r##"var SSR = {x: (p) => { while (p === "loop") {} return "ok"; }};"##;
+
+        let ssr = create_ssr(source, "SSR", ModuleType::Cjs);
+        ssr.set_timeout(Some(Duration::from_millis(100)));
+
+        let result = ssr.render_to_string(Some("loop"));
+        assert_eq!(result, Err(SsrError::Timeout(Duration::from_millis(100))));
+
+        // The instance is still usable after the termination.
+        assert_eq!(ssr.render_to_string(None).unwrap(), "ok");
+
+        let result = ssr.render_to_string_with_timeout(Some("loop"), Duration::from_millis(50));
+        assert_eq!(result, Err(SsrError::Timeout(Duration::from_millis(50))));
+    }
+
+    #[test]
+    fn test_unsettled_promise() {
+        init_test();
+
+        let source = r##"var SSR = {x: () => new Promise(() => {})};"##;
+
+        let ssr = create_ssr(source, "SSR", ModuleType::Cjs);
+        assert_eq!(ssr.render_to_string(None), Err(SsrError::UnsettledPromise));
+    }
+
+    #[test]
+    fn test_timers() {
+        init_test();
+
+        let source = r##"var SSR = {
+            render: () => new Promise((resolve) => {
+                const log = [];
+                setTimeout((a, b) => log.push(`timeout ${a}${b}`), 20, "x", "y");
+                const cancelled = setTimeout(() => log.push("cancelled"), 10);
+                clearTimeout(cancelled);
+                let ticks = 0;
+                const interval = setInterval(() => {
+                    log.push(`tick ${++ticks}`);
+                    if (ticks === 2) clearInterval(interval);
+                }, 5);
+                queueMicrotask(() => log.push("microtask"));
+                setTimeout(() => resolve(log.join(",")), 30);
+            }),
+        };"##;
+
+        let ssr = create_ssr(source, "SSR", ModuleType::Cjs);
+        assert_eq!(
+            ssr.render_to_string(None).unwrap(),
+            "microtask,tick 1,tick 2,timeout xy"
+        );
+    }
+
+    #[test]
+    fn test_huge_timer_delays() {
+        init_test();
+
+        let source = r##"var SSR = {
+            render: () => new Promise((resolve) => {
+                const log = [];
+                setTimeout(() => log.push("timeout"), 1e30);
+                const interval = setInterval(() => {
+                    log.push("interval");
+                    clearInterval(interval);
+                }, Number.MAX_VALUE);
+                setTimeout(() => log.push("infinity"), Infinity);
+                setTimeout(() => resolve(log.join(",")), 10);
+            }),
+        };"##;
+
+        let ssr = create_ssr(source, "SSR", ModuleType::Cjs);
+        assert_eq!(
+            ssr.render_to_string(None).unwrap(),
+            "timeout,interval,infinity"
+        );
+    }
+
+    #[test]
+    fn test_virtual_time() {
+        init_test();
+
+        let source = r##"var SSR = {
+            later: () => new Promise((resolve) => setTimeout(() => resolve("done"), 60 * 60 * 1000)),
+            never: () => new Promise(() => setInterval(() => {}, 1000)),
+        };"##;
+
+        let ssr = Ssr::builder()
+            .virtual_time(true)
+            .timeout(Duration::from_millis(200))
+            .source(source, "SSR")
+            .module_type(ModuleType::Cjs)
+            .build()
+            .unwrap();
+
+        let start = Instant::now();
+        assert_eq!(ssr.render_export("later", None).unwrap(), "done");
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        assert_eq!(
+            ssr.render_export("never", None),
+            Err(SsrError::Timeout(Duration::from_millis(200)))
+        );
+    }
+
+    #[test]
+    fn test_intervals_only() {
+        init_test();
+
+        let source = r##"var SSR = {
+            polling: () => new Promise((resolve) => {
+                let ticks = 0;
+                const interval = setInterval(() => {
+                    if (++ticks === 10) {
+                        clearInterval(interval);
+                        resolve(`ticks ${ticks}`);
+                    }
+                }, 1000);
+            }),
+            never: () => new Promise(() => setInterval(() => {}, 1000)),
+        };"##;
+
+        let ssr = Ssr::builder()
+            .virtual_time(true)
+            .render_cache(false)
+            .source(source, "SSR")
+            .module_type(ModuleType::Cjs)
+            .build()
+            .unwrap();
+
+        assert_eq!(ssr.render_export("polling", None).unwrap(), "ticks 10");
+        assert_eq!(
+            ssr.render_export("never", None),
+            Err(SsrError::UnsettledPromise)
+        );
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        assert_eq!(
+            runtime.block_on(ssr.render(None)),
+            Err(SsrError::UnsettledPromise)
+        );
+    }
+
+    #[test]
+    fn test_timer_timeout() {
+        init_test();
+
+        let source = r##"var SSR = {
+            x: () => new Promise((resolve) => setTimeout(resolve, 10 * 1000)),
+        };"##;
+
+        let ssr = create_ssr(source, "SSR", ModuleType::Cjs);
+        let start = Instant::now();
+        assert_eq!(
+            ssr.render_to_string_with_timeout(None, Duration::from_millis(50)),
+            Err(SsrError::Timeout(Duration::from_millis(50)))
+        );
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_console() {
+        init_test();
+
+        let source = r##"var SSR = {
+            x: () => {
+                console.log("hello %s, %d%%", "world", 42.5, { a: [1] });
+                console.warn("warning");
+                console.assert(1 + 1 === 3, "math");
+                console.debug(undefined, null, () => {});
+                console.group("group %d", 1);
+                console.groupCollapsed();
+                console.table([1, 2]);
+                console.time("render");
+                console.timeLog("render");
+                console.timeEnd("render");
+                console.count();
+                console.countReset();
+                console.groupEnd();
+                console.groupEnd();
+                return "done";
+            },
+        };"##;
+
+        let messages = Rc::new(RefCell::new(Vec::new()));
+        let sink = messages.clone();
+        let ssr = Ssr::builder()
+            .console(move |message| sink.borrow_mut().push(message.clone()))
+            .source(source, "SSR")
+            .module_type(ModuleType::Cjs)
+            .render_cache(false)
+            .build()
+            .unwrap();
+
+        let message = |level, message: &str| ConsoleMessage {
+            level,
+            message: message.to_string(),
+        };
+        let expected = vec![
+            message(LogLevel::Info, r#"hello world, 42.5% {"a":[1]}"#),
+            message(LogLevel::Warn, "warning"),
+            message(LogLevel::Error, "Assertion failed: math"),
+            message(LogLevel::Debug, "undefined null [Function (anonymous)]"),
+            message(LogLevel::Info, "group 1"),
+            message(LogLevel::Info, "[1,2]"),
+        ];
+
+        assert_eq!(ssr.render_to_string(None).unwrap(), "done");
+        assert_eq!(*messages.borrow(), expected);
+
+        let (html, captured) = ssr.capture_console(|ssr| ssr.render_to_string(None));
+        assert_eq!(html.unwrap(), "done");
+        assert_eq!(captured, expected);
+        assert_eq!(messages.borrow().len(), expected.len());
+    }
+
+    #[test]
+    fn test_heap_limit() {
+        init_test();
+
+        let source = r##"var SSR = {x: () => {
+            const chunks = [];
+            while (true) { chunks.push(new Array(100000).fill("leak")); }
+        }};"##;
+
+        let ssr = Ssr::with_heap_limits(0, 16 * 1024 * 1024);
+        ssr.load(source, "SSR", ModuleType::Cjs).unwrap();
+
+        assert!(!ssr.needs_recycle());
+        assert_eq!(ssr.render_to_string(None), Err(SsrError::OutOfMemory));
+        assert!(ssr.needs_recycle());
+    }
+
+    #[test]
+    fn test_builder() {
+        init_test();
+
+        let source = r##"var n = 0; var SSR = {x: () => `${CONFIG.title} ${++n}`};"##;
+
+        let ssr = Ssr::builder()
+            .global("CONFIG", &HashMap::from([("title", "Hello")]))
+            .render_cache(false)
+            .source(source, "SSR")
+            .module_type(ModuleType::Cjs)
+            .build()
+            .unwrap();
+
+        assert_eq!(ssr.render_to_string(None).unwrap(), "Hello 1");
+        assert_eq!(ssr.render_to_string(None).unwrap(), "Hello 2");
+
+        let ssr = Ssr::builder().source(source, "SSR").build().unwrap();
+        let err = ssr.render_to_string(None).unwrap_err();
+        assert!(err
+            .js_error()
+            .unwrap()
+            .message
+            .contains("CONFIG is not defined"));
+    }
+
+    #[test]
+    fn test_flags_after_init() {
+        init_test();
+
+        assert_eq!(
+            Ssr::init_with_flags("--no-opt"),
+            Err(SsrError::AlreadyInitialized)
+        );
+    }
+
+    #[test]
+    fn test_iife_and_auto_module_types() {
+        init_test();
+
+        let iife = r##"(function (exports) {
+            exports.Index = () => "<div>iife</div>";
+            return exports;
+        })({});"##;
+        let ssr = create_ssr(iife, "", ModuleType::Iife);
+        assert_eq!(ssr.render_to_string(None).unwrap(), "<div>iife</div>");
+
+        let esm = r##"
+        function render() {
+            return "<div>auto</div>";
+        }
+        export { render };
+        "##;
+        let ssr = create_ssr(esm, "render", ModuleType::Auto);
+        assert_eq!(ssr.render_to_string(None).unwrap(), "<div>auto</div>");
+
+        let minified = r##"const r=()=>"<div>minified</div>";export{r as render}"##;
+        let ssr = create_ssr(minified, "render", ModuleType::Auto);
+        assert_eq!(ssr.render_to_string(None).unwrap(), "<div>minified</div>");
+
+        // Missed by the detection, the module syntax fails to compile as CommonJS.
+        let missed = r##"const r=()=>"<div>missed</div>";/*! v1 */export{r as render}"##;
+        let ssr = create_ssr(missed, "render", ModuleType::Auto);
+        assert_eq!(ssr.render_to_string(None).unwrap(), "<div>missed</div>");
+    }
+
+    #[test]
+    fn test_render_export() {
+        init_test();
+
+        let source = r##"var SSR = {
+            renderHead: () => "<head></head>",
+            renderBody: (props) => `<body>${props}</body>`,
+            version: 1,
+        };"##;
+
+        let ssr = create_ssr(source, "SSR", ModuleType::Cjs);
+
+        assert_eq!(ssr.exports(), vec!["renderHead", "renderBody"]);
+        assert_eq!(
+            ssr.render_export("renderBody", Some("hi")).unwrap(),
+            "<body>hi</body>"
+        );
+        assert_eq!(
+            ssr.render_export("renderHead", Some("hi")).unwrap(),
+            "<head></head>"
+        );
+        assert_eq!(
+            ssr.render_to_string(Some("hi")).unwrap(),
+            "<head></head><body>hi</body>"
+        );
+        assert_eq!(
+            ssr.render_export("version", None),
+            Err(SsrError::ExportNotFound("version".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_render_cache() {
+        init_test();
+
+        let source = r##"var renders = 0;
+        var SSR = {x: (props) => `${props}:${++renders}`};"##;
+
+        let ssr = Ssr::builder()
+            .render_cache_size(NonZeroUsize::new(2).unwrap())
+            .source(source, "SSR")
+            .module_type(ModuleType::Cjs)
+            .build()
+            .unwrap();
+
+        assert_eq!(ssr.render_to_string(Some("a")).unwrap(), "a:1");
+        assert_eq!(ssr.render_to_string(Some("b")).unwrap(), "b:2");
+        assert_eq!(ssr.render_to_string(Some("a")).unwrap(), "a:1");
+
+        // `b` is dropped as the least recently used result.
+        assert_eq!(ssr.render_to_string(Some("c")).unwrap(), "c:3");
+        assert_eq!(ssr.render_to_string(Some("b")).unwrap(), "b:4");
+
+        ssr.invalidate("b");
+        assert_eq!(ssr.render_to_string(Some("b")).unwrap(), "b:5");
+        assert_eq!(
+            ssr.cache_stats(),
+            CacheStats {
+                hits: 1,
+                misses: 5,
+                evictions: 2,
+                entries: 2,
+                bytes: 10,
+            }
+        );
+
+        ssr.invalidate_all();
+        assert_eq!(ssr.render_to_string(Some("c")).unwrap(), "c:6");
+
+        let disabled = Ssr::builder()
+            .render_cache(false)
+            .source(source, "SSR")
+            .module_type(ModuleType::Cjs)
+            .build()
+            .unwrap();
+        assert_eq!(disabled.render_to_string(Some("a")).unwrap(), "a:1");
+        assert_eq!(disabled.render_to_string(Some("a")).unwrap(), "a:2");
+        assert_eq!(disabled.cache_stats(), CacheStats::default());
+    }
+
+    #[test]
+    fn test_cache_key_and_hints() {
+        init_test();
+
+        let source = r##"var renders = 0;
+        var SSR = {x: (props) => {
+            renders++;
+            if (props.user) return { html: `${props.user}:${renders}`, cacheable: false };
+            return { html: `${props.page}:${renders}`, maxAge: props.maxAge };
+        }};"##;
+
+        let ssr = Ssr::builder()
+            .cache_key_ignoring(&["csrf"])
+            .source(source, "SSR")
+            .module_type(ModuleType::Cjs)
+            .build()
+            .unwrap();
+
+        let render = |props: serde_json::Value| ssr.render_with(&props).unwrap();
+        assert_eq!(
+            render(serde_json::json!({ "page": "a", "csrf": "1" })),
+            "a:1"
+        );
+        assert_eq!(
+            render(serde_json::json!({ "csrf": "2", "page": "a" })),
+            "a:1"
+        );
+
+        assert_eq!(render(serde_json::json!({ "user": "u" })), "u:2");
+        assert_eq!(render(serde_json::json!({ "user": "u" })), "u:3");
+
+        assert_eq!(
+            render(serde_json::json!({ "page": "b", "maxAge": 0 })),
+            "b:4"
+        );
+        assert_eq!(
+            render(serde_json::json!({ "page": "b", "maxAge": 0 })),
+            "b:5"
+        );
+
+        ssr.invalidate(r#"{"page":"a"}"#);
+        assert_eq!(render(serde_json::json!({ "page": "a" })), "a:6");
+
+        // Invalid hints are ignored rather than failing the render.
+        assert_eq!(
+            render(serde_json::json!({ "page": "c", "maxAge": -1 })),
+            "c:7"
+        );
+        assert_eq!(
+            render(serde_json::json!({ "page": "c", "maxAge": -1 })),
+            "c:7"
+        );
+        assert_eq!(
+            render(serde_json::json!({ "page": "d", "maxAge": "soon" })),
+            "d:8"
+        );
+    }
+
+    #[test]
+    fn test_html_object_other_fields() {
+        init_test();
+
+        let source = r##"var SSR = {
+            x: () => ({ html: "<p>page</p>", status: "not found", headers: 1, cacheable: "no" }),
+        };"##;
+
+        let ssr = create_ssr(source, "SSR", ModuleType::Cjs);
+        assert_eq!(ssr.render_to_string(None).unwrap(), "<p>page</p>");
+        assert_eq!(ssr.cache_stats().entries, 1);
+    }
+
+    #[test]
+    fn test_shared_render_cache() {
+        init_test();
+
+        let source = r##"var renders = 0;
+        var SSR = {x: (props) => `${props}:${++renders}`};"##;
+        let build = |cache: Arc<dyn RenderCache>| {
+            Ssr::builder()
+                .render_cache_backend(cache)
+                .source(source, "SSR")
+                .module_type(ModuleType::Cjs)
+                .build()
+                .unwrap()
+        };
+
+        let cache = Arc::new(MemoryRenderCache::default());
+        let first = build(cache.clone());
+        let second = build(cache.clone());
+        assert_eq!(first.render_to_string(Some("a")).unwrap(), "a:1");
+        assert_eq!(second.render_to_string(Some("a")).unwrap(), "a:1");
+
+        second.invalidate("a");
+        assert_eq!(first.render_to_string(Some("a")).unwrap(), "a:2");
+        assert_eq!(cache.stats().hits, 1);
+
+        let store = Arc::new(StoreRenderCache::new(InMemoryStore::new()));
+        let first = build(store.clone());
+        let second = build(store.clone());
+        assert_eq!(first.render_to_string(Some("b")).unwrap(), "b:1");
+        assert_eq!(second.render_to_string(Some("b")).unwrap(), "b:1");
+        assert_eq!(store.store().len(), 1);
+
+        second.invalidate_all();
+        assert!(store.store().is_empty());
+    }
+
+    #[test]
+    fn test_code_cache() {
+        init_test();
+
+        let dir =
+            std::env::temp_dir().join(format!("ssr_rs_test_code_cache_{}", std::process::id()));
+        let source = r##"var SSR = {x: () => "<p>cached</p>"};"##;
+        let render = || {
+            let ssr = Ssr::builder()
+                .code_cache_dir(&dir)
+                .source(source, "SSR")
+                .module_type(ModuleType::Cjs)
+                .build()
+                .unwrap();
+            ssr.render_to_string(None).unwrap()
+        };
+        let cache_file = || {
+            let mut entries = std::fs::read_dir(&dir).unwrap();
+            let path = entries.next().unwrap().unwrap().path();
+            assert!(entries.next().is_none());
+            path
+        };
+
+        assert_eq!(render(), "<p>cached</p>");
+        let path = cache_file();
+        let data = std::fs::read(&path).unwrap();
+        assert!(!data.is_empty());
+
+        assert_eq!(render(), "<p>cached</p>");
+        assert_eq!(std::fs::read(cache_file()).unwrap(), data);
+
+        // A cache V8 rejects is replaced.
+        std::fs::write(&path, b"not a code cache").unwrap();
+        assert_eq!(render(), "<p>cached</p>");
+        assert_eq!(std::fs::read(cache_file()).unwrap(), data);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot() {
+        init_test();
+
+        let source = r##"var renders = 0;
+        var greeting = ["Hello", APP].join(", ");
+        var SSR = {
+            x: () => `${greeting}:${++renders}`,
+            y: () => { console.log("y"); return typeof setTimeout; },
+        };"##;
+        let snapshot = Ssr::builder()
+            .global("APP", &"snapshot")
+            .source(source, "SSR")
+            .module_type(ModuleType::Cjs)
+            .create_snapshot()
+            .unwrap();
+        let snapshot = Snapshot::from_bytes(&snapshot.to_bytes()).unwrap();
+
+        for _ in 0..2 {
+            let ssr = Ssr::builder()
+                .snapshot(snapshot.clone())
+                .render_cache(false)
+                .build()
+                .unwrap();
+            assert_eq!(ssr.exports(), ["x", "y"]);
+            assert_eq!(ssr.render_export("x", None).unwrap(), "Hello, snapshot:1");
+            assert_eq!(ssr.render_export("x", None).unwrap(), "Hello, snapshot:2");
+
+            let (result, logs) = ssr.capture_console(|ssr| ssr.render_export("y", None));
+            assert_eq!(result.unwrap(), "function");
+            assert_eq!(logs[0].message, "y");
+        }
+
+        let err = Ssr::builder()
+            .snapshot(snapshot)
+            .create_snapshot()
+            .unwrap_err();
+        assert!(matches!(err, SsrError::InvalidSnapshot(_)));
+        assert!(matches!(
+            Snapshot::from_bytes(b"snapshot"),
+            Err(SsrError::InvalidSnapshot(_))
+        ));
+    }
+
+    #[test]
+    fn test_render_with_serde() {
+        init_test();
+
+        #[derive(serde::Serialize)]
+        struct Props {
+            title: &'static str,
+            items: Vec<u32>,
+        }
+
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        struct Page {
+            html: String,
+            count: u32,
+        }
+
+        let source = r##"var SSR = {
+            render: (props) => `<h1>${props.title}</h1>${props.items.join(",")}`,
+            page: (props) => Promise.resolve({ html: props.title, count: props.items.length, skip: () => {} }),
+        };"##;
+
+        let ssr = create_ssr(source, "SSR", ModuleType::Cjs);
+        let props = Props {
+            title: "Hi",
+            items: vec![1, 2, 3],
+        };
+
+        assert_eq!(
+            ssr.render_export_with("render", &props).unwrap(),
+            "<h1>Hi</h1>1,2,3"
+        );
+        assert_eq!(
+            ssr.render_export_as::<Page, _>("page", &props).unwrap(),
+            Page {
+                html: "Hi".to_string(),
+                count: 3
+            }
+        );
+    }
+
+    #[test]
+    fn test_render_to_stream() {
+        init_test();
+
+        let source = r##"var SSR = {
+            shell: (props, write) => {
+                write(`<head>${props}</head>`);
+                return Promise.resolve().then(() => {
+                    write("<body>");
+                    write(new Uint8Array([104, 105]));
+                    return "</body>";
+                });
+            },
+            stream: () => {
+                const chunks = ["<footer>", "</footer>"];
+                return { getReader: () => ({ read: async () => ({ done: !chunks.length, value: chunks.shift() }) }) };
+            },
+            stuck: (props, write) => {
+                write("<p>");
+                return new Promise(() => {});
+            },
+            failing: async (props, write) => {
+                write("<p>");
+                throw new Error("boom");
+            },
+        };"##;
+
+        let ssr = create_ssr(source, "SSR", ModuleType::Cjs);
+        let chunks = |stream: RenderStream| {
+            stream
+                .map(|chunk| chunk.map(|chunk| String::from_utf8(chunk).unwrap()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            chunks(ssr.render_export_to_stream("shell", Some("x")).unwrap()),
+            vec![
+                Ok("<head>x</head>".to_string()),
+                Ok("<body>".to_string()),
+                Ok("hi".to_string()),
+                Ok("</body>".to_string()),
+            ]
+        );
+        assert_eq!(
+            chunks(ssr.render_export_to_stream("stream", None).unwrap()),
+            vec![Ok("<footer>".to_string()), Ok("</footer>".to_string())]
+        );
+        assert_eq!(
+            chunks(ssr.render_export_to_stream("stuck", None).unwrap()),
+            vec![Ok("<p>".to_string()), Err(SsrError::UnsettledPromise)]
+        );
+
+        let failing = chunks(ssr.render_export_to_stream("failing", None).unwrap());
+        assert_eq!(failing[0], Ok("<p>".to_string()));
+        assert!(
+            matches!(&failing[1], Err(SsrError::PromiseRejected(err)) if err.message.contains("boom"))
+        );
+        assert_eq!(failing.len(), 2);
+
+        assert_eq!(
+            ssr.render_to_stream(None).unwrap().take(5).count(),
+            5,
+            "the render functions are streamed one after the other"
+        );
+        assert!(matches!(
+            ssr.render_export_to_stream("missing", None),
+            Err(SsrError::ExportNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_async_render() {
+        init_test();
+
+        #[derive(serde::Deserialize)]
+        struct Query {
+            id: u32,
+        }
+
+        let source = r##"var SSR = {
+            render: async (props) => {
+                const [user, post] = await Promise.all([fetchUser({ id: 1 }), fetchPost({ id: Number(props) })]);
+                return `<p>${user.name}: ${post}</p>`;
+            },
+        };"##;
+
+        let ssr = Ssr::builder()
+            .render_cache(false)
+            .async_function("fetchUser", |query: Query| async move {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Ok::<_, String>(serde_json::json!({ "name": format!("user {}", query.id) }))
+            })
+            .async_function("fetchPost", |query: Query| async move {
+                tokio::time::sleep(Duration::from_millis(10 * query.id as u64)).await;
+                match query.id {
+                    404 => Err(format!("post {} not found", query.id)),
+                    id => Ok(format!("post {id}")),
+                }
+            })
+            .source(source, "SSR")
+            .module_type(ModuleType::Cjs)
+            .build()
+            .unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            assert_eq!(
+                ssr.render(Some("2")).await.unwrap(),
+                "<p>user 1: post 2</p>"
+            );
+            assert!(matches!(
+                ssr.render(Some("404")).await,
+                Err(SsrError::PromiseRejected(err)) if err.message.contains("post 404 not found")
+            ));
+
+            ssr.set_timeout(Some(Duration::from_millis(50)));
+            assert_eq!(
+                ssr.render(Some("100")).await,
+                Err(SsrError::Timeout(Duration::from_millis(50)))
+            );
+        });
+
+        assert_eq!(
+            ssr.render_to_string(Some("2")),
+            Err(SsrError::UnsettledPromise)
+        );
+    }
+
+    #[test]
+    fn test_render_output() {
+        init_test();
+
+        let source = r##"var SSR = {
+            page: (props) => ({ html: `<p>${props.path}</p>`, head: "<title>Page</title>", css: { code: "p{}" } }),
+            missing: () => Promise.resolve({ body: "Not found", status: 404, headers: { "cache-control": "no-store" } }),
+            login: () => ({ redirect: "/login" }),
+            plain: () => "<div></div>",
+            invalid: () => ({ status: "teapot" }),
+        };"##;
+
+        let ssr = create_ssr(source, "SSR", ModuleType::Cjs);
+
+        let output = ssr
+            .render_output_with("page", &serde_json::json!({ "path": "/" }))
+            .unwrap();
+        assert_eq!(output.html, "<p>/</p>");
+        assert_eq!(output.head, "<title>Page</title>");
+        assert_eq!(output.css, "p{}");
+        assert_eq!(output.status, 200);
+
+        let output = ssr.render_output("missing", None).unwrap();
+        assert_eq!(output.html, "Not found");
+        assert_eq!(output.status, 404);
+        assert_eq!(
+            output.headers,
+            vec![("cache-control".to_string(), "no-store".to_string())]
+        );
+
+        let output = ssr.render_output("login", None).unwrap();
+        assert!(output.is_redirect());
+        assert_eq!(output.status, 302);
+
+        assert_eq!(
+            ssr.render_output("plain", None).unwrap().html,
+            "<div></div>"
+        );
+        assert!(matches!(
+            ssr.render_output("invalid", None),
+            Err(SsrError::InvalidOutput(_))
+        ));
+    }
 }
@@ -0,0 +1,372 @@
+use crate::error::{JsError, SsrError};
+use crate::event_loop::EventLoop;
+use crate::ssr::Ssr;
+use bytes::Bytes;
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+use v8::{Function, Global, Local, PromiseState, Value};
+
+/// Builds the `write` callback handed to the render functions: it only collects the chunks in a
+/// JS array, drained each time the stream is polled.
+const WRITER_SOURCE: &str =
+    "(function (chunks) { return function write(chunk) { chunks.push(chunk); }; })";
+
+/// Where the chunks of the running render function come from.
+enum Source {
+    /// The next render function has not been called yet.
+    Idle,
+    /// Chunks are given to `write` until the returned promise settles.
+    Writer(Global<v8::Promise>),
+    /// Chunks are read from the reader of a returned `ReadableStream`.
+    Reader(Global<v8::Object>),
+    /// All the render functions are done.
+    Done,
+}
+
+/// The chunks of a render, returned by [`Ssr::render_to_stream`].
+///
+/// The render functions are called with the props and a `write(chunk)` callback. Chunks can be
+/// strings or `Uint8Array`s, and are produced either by:
+/// - calling `write` until the promise returned by the render function settles;
+/// - returning a `ReadableStream` (or any object with a `getReader()` method);
+/// - returning a string, emitted as the last chunk.
+///
+/// The JS code runs as the stream is polled: each call to [`Iterator::next`] runs it until at
+/// least one chunk is available, running the timers the render functions wait for. The timeout
+/// set with [`Ssr::set_timeout`] applies to each of these steps. After an error the stream ends.
+pub struct RenderStream<'a> {
+    ssr: &'a Ssr,
+    functions: VecDeque<Global<Function>>,
+    props: Global<Value>,
+    write: Global<Function>,
+    written: Global<v8::Array>,
+    source: Source,
+    chunks: VecDeque<Vec<u8>>,
+}
+
+impl<'a> RenderStream<'a> {
+    pub(crate) fn new(
+        ssr: &'a Ssr,
+        functions: VecDeque<Global<Function>>,
+        params: Option<&str>,
+    ) -> Result<Self, SsrError> {
+        ssr.event_loop().clear();
+        let (props, write, written) = ssr.run_in_context(|scope| {
+            let props: Local<Value> = match params {
+                Some(params) => v8::String::new(scope, params)
+                    .ok_or_else(|| SsrError::Serialization("String too long for V8".to_string()))?
+                    .into(),
+                None => v8::undefined(scope).into(),
+            };
+
+            let source = v8::String::new(scope, WRITER_SOURCE).unwrap();
+            let factory = v8::Script::compile(scope, source, None)
+                .and_then(|script| script.run(scope))
+                .and_then(|factory| Local::<Function>::try_from(factory).ok())
+                .ok_or_else(|| {
+                    SsrError::Evaluation(JsError::from_try_catch(
+                        scope,
+                        "Failed to create the writer",
+                    ))
+                })?;
+
+            let written = v8::Array::new(scope, 0);
+            let undef = v8::undefined(scope).into();
+            let write = factory
+                .call(scope, undef, &[written.into()])
+                .and_then(|write| Local::<Function>::try_from(write).ok())
+                .ok_or_else(|| {
+                    SsrError::Evaluation(JsError::from_try_catch(
+                        scope,
+                        "Failed to create the writer",
+                    ))
+                })?;
+
+            Ok((
+                Global::new(scope, props),
+                Global::new(scope, write),
+                Global::new(scope, written),
+            ))
+        })?;
+
+        Ok(RenderStream {
+            ssr,
+            functions,
+            props,
+            write,
+            written,
+            source: Source::Idle,
+            chunks: VecDeque::new(),
+        })
+    }
+
+    /// Runs the JS code until new chunks are available or the current source is done.
+    fn pump(&mut self) -> Result<(), SsrError> {
+        let ssr = self.ssr;
+        ssr.run_in_context(|scope| {
+            let scope: &mut v8::HandleScope = scope;
+            let source = std::mem::replace(&mut self.source, Source::Done);
+
+            self.source = match source {
+                Source::Idle => self.call_next(scope)?,
+                Source::Writer(promise) => self.poll_writer(scope, promise)?,
+                Source::Reader(reader) => self.poll_reader(scope, reader)?,
+                Source::Done => Source::Done,
+            };
+            self.drain_written(scope)?;
+            Ok(())
+        })
+    }
+
+    fn call_next(&mut self, scope: &mut v8::HandleScope) -> Result<Source, SsrError> {
+        let Some(func) = self.functions.pop_front() else {
+            return Ok(Source::Done);
+        };
+
+        let scope = &mut v8::TryCatch::new(scope);
+        let func = Local::new(scope, &func);
+        let props = Local::new(scope, &self.props);
+        let write = Local::new(scope, &self.write);
+        let undef = v8::undefined(scope).into();
+
+        let result = func
+            .call(scope, undef, &[props, write.into()])
+            .ok_or_else(|| {
+                SsrError::Evaluation(JsError::from_try_catch(scope, "Failed to call function"))
+            })?;
+
+        self.source_from(scope, result)
+    }
+
+    /// Picks how the chunks are read from what a render function returned (or resolved to).
+    fn source_from<'s>(
+        &mut self,
+        scope: &mut v8::TryCatch<v8::HandleScope<'s>>,
+        value: Local<'s, Value>,
+    ) -> Result<Source, SsrError> {
+        if let Ok(promise) = Local::<v8::Promise>::try_from(value) {
+            return Ok(Source::Writer(Global::new(scope, promise)));
+        }
+
+        if let Some(reader) = Self::call_method(scope, value, "getReader")? {
+            let reader = reader.to_object(scope).ok_or_else(|| {
+                SsrError::Evaluation(JsError::new("getReader() did not return a reader"))
+            })?;
+            return Ok(Source::Reader(Global::new(scope, reader)));
+        }
+
+        if !value.is_null_or_undefined() {
+            self.push_chunk(scope, value)?;
+        }
+        Ok(self.next_source())
+    }
+
+    fn poll_writer(
+        &mut self,
+        scope: &mut v8::HandleScope,
+        promise: Global<v8::Promise>,
+    ) -> Result<Source, SsrError> {
+        let event_loop = EventLoop::get(scope);
+        let scope = &mut v8::TryCatch::new(scope);
+        let local = Local::new(scope, &promise);
+
+        loop {
+            if local.state() == PromiseState::Pending {
+                scope.perform_microtask_checkpoint();
+            }
+            let written = self.drain_written(scope)?;
+
+            match local.state() {
+                PromiseState::Pending if written => return Ok(Source::Writer(promise)),
+                PromiseState::Pending => {
+                    if !event_loop.run_next_timers(scope)? {
+                        return Err(SsrError::UnsettledPromise);
+                    }
+                }
+                PromiseState::Rejected => {
+                    let reason = local.result(scope);
+                    return Err(SsrError::PromiseRejected(JsError::from_exception(
+                        scope, reason,
+                    )));
+                }
+                PromiseState::Fulfilled => {
+                    let value = local.result(scope);
+                    return self.source_from(scope, value);
+                }
+            }
+        }
+    }
+
+    fn poll_reader(
+        &mut self,
+        scope: &mut v8::HandleScope,
+        reader: Global<v8::Object>,
+    ) -> Result<Source, SsrError> {
+        let scope = &mut v8::TryCatch::new(scope);
+        let local = Local::new(scope, &reader);
+
+        let result = Self::call_method(scope, local.into(), "read")?
+            .ok_or_else(|| SsrError::Evaluation(JsError::new("The reader has no read() method")))?;
+        let result = match Local::<v8::Promise>::try_from(result) {
+            Ok(promise) => Ssr::settle_promise(scope, promise)?,
+            Err(_) => result,
+        };
+        let result = result
+            .to_object(scope)
+            .ok_or_else(|| SsrError::Evaluation(JsError::new("read() did not return an object")))?;
+
+        let done = v8::String::new(scope, "done").unwrap();
+        if result
+            .get(scope, done.into())
+            .is_some_and(|done| done.boolean_value(scope))
+        {
+            return Ok(self.next_source());
+        }
+
+        let value = v8::String::new(scope, "value").unwrap();
+        if let Some(value) = result.get(scope, value.into()) {
+            if !value.is_null_or_undefined() {
+                self.push_chunk(scope, value)?;
+            }
+        }
+        Ok(Source::Reader(reader))
+    }
+
+    /// Calls `value[name]()` if `value` is an object with such a method.
+    fn call_method<'s>(
+        scope: &mut v8::TryCatch<v8::HandleScope<'s>>,
+        value: Local<'s, Value>,
+        name: &str,
+    ) -> Result<Option<Local<'s, Value>>, SsrError> {
+        if !value.is_object() {
+            return Ok(None);
+        }
+        let object = value.to_object(scope).unwrap();
+        let key = v8::String::new(scope, name).unwrap();
+        let Some(method) = object
+            .get(scope, key.into())
+            .and_then(|method| Local::<Function>::try_from(method).ok())
+        else {
+            return Ok(None);
+        };
+
+        method.call(scope, value, &[]).map(Some).ok_or_else(|| {
+            SsrError::Evaluation(JsError::from_try_catch(
+                scope,
+                &format!("Failed to call {name}()"),
+            ))
+        })
+    }
+
+    fn next_source(&self) -> Source {
+        if self.functions.is_empty() {
+            Source::Done
+        } else {
+            Source::Idle
+        }
+    }
+
+    /// Moves the chunks given to `write` to the stream, returning whether there were any.
+    fn drain_written(&mut self, scope: &mut v8::HandleScope) -> Result<bool, SsrError> {
+        let written = Local::new(scope, &self.written);
+        let length = written.length();
+
+        for index in 0..length {
+            if let Some(chunk) = written.get_index(scope, index) {
+                self.push_chunk(scope, chunk)?;
+            }
+        }
+
+        if length > 0 {
+            let key = v8::String::new(scope, "length").unwrap();
+            let zero = v8::Integer::new(scope, 0);
+            written.set(scope, key.into(), zero.into());
+        }
+        Ok(length > 0)
+    }
+
+    fn push_chunk(
+        &mut self,
+        scope: &mut v8::HandleScope,
+        chunk: Local<Value>,
+    ) -> Result<(), SsrError> {
+        let bytes = match Local::<v8::ArrayBufferView>::try_from(chunk) {
+            Ok(view) => {
+                let mut bytes = vec![0; view.byte_length()];
+                view.copy_contents(&mut bytes);
+                bytes
+            }
+            Err(_) => chunk
+                .to_string(scope)
+                .ok_or(SsrError::ResultNotStringifiable)?
+                .to_rust_string_lossy(scope)
+                .into_bytes(),
+        };
+
+        if !bytes.is_empty() {
+            self.chunks.push_back(bytes);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RenderStream<'_> {
+    fn drop(&mut self) {
+        // The timers left behind by the render functions are not needed anymore.
+        self.ssr.event_loop().clear();
+    }
+}
+
+impl Iterator for RenderStream<'_> {
+    type Item = Result<Vec<u8>, SsrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(chunk) = self.chunks.pop_front() {
+                return Some(Ok(chunk));
+            }
+            if matches!(self.source, Source::Done) {
+                return None;
+            }
+            if let Err(err) = self.pump() {
+                self.source = Source::Done;
+                self.functions.clear();
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+/// The sending half of a [`ChunkStream`].
+pub(crate) type ChunkSender = mpsc::Sender<Result<Bytes, SsrError>>;
+
+/// The chunks of a render run by an [`SsrPool`](crate::SsrPool) worker, returned by
+/// [`SsrPool::render_to_stream`](crate::SsrPool::render_to_stream).
+///
+/// The worker drives a [`RenderStream`] and sends its chunks through a bounded channel: it
+/// waits while the consumer is behind, and stops the render once this stream is dropped.
+/// Unlike [`RenderStream`], it is `Send` and polled without blocking, e.g. as a response body.
+pub struct ChunkStream {
+    chunks: mpsc::Receiver<Result<Bytes, SsrError>>,
+}
+
+impl ChunkStream {
+    /// The number of chunks the worker may produce ahead of the consumer.
+    const CAPACITY: usize = 16;
+
+    pub(crate) fn channel() -> (ChunkSender, Self) {
+        let (sender, chunks) = mpsc::channel(Self::CAPACITY);
+        (sender, ChunkStream { chunks })
+    }
+}
+
+impl Stream for ChunkStream {
+    type Item = Result<Bytes, SsrError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.chunks.poll_recv(cx)
+    }
+}
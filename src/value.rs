@@ -0,0 +1,139 @@
+use crate::error::SsrError;
+use serde_json::{Map, Number, Value};
+
+/// Nesting level after which a JS value is considered cyclic.
+const MAX_DEPTH: usize = 128;
+
+/// Converts a JSON value to the equivalent JS value without going through `JSON.parse`.
+pub(crate) fn to_v8<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    value: &Value,
+) -> Result<v8::Local<'s, v8::Value>, SsrError> {
+    Ok(match value {
+        Value::Null => v8::null(scope).into(),
+        Value::Bool(value) => v8::Boolean::new(scope, *value).into(),
+        Value::Number(number) => match number.as_i64() {
+            Some(value) if i32::try_from(value).is_ok() => {
+                v8::Integer::new(scope, value as i32).into()
+            }
+            _ => v8::Number::new(scope, number.as_f64().unwrap_or(f64::NAN)).into(),
+        },
+        Value::String(value) => new_string(scope, value)?.into(),
+        Value::Array(values) => {
+            let array = v8::Array::new(scope, values.len() as i32);
+            for (index, value) in values.iter().enumerate() {
+                let value = to_v8(scope, value)?;
+                array.set_index(scope, index as u32, value);
+            }
+            array.into()
+        }
+        Value::Object(entries) => {
+            let object = v8::Object::new(scope);
+            for (key, value) in entries {
+                let key = new_string(scope, key)?;
+                let value = to_v8(scope, value)?;
+                object.set(scope, key.into(), value);
+            }
+            object.into()
+        }
+    })
+}
+
+/// Converts a JS value to JSON following the `JSON.stringify` semantics: `toJSON` is honored,
+/// functions, symbols and `undefined` are skipped in objects and become `null` in arrays.
+pub(crate) fn from_v8(
+    scope: &mut v8::HandleScope,
+    value: v8::Local<v8::Value>,
+) -> Result<Value, SsrError> {
+    from_v8_at_depth(scope, value, 0)
+}
+
+fn from_v8_at_depth(
+    scope: &mut v8::HandleScope,
+    value: v8::Local<v8::Value>,
+    depth: usize,
+) -> Result<Value, SsrError> {
+    if depth > MAX_DEPTH {
+        return Err(SsrError::Serialization(
+            "Maximum depth exceeded, the value is probably cyclic".to_string(),
+        ));
+    }
+
+    if is_skipped(value) || value.is_null() {
+        return Ok(Value::Null);
+    }
+    if value.is_boolean() {
+        return Ok(Value::Bool(value.boolean_value(scope)));
+    }
+    if value.is_number() {
+        return Ok(number_to_json(
+            value.number_value(scope).unwrap_or(f64::NAN),
+        ));
+    }
+    if value.is_string() || value.is_big_int() {
+        return Ok(Value::String(value.to_rust_string_lossy(scope)));
+    }
+
+    let object = value
+        .to_object(scope)
+        .ok_or_else(|| SsrError::Serialization("Unsupported JS value".to_string()))?;
+
+    let to_json_key = v8::String::new(scope, "toJSON").unwrap();
+    if let Some(to_json) = object
+        .get(scope, to_json_key.into())
+        .and_then(|to_json| v8::Local::<v8::Function>::try_from(to_json).ok())
+    {
+        let json = to_json
+            .call(scope, object.into(), &[])
+            .ok_or_else(|| SsrError::Serialization("toJSON threw an exception".to_string()))?;
+        return from_v8_at_depth(scope, json, depth + 1);
+    }
+
+    if let Ok(array) = v8::Local::<v8::Array>::try_from(value) {
+        let mut values = Vec::with_capacity(array.length() as usize);
+        for index in 0..array.length() {
+            let value = array
+                .get_index(scope, index)
+                .unwrap_or_else(|| v8::undefined(scope).into());
+            values.push(from_v8_at_depth(scope, value, depth + 1)?);
+        }
+        return Ok(Value::Array(values));
+    }
+
+    let mut entries = Map::new();
+    let keys = object
+        .get_own_property_names(scope, v8::GetPropertyNamesArgs::default())
+        .ok_or_else(|| SsrError::Serialization("Failed to list object keys".to_string()))?;
+    for index in 0..keys.length() {
+        let key = keys.get_index(scope, index).unwrap();
+        let Some(value) = object.get(scope, key) else {
+            continue;
+        };
+        if is_skipped(value) {
+            continue;
+        }
+        let key = key.to_rust_string_lossy(scope);
+        entries.insert(key, from_v8_at_depth(scope, value, depth + 1)?);
+    }
+    Ok(Value::Object(entries))
+}
+
+fn is_skipped(value: v8::Local<v8::Value>) -> bool {
+    value.is_undefined() || value.is_function() || value.is_symbol()
+}
+
+fn number_to_json(number: f64) -> Value {
+    if number.fract() == 0.0 && number.abs() < i64::MAX as f64 {
+        Value::Number(Number::from(number as i64))
+    } else {
+        Number::from_f64(number).map_or(Value::Null, Value::Number)
+    }
+}
+
+fn new_string<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    value: &str,
+) -> Result<v8::Local<'s, v8::String>, SsrError> {
+    v8::String::new(scope, value)
+        .ok_or_else(|| SsrError::Serialization("String too long for V8".to_string()))
+}
@@ -0,0 +1,115 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Instant;
+
+/// Terminates the JS execution of an isolate once an armed deadline expires.
+///
+/// A single thread is spawned per isolate and sleeps until the watchdog is armed, so
+/// guarding a call costs a mutex lock instead of a thread spawn.
+pub(crate) struct Watchdog {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    condvar: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    deadline: Option<Instant>,
+    fired: bool,
+    shutdown: bool,
+}
+
+impl Watchdog {
+    pub(crate) fn new(handle: v8::IsolateHandle) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            condvar: Condvar::new(),
+        });
+
+        let thread_shared = shared.clone();
+        thread::Builder::new()
+            .name("ssr-watchdog".to_string())
+            .spawn(move || Self::run(&thread_shared, &handle))
+            .expect("Failed to spawn the ssr watchdog thread");
+
+        Watchdog { shared }
+    }
+
+    /// Starts watching: the isolate execution is terminated if the guard is still alive
+    /// at `deadline`.
+    pub(crate) fn arm(&self, deadline: Instant) -> WatchdogGuard<'_> {
+        let mut state = self.shared.state.lock().unwrap();
+        state.deadline = Some(deadline);
+        state.fired = false;
+        self.shared.condvar.notify_one();
+
+        WatchdogGuard {
+            watchdog: self,
+            disarmed: false,
+        }
+    }
+
+    fn disarm(&self) -> bool {
+        let mut state = self.shared.state.lock().unwrap();
+        state.deadline = None;
+        std::mem::take(&mut state.fired)
+    }
+
+    fn run(shared: &Shared, handle: &v8::IsolateHandle) {
+        let mut state = shared.state.lock().unwrap();
+        while !state.shutdown {
+            match state.deadline {
+                None => state = shared.condvar.wait(state).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        // Terminating under the lock guarantees a disarmed guard never
+                        // observes a late termination.
+                        handle.terminate_execution();
+                        state.fired = true;
+                        state.deadline = None;
+                    } else {
+                        state = shared
+                            .condvar
+                            .wait_timeout(state, deadline - now)
+                            .unwrap()
+                            .0;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().shutdown = true;
+        self.shared.condvar.notify_one();
+    }
+}
+
+/// Keeps the [`Watchdog`] armed until dropped or disarmed.
+pub(crate) struct WatchdogGuard<'a> {
+    watchdog: &'a Watchdog,
+    disarmed: bool,
+}
+
+impl WatchdogGuard<'_> {
+    /// Stops watching and returns whether the deadline expired (and the execution was
+    /// terminated) in the meantime.
+    pub(crate) fn disarm(mut self) -> bool {
+        self.disarmed = true;
+        self.watchdog.disarm()
+    }
+}
+
+impl Drop for WatchdogGuard<'_> {
+    fn drop(&mut self) {
+        if !self.disarmed {
+            self.watchdog.disarm();
+        }
+    }
+}
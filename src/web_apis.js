@@ -0,0 +1,291 @@
+// Defines the Web APIs on the global object, on top of the native `ops` of `web_apis.rs`.
+(function (ops) {
+  "use strict";
+
+  function define(name, value) {
+    Object.defineProperty(globalThis, name, {
+      value,
+      writable: true,
+      configurable: true,
+      enumerable: false,
+    });
+  }
+
+  function toBytes(input) {
+    if (input === undefined) return new Uint8Array(0);
+    if (input instanceof ArrayBuffer) return new Uint8Array(input);
+    if (ArrayBuffer.isView(input)) {
+      return new Uint8Array(input.buffer, input.byteOffset, input.byteLength);
+    }
+    throw new TypeError("The input must be an ArrayBuffer or an ArrayBufferView");
+  }
+
+  class TextEncoder {
+    get encoding() {
+      return "utf-8";
+    }
+
+    encode(input = "") {
+      return ops.encode(String(input));
+    }
+
+    encodeInto(source, destination) {
+      const [read, bytes] = ops.encodeInto(String(source), destination.length);
+      destination.set(bytes);
+      return { read, written: bytes.length };
+    }
+  }
+
+  const UTF8_LABELS = ["utf-8", "utf8", "unicode-1-1-utf-8"];
+  const decoders = new WeakMap();
+
+  class TextDecoder {
+    constructor(label = "utf-8", options = {}) {
+      if (!UTF8_LABELS.includes(String(label).trim().toLowerCase())) {
+        throw new RangeError(`The encoding "${label}" is not supported`);
+      }
+      decoders.set(this, {
+        fatal: Boolean(options.fatal),
+        ignoreBOM: Boolean(options.ignoreBOM),
+        pending: new Uint8Array(0),
+        started: false,
+      });
+    }
+
+    get encoding() {
+      return "utf-8";
+    }
+
+    get fatal() {
+      return decoders.get(this).fatal;
+    }
+
+    get ignoreBOM() {
+      return decoders.get(this).ignoreBOM;
+    }
+
+    decode(input, options = {}) {
+      const decoder = decoders.get(this);
+      let bytes = toBytes(input);
+      if (decoder.pending.length > 0) {
+        const joined = new Uint8Array(decoder.pending.length + bytes.length);
+        joined.set(decoder.pending);
+        joined.set(bytes, decoder.pending.length);
+        bytes = joined;
+      }
+
+      const stream = Boolean(options.stream);
+      let [text, pending] = ops.decode(bytes, decoder.fatal, stream);
+      decoder.pending = bytes.slice(bytes.length - pending);
+      if (!decoder.started && !decoder.ignoreBOM && text.charCodeAt(0) === 0xfeff) {
+        text = text.slice(1);
+      }
+      decoder.started = stream && (decoder.started || text.length > 0);
+      return text;
+    }
+  }
+
+  // The order of the components returned by `ops.parseUrl` and `ops.setUrl`.
+  const COMPONENTS = [
+    "href",
+    "origin",
+    "protocol",
+    "username",
+    "password",
+    "host",
+    "hostname",
+    "port",
+    "pathname",
+    "search",
+    "hash",
+  ];
+  const SEARCH = COMPONENTS.indexOf("search");
+  const urls = new WeakMap();
+  const params = new WeakMap();
+
+  function parseUrl(url, base) {
+    return base === undefined ? ops.parseUrl(String(url)) : ops.parseUrl(String(url), String(base));
+  }
+
+  class URL {
+    constructor(url, base) {
+      const components = parseUrl(url, base);
+      if (components === null) throw new TypeError(`Invalid URL: ${url}`);
+      urls.set(this, { components, searchParams: null });
+    }
+
+    static canParse(url, base) {
+      return parseUrl(url, base) !== null;
+    }
+
+    get searchParams() {
+      const state = urls.get(this);
+      if (!state.searchParams) {
+        state.searchParams = new URLSearchParams(state.components[SEARCH]);
+        params.get(state.searchParams).url = this;
+      }
+      return state.searchParams;
+    }
+
+    toString() {
+      return this.href;
+    }
+
+    toJSON() {
+      return this.href;
+    }
+  }
+
+  function updateUrl(url, components) {
+    const state = urls.get(url);
+    state.components = components;
+    if (state.searchParams) {
+      params.get(state.searchParams).list = ops.parseSearchParams(components[SEARCH].slice(1));
+    }
+  }
+
+  COMPONENTS.forEach((name, index) => {
+    const set =
+      name === "origin"
+        ? undefined
+        : function (value) {
+            const components = ops.setUrl(urls.get(this).components[0], name, String(value));
+            if (components === null) throw new TypeError(`Invalid URL: ${value}`);
+            updateUrl(this, components);
+          };
+    Object.defineProperty(URL.prototype, name, {
+      get() {
+        return urls.get(this).components[index];
+      },
+      set,
+      enumerable: true,
+      configurable: true,
+    });
+  });
+
+  // Writes the serialized `searchParams` back to the URL they were created by, if any.
+  function update(searchParams) {
+    const state = params.get(searchParams);
+    if (state.url) {
+      urls.get(state.url).components = ops.setUrl(
+        urls.get(state.url).components[0],
+        "search",
+        searchParams.toString(),
+      );
+    }
+  }
+
+  class URLSearchParams {
+    constructor(init = "") {
+      let list = [];
+      if (typeof init === "string") {
+        list = ops.parseSearchParams(init.startsWith("?") ? init.slice(1) : init);
+      } else if (init instanceof URLSearchParams) {
+        list = params.get(init).list.map(([name, value]) => [name, value]);
+      } else if (init && typeof init[Symbol.iterator] === "function") {
+        for (const pair of init) {
+          const [name, value, ...rest] = pair;
+          if (rest.length > 0 || pair.length !== 2) {
+            throw new TypeError("Each pair must be an iterable of two values");
+          }
+          list.push([String(name), String(value)]);
+        }
+      } else if (init && typeof init === "object") {
+        list = Object.keys(init).map((name) => [name, String(init[name])]);
+      } else {
+        list = ops.parseSearchParams(String(init));
+      }
+      params.set(this, { list, url: null });
+    }
+
+    get size() {
+      return params.get(this).list.length;
+    }
+
+    append(name, value) {
+      params.get(this).list.push([String(name), String(value)]);
+      update(this);
+    }
+
+    delete(name, value) {
+      const state = params.get(this);
+      state.list = state.list.filter(
+        ([key, entry]) => key !== String(name) || (value !== undefined && entry !== String(value)),
+      );
+      update(this);
+    }
+
+    get(name) {
+      const pair = params.get(this).list.find(([key]) => key === String(name));
+      return pair ? pair[1] : null;
+    }
+
+    getAll(name) {
+      return params
+        .get(this)
+        .list.filter(([key]) => key === String(name))
+        .map(([, value]) => value);
+    }
+
+    has(name, value) {
+      return params
+        .get(this)
+        .list.some(([key, entry]) => key === String(name) && (value === undefined || entry === String(value)));
+    }
+
+    set(name, value) {
+      const state = params.get(this);
+      const index = state.list.findIndex(([key]) => key === String(name));
+      if (index === -1) {
+        state.list.push([String(name), String(value)]);
+      } else {
+        state.list[index][1] = String(value);
+        state.list = state.list.filter(([key], other) => other <= index || key !== String(name));
+      }
+      update(this);
+    }
+
+    sort() {
+      // `Array.prototype.sort` is stable, and compares UTF-16 code units as required.
+      params.get(this).list.sort(([a], [b]) => (a < b ? -1 : a > b ? 1 : 0));
+      update(this);
+    }
+
+    forEach(callback, thisArg) {
+      for (const [name, value] of params.get(this).list) {
+        callback.call(thisArg, value, name, this);
+      }
+    }
+
+    *keys() {
+      for (const [name] of params.get(this).list) yield name;
+    }
+
+    *values() {
+      for (const [, value] of params.get(this).list) yield value;
+    }
+
+    *entries() {
+      for (const [name, value] of params.get(this).list) yield [name, value];
+    }
+
+    [Symbol.iterator]() {
+      return this.entries();
+    }
+
+    toString() {
+      return ops.serializeSearchParams(params.get(this).list.flat());
+    }
+  }
+
+  define("TextEncoder", TextEncoder);
+  define("TextDecoder", TextDecoder);
+  define("URL", URL);
+  define("URLSearchParams", URLSearchParams);
+  define("atob", function atob(data) {
+    return ops.atob(String(data));
+  });
+  define("btoa", function btoa(data) {
+    return ops.btoa(String(data));
+  });
+});
@@ -0,0 +1,466 @@
+//! The Web APIs bundles expect to find on the global object: `TextEncoder`, `TextDecoder`,
+//! `URL`, `URLSearchParams`, `atob` and `btoa`.
+//!
+//! The encoding, URL parsing and base64 work is done here, by the native `ops` handed to
+//! `web_apis.js`, which defines the classes around them.
+
+use crate::error::{JsError, SsrError};
+use crate::ssr::Ssr;
+use base64::alphabet;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+use url::{form_urlencoded, quirks, Url};
+use v8::MapFnTo;
+
+/// The source of the function defining the globals, called with the native ops.
+const SOURCE: &str = include_str!("web_apis.js");
+
+/// The resource name of [`SOURCE`], as it appears in JS stack traces.
+const FILE_NAME: &str = "ssr_rs:web_apis.js";
+
+/// Decodes base64 as `atob` does, once the padding has been stripped by [`forgiving_base64`].
+const FORGIVING_BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new()
+        .with_decode_padding_mode(DecodePaddingMode::RequireNone)
+        .with_decode_allow_trailing_bits(true),
+);
+
+/// Defines the Web API globals on the global object of the current context.
+pub(crate) fn install(scope: &mut v8::ContextScope<'_, v8::HandleScope>) -> Result<(), SsrError> {
+    let ops = v8::Object::new(scope);
+    set_op(scope, ops, "encode", encode_callback)?;
+    set_op(scope, ops, "encodeInto", encode_into_callback)?;
+    set_op(scope, ops, "decode", decode_callback)?;
+    set_op(scope, ops, "parseUrl", parse_url_callback)?;
+    set_op(scope, ops, "setUrl", set_url_callback)?;
+    set_op(
+        scope,
+        ops,
+        "parseSearchParams",
+        parse_search_params_callback,
+    )?;
+    set_op(
+        scope,
+        ops,
+        "serializeSearchParams",
+        serialize_search_params_callback,
+    )?;
+    set_op(scope, ops, "atob", atob_callback)?;
+    set_op(scope, ops, "btoa", btoa_callback)?;
+
+    let scope = &mut v8::TryCatch::new(scope);
+    let source = v8::String::new(scope, SOURCE).unwrap();
+    let origin = Ssr::script_origin(scope, FILE_NAME, false);
+    let define = v8::Script::compile(scope, source, Some(&origin))
+        .and_then(|script| script.run(scope))
+        .and_then(|define| v8::Local::<v8::Function>::try_from(define).ok())
+        .ok_or_else(|| {
+            SsrError::Evaluation(JsError::from_try_catch(
+                scope,
+                "Failed to define the web APIs",
+            ))
+        })?;
+
+    let undefined = v8::undefined(scope).into();
+    define
+        .call(scope, undefined, &[ops.into()])
+        .ok_or_else(|| {
+            SsrError::Evaluation(JsError::from_try_catch(
+                scope,
+                "Failed to define the web APIs",
+            ))
+        })?;
+    Ok(())
+}
+
+/// Returns the native callbacks of the ops, see [`crate::snapshot`].
+pub(crate) fn callbacks() -> Vec<v8::FunctionCallback> {
+    vec![
+        encode_callback.map_fn_to(),
+        encode_into_callback.map_fn_to(),
+        decode_callback.map_fn_to(),
+        parse_url_callback.map_fn_to(),
+        set_url_callback.map_fn_to(),
+        parse_search_params_callback.map_fn_to(),
+        serialize_search_params_callback.map_fn_to(),
+        atob_callback.map_fn_to(),
+        btoa_callback.map_fn_to(),
+    ]
+}
+
+fn set_op(
+    scope: &mut v8::HandleScope,
+    ops: v8::Local<v8::Object>,
+    name: &str,
+    callback: impl v8::MapFnTo<v8::FunctionCallback>,
+) -> Result<(), SsrError> {
+    let function = v8::Function::new(scope, callback).ok_or_else(|| {
+        SsrError::Evaluation(JsError::new(format!("Failed to create the {name} op")))
+    })?;
+    let key = v8::String::new(scope, name).unwrap();
+    ops.set(scope, key.into(), function.into());
+    Ok(())
+}
+
+/// Implements `encode(input)`, returning the UTF-8 bytes of `input` in a `Uint8Array`.
+///
+/// Lone surrogates are replaced with U+FFFD, as `TextEncoder` requires.
+fn encode_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let input = args.get(0).to_rust_string_lossy(scope);
+    rv.set(uint8_array(scope, input.into_bytes()).into());
+}
+
+/// Implements `encodeInto(input, capacity)`, returning `[read, bytes]`: the UTF-8 bytes of the
+/// longest prefix of `input` fitting in `capacity` bytes, and its length in UTF-16 code units.
+fn encode_into_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let input = args.get(0).to_rust_string_lossy(scope);
+    let capacity = args.get(1).uint32_value(scope).unwrap_or_default() as usize;
+    let (read, written) = encode_prefix(&input, capacity);
+
+    let read = v8::Integer::new_from_unsigned(scope, read as u32);
+    let bytes = uint8_array(scope, input.as_bytes()[..written].to_vec());
+    rv.set(v8::Array::new_with_elements(scope, &[read.into(), bytes.into()]).into());
+}
+
+/// Implements `decode(bytes, fatal, stream)`, returning `[text, pending]`: the text decoded from
+/// the UTF-8 `bytes`, and the number of trailing bytes left out because they start a sequence
+/// that the next chunk of the stream may complete.
+fn decode_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let Ok(view) = v8::Local::<v8::ArrayBufferView>::try_from(args.get(0)) else {
+        throw_type_error(scope, "The input must be an ArrayBufferView");
+        return;
+    };
+    let fatal = args.get(1).boolean_value(scope);
+    let stream = args.get(2).boolean_value(scope);
+
+    let mut bytes = vec![0; view.byte_length()];
+    view.copy_contents(&mut bytes);
+    let pending = if stream { incomplete_suffix(&bytes) } else { 0 };
+    let bytes = &bytes[..bytes.len() - pending];
+
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) if fatal => {
+            throw_type_error(scope, "The encoded data is not valid UTF-8");
+            return;
+        }
+        Err(_) => String::from_utf8_lossy(bytes).into_owned(),
+    };
+
+    let text = v8::String::new(scope, &text).unwrap();
+    let pending = v8::Integer::new_from_unsigned(scope, pending as u32);
+    rv.set(v8::Array::new_with_elements(scope, &[text.into(), pending.into()]).into());
+}
+
+/// Implements `parseUrl(input, base)`, returning the components of the URL (see
+/// [`url_components`]), or `null` if it is not valid.
+fn parse_url_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let input = args.get(0).to_rust_string_lossy(scope);
+    let base = args.get(1);
+    let url = if base.is_undefined() {
+        Url::parse(&input)
+    } else {
+        let base = base.to_rust_string_lossy(scope);
+        Url::parse(&base).and_then(|base| base.join(&input))
+    };
+
+    match url {
+        Ok(url) => rv.set(url_components(scope, &url).into()),
+        Err(_) => rv.set_null(),
+    }
+}
+
+/// Implements `setUrl(href, name, value)`, returning the components of the URL `href` once its
+/// `name` component has been set to `value`, or `null` if `href` is set to an invalid URL.
+///
+/// An invalid value for the other components is ignored, as the `URL` setters do.
+fn set_url_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let href = args.get(0).to_rust_string_lossy(scope);
+    let name = args.get(1).to_rust_string_lossy(scope);
+    let value = args.get(2).to_rust_string_lossy(scope);
+    // Setting `href` parses a new URL, which must be valid.
+    let url = if name == "href" {
+        Url::parse(&value)
+    } else {
+        Url::parse(&href)
+    };
+    let Ok(mut url) = url else {
+        rv.set_null();
+        return;
+    };
+
+    let _ = match name.as_str() {
+        "protocol" => quirks::set_protocol(&mut url, &value),
+        "username" => quirks::set_username(&mut url, &value),
+        "password" => quirks::set_password(&mut url, &value),
+        "host" => quirks::set_host(&mut url, &value),
+        "hostname" => quirks::set_hostname(&mut url, &value),
+        "port" => quirks::set_port(&mut url, &value),
+        "pathname" => {
+            quirks::set_pathname(&mut url, &value);
+            Ok(())
+        }
+        "search" => {
+            quirks::set_search(&mut url, &value);
+            Ok(())
+        }
+        "hash" => {
+            quirks::set_hash(&mut url, &value);
+            Ok(())
+        }
+        _ => Ok(()),
+    };
+    rv.set(url_components(scope, &url).into());
+}
+
+/// Implements `parseSearchParams(query)`, returning the `[name, value]` pairs of the
+/// `application/x-www-form-urlencoded` `query`.
+fn parse_search_params_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let query = args.get(0).to_rust_string_lossy(scope);
+    let pairs: Vec<_> = form_urlencoded::parse(query.as_bytes())
+        .map(|(name, value)| {
+            let name = v8::String::new(scope, &name).unwrap();
+            let value = v8::String::new(scope, &value).unwrap();
+            v8::Array::new_with_elements(scope, &[name.into(), value.into()]).into()
+        })
+        .collect();
+    rv.set(v8::Array::new_with_elements(scope, &pairs).into());
+}
+
+/// Implements `serializeSearchParams(entries)`, `entries` alternating names and values.
+fn serialize_search_params_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let Ok(entries) = v8::Local::<v8::Array>::try_from(args.get(0)) else {
+        throw_type_error(scope, "The entries must be an array");
+        return;
+    };
+
+    let entries: Vec<_> = (0..entries.length())
+        .map(|index| {
+            entries
+                .get_index(scope, index)
+                .map(|entry| entry.to_rust_string_lossy(scope))
+                .unwrap_or_default()
+        })
+        .collect();
+    let mut serializer = form_urlencoded::Serializer::new(String::new());
+    for pair in entries.chunks(2) {
+        serializer.append_pair(&pair[0], pair.get(1).map_or("", String::as_str));
+    }
+
+    let query = v8::String::new(scope, &serializer.finish()).unwrap();
+    rv.set(query.into());
+}
+
+/// Implements `atob(data)`, returning the decoded bytes as a string of Latin-1 characters.
+fn atob_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let data = args.get(0).to_rust_string_lossy(scope);
+    match forgiving_base64(&data) {
+        Some(bytes) => {
+            let text = v8::String::new_from_one_byte(scope, &bytes, v8::NewStringType::Normal);
+            rv.set(text.unwrap().into());
+        }
+        None => throw_invalid_character(scope, "The string to be decoded is not correctly encoded"),
+    }
+}
+
+/// Implements `btoa(data)`, encoding the Latin-1 characters of `data`.
+fn btoa_callback(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let Some(data) = args.get(0).to_string(scope) else {
+        return;
+    };
+    let mut units = vec![0; data.length()];
+    data.write(scope, &mut units, 0, v8::WriteOptions::NO_NULL_TERMINATION);
+
+    let Ok(bytes) = units
+        .into_iter()
+        .map(u8::try_from)
+        .collect::<Result<Vec<_>, _>>()
+    else {
+        throw_invalid_character(
+            scope,
+            "The string to be encoded contains characters outside of the Latin1 range",
+        );
+        return;
+    };
+
+    let encoded = base64::engine::general_purpose::STANDARD.encode(bytes);
+    rv.set(v8::String::new(scope, &encoded).unwrap().into());
+}
+
+/// Returns the components of `url`, in the order of `COMPONENTS` in `web_apis.js`.
+fn url_components<'s>(scope: &mut v8::HandleScope<'s>, url: &Url) -> v8::Local<'s, v8::Array> {
+    let components = [
+        quirks::href(url).to_string(),
+        quirks::origin(url),
+        quirks::protocol(url).to_string(),
+        quirks::username(url).to_string(),
+        quirks::password(url).to_string(),
+        quirks::host(url).to_string(),
+        quirks::hostname(url).to_string(),
+        quirks::port(url).to_string(),
+        quirks::pathname(url).to_string(),
+        quirks::search(url).to_string(),
+        quirks::hash(url).to_string(),
+    ];
+    let components: Vec<_> = components
+        .iter()
+        .map(|component| v8::String::new(scope, component).unwrap().into())
+        .collect();
+    v8::Array::new_with_elements(scope, &components)
+}
+
+fn uint8_array<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    bytes: Vec<u8>,
+) -> v8::Local<'s, v8::Uint8Array> {
+    let length = bytes.len();
+    let store = v8::ArrayBuffer::new_backing_store_from_vec(bytes).make_shared();
+    let buffer = v8::ArrayBuffer::with_backing_store(scope, &store);
+    v8::Uint8Array::new(scope, buffer, 0, length).unwrap()
+}
+
+/// Returns the length in UTF-16 code units of the longest prefix of `input` whose UTF-8
+/// encoding fits in `capacity` bytes, along with the length of that encoding.
+fn encode_prefix(input: &str, capacity: usize) -> (usize, usize) {
+    let mut read = 0;
+    let mut written = 0;
+    for c in input.chars() {
+        if written + c.len_utf8() > capacity {
+            break;
+        }
+        read += c.len_utf16();
+        written += c.len_utf8();
+    }
+    (read, written)
+}
+
+/// Returns the number of bytes at the end of `bytes` that start a UTF-8 sequence without
+/// completing it.
+fn incomplete_suffix(bytes: &[u8]) -> usize {
+    for len in 1..=bytes.len().min(3) {
+        let byte = bytes[bytes.len() - len];
+        if byte & 0b1100_0000 == 0b1000_0000 {
+            // A continuation byte: the sequence starts further back.
+            continue;
+        }
+        let expected = match byte {
+            0b1100_0000..=0b1101_1111 => 2,
+            0b1110_0000..=0b1110_1111 => 3,
+            0b1111_0000..=0b1111_0111 => 4,
+            _ => return 0,
+        };
+        return if expected > len { len } else { 0 };
+    }
+    0
+}
+
+/// Decodes `data` with the "forgiving-base64 decode" algorithm of the HTML standard: ASCII
+/// whitespace is ignored and the padding is optional. Returns `None` if `data` is not valid.
+#[allow(clippy::manual_is_multiple_of)] // `is_multiple_of` needs Rust 1.87
+fn forgiving_base64(data: &str) -> Option<Vec<u8>> {
+    let mut data: String = data
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' '))
+        .collect();
+    if data.len() % 4 == 0 {
+        for _ in 0..2 {
+            if data.ends_with('=') {
+                data.pop();
+            }
+        }
+    }
+    if data.len() % 4 == 1 {
+        return None;
+    }
+    FORGIVING_BASE64.decode(data).ok()
+}
+
+fn throw_type_error(scope: &mut v8::HandleScope, msg: &str) {
+    let msg = v8::String::new(scope, msg).unwrap();
+    let error = v8::Exception::type_error(scope, msg);
+    scope.throw_exception(error);
+}
+
+/// Throws an error named `InvalidCharacterError`, like the `DOMException` thrown by browsers.
+fn throw_invalid_character(scope: &mut v8::HandleScope, msg: &str) {
+    let msg = v8::String::new(scope, msg).unwrap();
+    let error = v8::Exception::error(scope, msg);
+    if let Some(object) = error.to_object(scope) {
+        let key = v8::String::new(scope, "name").unwrap();
+        let name = v8::String::new(scope, "InvalidCharacterError").unwrap();
+        object.set(scope, key.into(), name.into());
+    }
+    scope.throw_exception(error);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_prefix() {
+        assert_eq!(encode_prefix("abc", 10), (3, 3));
+        assert_eq!(encode_prefix("aé", 2), (1, 1));
+        assert_eq!(encode_prefix("aé", 3), (2, 3));
+        // A character outside of the BMP is two UTF-16 code units.
+        assert_eq!(encode_prefix("😀!", 4), (2, 4));
+    }
+
+    #[test]
+    fn test_incomplete_suffix() {
+        assert_eq!(incomplete_suffix(b"abc"), 0);
+        assert_eq!(incomplete_suffix("é".as_bytes()), 0);
+        assert_eq!(incomplete_suffix(&"é".as_bytes()[..1]), 1);
+        assert_eq!(incomplete_suffix(&"😀".as_bytes()[..3]), 3);
+        assert_eq!(incomplete_suffix(&[b'a', 0xFF]), 0);
+    }
+
+    #[test]
+    fn test_forgiving_base64() {
+        assert_eq!(forgiving_base64("aGk=").as_deref(), Some(&b"hi"[..]));
+        assert_eq!(forgiving_base64("aGk").as_deref(), Some(&b"hi"[..]));
+        assert_eq!(forgiving_base64(" aG\nk= ").as_deref(), Some(&b"hi"[..]));
+        assert_eq!(forgiving_base64(""), Some(Vec::new()));
+        assert_eq!(forgiving_base64("a"), None);
+        assert_eq!(forgiving_base64("a===").as_deref(), None);
+        assert_eq!(forgiving_base64("aG!="), None);
+    }
+}
@@ -1,4 +1,4 @@
-use ssr_rs::Ssr;
+use ssr_rs::{ModuleType, Ssr};
 use std::fs::read_to_string;
 
 #[test]
@@ -6,7 +6,7 @@ fn renders_react_17_exported_as_iife() {
     let source = read_to_string("./tests/assets/react-17-iife.js").unwrap();
 
     let ssr = Ssr::new();
-    ssr.load(&source, "", "cjs").unwrap();
+    ssr.load(&source, "", ModuleType::Iife).unwrap();
 
     let html = ssr.render_to_string(None).unwrap();
 
@@ -18,7 +18,7 @@ fn renders_react_18_exported_as_iife() {
     let source = read_to_string("./tests/assets/react-18-iife.js").unwrap();
 
     let ssr = Ssr::new();
-    ssr.load(&source, "", "cjs").unwrap();
+    ssr.load(&source, "", ModuleType::Iife).unwrap();
 
     let html = ssr.render_to_string(None).unwrap();
 
@@ -1,4 +1,4 @@
-use ssr_rs::Ssr;
+use ssr_rs::{ModuleType, Ssr};
 use std::fs::read_to_string;
 
 #[test]
@@ -6,7 +6,7 @@ fn renders_svelte_exported_as_esm() {
     let source = read_to_string("./tests/assets/svelte-4-esm.js").unwrap();
 
     let ssr = Ssr::new();
-    ssr.load(&source, "render", "esm").unwrap();
+    ssr.load(&source, "render", ModuleType::Esm).unwrap();
 
     let html = ssr.render_to_string(None).unwrap();
 